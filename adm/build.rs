@@ -29,6 +29,7 @@ use protoc_rust::Customize;
 
 const PROTO_FILES_DIR: &str = "../protos";
 const SETTINGS_PROTO_FILES_DIR: &str = "../families/settings/protos";
+const IDENTITY_PROTO_FILES_DIR: &str = "../families/identity/protos";
 const PROTO_DIR_NAME: &str = "proto";
 const GENERATED_SOURCE_HEADER: &str = r#"
 /*
@@ -53,6 +54,10 @@ fn main() {
         "{}/*.proto",
         SETTINGS_PROTO_FILES_DIR
     )));
+    proto_src_files.append(&mut glob_simple(&format!(
+        "{}/*.proto",
+        IDENTITY_PROTO_FILES_DIR
+    )));
     let last_build_time = read_last_build_time();
 
     let latest_change =
@@ -80,7 +85,12 @@ fn main() {
                     .map(|proto_file| proto_file.file_path.as_ref())
                     .collect::<Vec<&str>>(),
             )
-            .includes(&["src", PROTO_FILES_DIR, SETTINGS_PROTO_FILES_DIR])
+            .includes(&[
+                "src",
+                PROTO_FILES_DIR,
+                SETTINGS_PROTO_FILES_DIR,
+                IDENTITY_PROTO_FILES_DIR,
+            ])
             .customize(Customize::default())
             .run()
             .expect("unable to run protoc");
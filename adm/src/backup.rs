@@ -0,0 +1,477 @@
+/*
+ * Copyright 2018 Intel Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * ------------------------------------------------------------------------------
+ */
+
+//! The blockstore backup container format.
+//!
+//! A backup file starts with an uncompressed header: the magic bytes `STBK`,
+//! the big-endian `u32` format version, and a length-prefixed JSON
+//! `BackupHeader`. The header is followed by the body, which is compressed as
+//! described in the header. The body is a sequence of block records, each a
+//! tag byte, the length of the packed `Block`, the packed `Block` and the
+//! CRC-32 of the packed bytes. The body ends with a trailer record holding the
+//! number of blocks written and a CRC-32 over all packed blocks.
+
+use std;
+use std::io::{self, Read, Write};
+
+use crc32fast::Hasher;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use protobuf;
+use protobuf::Message;
+use serde_json;
+use zstd;
+
+use proto::block::Block;
+
+pub const BACKUP_MAGIC: &[u8; 4] = b"STBK";
+pub const BACKUP_FORMAT_VERSION: u32 = 1;
+
+const BLOCK_RECORD: u8 = 1;
+const TRAILER_RECORD: u8 = 2;
+
+const ZSTD_LEVEL: i32 = 3;
+
+#[derive(Debug)]
+pub enum BackupError {
+    IoError(String),
+    FormatError(String),
+    ChecksumError(String),
+}
+
+impl std::fmt::Display for BackupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            BackupError::IoError(ref msg) => write!(f, "IoError: {}", msg),
+            BackupError::FormatError(ref msg) => write!(f, "FormatError: {}", msg),
+            BackupError::ChecksumError(ref msg) => write!(f, "ChecksumError: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for BackupError {
+    fn description(&self) -> &str {
+        match *self {
+            BackupError::IoError(ref msg) => msg,
+            BackupError::FormatError(ref msg) => msg,
+            BackupError::ChecksumError(ref msg) => msg,
+        }
+    }
+
+    fn cause(&self) -> Option<&dyn std::error::Error> {
+        match *self {
+            BackupError::IoError(_) => None,
+            BackupError::FormatError(_) => None,
+            BackupError::ChecksumError(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for BackupError {
+    fn from(err: io::Error) -> Self {
+        BackupError::IoError(format!("{}", err))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "none" => Some(Compression::None),
+            "gzip" => Some(Compression::Gzip),
+            "zstd" => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BackupHeader {
+    pub format_version: u32,
+    /// The id of the lowest block of the chain the blocks belong to: its
+    /// genesis block, or the block it was restored from a snapshot at
+    pub chain_id: String,
+    /// The id of the last block in the backup
+    pub head_block_id: String,
+    pub first_block_num: u64,
+    pub last_block_num: u64,
+    pub compression: Compression,
+}
+
+/// Compresses the body of a file, which follows its uncompressed header.
+pub enum BodyWriter<W: Write> {
+    Plain(W),
+    Gzip(GzEncoder<W>),
+    Zstd(zstd::stream::write::Encoder<W>),
+}
+
+impl<W: Write> BodyWriter<W> {
+    pub fn new(writer: W, compression: Compression) -> io::Result<Self> {
+        Ok(match compression {
+            Compression::None => BodyWriter::Plain(writer),
+            Compression::Gzip => {
+                BodyWriter::Gzip(GzEncoder::new(writer, flate2::Compression::default()))
+            }
+            Compression::Zstd => {
+                BodyWriter::Zstd(zstd::stream::write::Encoder::new(writer, ZSTD_LEVEL)?)
+            }
+        })
+    }
+
+    pub fn finish(self) -> io::Result<W> {
+        match self {
+            BodyWriter::Plain(writer) => Ok(writer),
+            BodyWriter::Gzip(encoder) => encoder.finish(),
+            BodyWriter::Zstd(encoder) => encoder.finish(),
+        }
+    }
+}
+
+impl<W: Write> Write for BodyWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match *self {
+            BodyWriter::Plain(ref mut writer) => writer.write(buf),
+            BodyWriter::Gzip(ref mut encoder) => encoder.write(buf),
+            BodyWriter::Zstd(ref mut encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match *self {
+            BodyWriter::Plain(ref mut writer) => writer.flush(),
+            BodyWriter::Gzip(ref mut encoder) => encoder.flush(),
+            BodyWriter::Zstd(ref mut encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// Writes blocks to a backup, in the order they are given.
+pub struct BackupWriter<W: Write> {
+    body: BodyWriter<W>,
+    block_count: u64,
+    hasher: Hasher,
+}
+
+impl<W: Write> BackupWriter<W> {
+    pub fn new(mut writer: W, header: &BackupHeader) -> Result<Self, BackupError> {
+        let header_bytes = serde_json::to_vec(header)
+            .map_err(|err| BackupError::FormatError(format!("Invalid header: {}", err)))?;
+
+        writer.write_all(BACKUP_MAGIC)?;
+        writer.write_all(&header.format_version.to_be_bytes())?;
+        writer.write_all(&(header_bytes.len() as u32).to_be_bytes())?;
+        writer.write_all(&header_bytes)?;
+
+        Ok(BackupWriter {
+            body: BodyWriter::new(writer, header.compression)?,
+            block_count: 0,
+            hasher: Hasher::new(),
+        })
+    }
+
+    pub fn write_block(&mut self, block: &Block) -> Result<(), BackupError> {
+        let packed = block.write_to_bytes().map_err(|err| {
+            BackupError::FormatError(format!(
+                "Failed to serialize block {}: {}",
+                block.header_signature, err
+            ))
+        })?;
+
+        self.body.write_all(&[BLOCK_RECORD])?;
+        self.body.write_all(&(packed.len() as u32).to_be_bytes())?;
+        self.body.write_all(&packed)?;
+        self.body.write_all(&crc32(&packed).to_be_bytes())?;
+
+        self.hasher.update(&packed);
+        self.block_count += 1;
+        Ok(())
+    }
+
+    /// Writes the trailer and flushes any compressed data, returning the
+    /// underlying writer.
+    pub fn finish(mut self) -> Result<W, BackupError> {
+        self.body.write_all(&[TRAILER_RECORD])?;
+        self.body.write_all(&self.block_count.to_be_bytes())?;
+        self.body.write_all(&self.hasher.finalize().to_be_bytes())?;
+
+        let mut writer = self.body.finish()?;
+        writer.flush()?;
+        Ok(writer)
+    }
+}
+
+/// Reads blocks from a backup, checking each block's checksum and, once the
+/// last block has been read, the trailer.
+pub struct BackupReader<'a> {
+    header: BackupHeader,
+    body: Box<dyn Read + 'a>,
+    block_count: u64,
+    hasher: Hasher,
+}
+
+impl<'a> BackupReader<'a> {
+    pub fn new<R: Read + 'a>(mut reader: R) -> Result<Self, BackupError> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != BACKUP_MAGIC {
+            return Err(BackupError::FormatError("Not a blockstore backup".into()));
+        }
+
+        let format_version = read_u32(&mut reader)?;
+        if format_version != BACKUP_FORMAT_VERSION {
+            return Err(BackupError::FormatError(format!(
+                "Unsupported backup format version: {}",
+                format_version
+            )));
+        }
+
+        let header_len = read_u32(&mut reader)?;
+        let header_bytes = read_bytes(&mut reader, header_len)?;
+        let header: BackupHeader = serde_json::from_slice(&header_bytes)
+            .map_err(|err| BackupError::FormatError(format!("Invalid header: {}", err)))?;
+        if header.format_version != format_version {
+            return Err(BackupError::FormatError(format!(
+                "Header format version {} does not match file format version {}",
+                header.format_version, format_version
+            )));
+        }
+
+        Ok(BackupReader {
+            body: body_reader(reader, header.compression)?,
+            header,
+            block_count: 0,
+            hasher: Hasher::new(),
+        })
+    }
+
+    pub fn header(&self) -> &BackupHeader {
+        &self.header
+    }
+
+    /// Reads the next block, or returns None once the trailer has been read
+    /// and checked.
+    pub fn read_block(&mut self) -> Result<Option<Block>, BackupError> {
+        let mut tag = [0; 1];
+        self.body.read_exact(&mut tag).map_err(|err| {
+            BackupError::FormatError(format!("Backup is truncated, missing trailer: {}", err))
+        })?;
+
+        match tag[0] {
+            BLOCK_RECORD => {
+                let len = read_u32(&mut self.body)?;
+                let packed = read_bytes(&mut self.body, len)?;
+                let expected_crc = read_u32(&mut self.body)?;
+                if crc32(&packed) != expected_crc {
+                    return Err(BackupError::ChecksumError(format!(
+                        "Checksum mismatch for block {} in backup",
+                        self.block_count
+                    )));
+                }
+                self.hasher.update(&packed);
+                self.block_count += 1;
+
+                protobuf::parse_from_bytes(&packed)
+                    .map(Some)
+                    .map_err(|err| {
+                        BackupError::FormatError(format!("Failed to parse block: {}", err))
+                    })
+            }
+            TRAILER_RECORD => {
+                let block_count = read_u64(&mut self.body)?;
+                let expected_crc = read_u32(&mut self.body)?;
+                if block_count != self.block_count {
+                    return Err(BackupError::FormatError(format!(
+                        "Backup trailer lists {} blocks, but {} were read",
+                        block_count, self.block_count
+                    )));
+                }
+                if self.hasher.clone().finalize() != expected_crc {
+                    return Err(BackupError::ChecksumError(
+                        "Checksum mismatch for backup trailer".into(),
+                    ));
+                }
+                Ok(None)
+            }
+            tag => Err(BackupError::FormatError(format!(
+                "Unknown record type in backup: {}",
+                tag
+            ))),
+        }
+    }
+}
+
+/// Decompresses the body of a file, which follows its uncompressed header.
+pub fn body_reader<'a, R: Read + 'a>(
+    reader: R,
+    compression: Compression,
+) -> io::Result<Box<dyn Read + 'a>> {
+    Ok(match compression {
+        Compression::None => Box::new(reader),
+        Compression::Gzip => Box::new(GzDecoder::new(reader)),
+        Compression::Zstd => Box::new(zstd::stream::read::Decoder::new(reader)?),
+    })
+}
+
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut hasher = Hasher::new();
+    hasher.update(bytes);
+    hasher.finalize()
+}
+
+pub fn read_u32<R: Read + ?Sized>(reader: &mut R) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_be_bytes(buf))
+}
+
+pub fn read_u64<R: Read + ?Sized>(reader: &mut R) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_be_bytes(buf))
+}
+
+/// Reads a length-prefixed field of LEN bytes. The length is not trusted: the
+/// field is read into a buffer that grows as bytes arrive, so a corrupt length
+/// fails once the input ends, rather than allocating LEN bytes up front.
+pub fn read_bytes<R: Read + ?Sized>(reader: &mut R, len: u32) -> Result<Vec<u8>, BackupError> {
+    let mut bytes = vec![];
+    (&mut *reader)
+        .take(u64::from(len))
+        .read_to_end(&mut bytes)?;
+    if bytes.len() != len as usize {
+        return Err(BackupError::FormatError(format!(
+            "Field of {} bytes is truncated after {} bytes",
+            len,
+            bytes.len()
+        )));
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_header(compression: Compression) -> BackupHeader {
+        BackupHeader {
+            format_version: BACKUP_FORMAT_VERSION,
+            chain_id: "block-0".into(),
+            head_block_id: "block-2".into(),
+            first_block_num: 0,
+            last_block_num: 2,
+            compression,
+        }
+    }
+
+    fn make_blocks() -> Vec<Block> {
+        (0..3)
+            .map(|i| {
+                let mut block = Block::new();
+                block.set_header_signature(format!("block-{}", i));
+                block
+            })
+            .collect()
+    }
+
+    fn write_backup(header: &BackupHeader, blocks: &[Block]) -> Vec<u8> {
+        let mut writer = BackupWriter::new(vec![], header).unwrap();
+        for block in blocks {
+            writer.write_block(block).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    /// Writes a backup using each compression type and reads it back,
+    /// asserting that the header and blocks are unchanged.
+    #[test]
+    fn backup_round_trip() {
+        for compression in &[Compression::None, Compression::Gzip, Compression::Zstd] {
+            let header = make_header(*compression);
+            let blocks = make_blocks();
+            let buffer = write_backup(&header, &blocks);
+
+            let mut reader = BackupReader::new(&buffer[..]).unwrap();
+            assert_eq!(reader.header(), &header);
+
+            let mut restored = vec![];
+            while let Some(block) = reader.read_block().unwrap() {
+                restored.push(block);
+            }
+            assert_eq!(restored, blocks);
+        }
+    }
+
+    /// Asserts that a corrupted block, a missing trailer, a block length
+    /// past the end of the backup and an unknown file are all rejected.
+    #[test]
+    fn backup_corruption() {
+        let header = make_header(Compression::None);
+        let blocks = make_blocks();
+        let buffer = write_backup(&header, &blocks);
+
+        // Flip a byte in the first block's header signature
+        let mut corrupted = buffer.clone();
+        let position = corrupted
+            .windows(7)
+            .position(|window| window == b"block-0")
+            .unwrap();
+        // Skip past the header, which also contains "block-0"
+        let position = position
+            + 7
+            + corrupted[position + 7..]
+                .windows(7)
+                .position(|window| window == b"block-0")
+                .unwrap();
+        corrupted[position] ^= 0xff;
+        let mut reader = BackupReader::new(&corrupted[..]).unwrap();
+        match reader.read_block() {
+            Err(BackupError::ChecksumError(_)) => (),
+            res => panic!("Expected checksum error, got {:?}", res),
+        }
+
+        // Drop the trailer
+        let truncated = &buffer[..buffer.len() - 13];
+        let mut reader = BackupReader::new(truncated).unwrap();
+        for _ in 0..3 {
+            assert!(reader.read_block().unwrap().is_some());
+        }
+        assert!(reader.read_block().is_err());
+
+        // Claim a block far larger than the backup
+        let mut oversized = buffer[..buffer.len() - 13].to_vec();
+        oversized.push(BLOCK_RECORD);
+        oversized.extend_from_slice(&u32::MAX.to_be_bytes());
+        oversized.extend_from_slice(b"block-3");
+        let mut reader = BackupReader::new(&oversized[..]).unwrap();
+        for _ in 0..3 {
+            assert!(reader.read_block().unwrap().is_some());
+        }
+        match reader.read_block() {
+            Err(BackupError::FormatError(_)) => (),
+            res => panic!("Expected format error, got {:?}", res),
+        }
+
+        assert!(BackupReader::new(&b"not a backup"[..]).is_err());
+    }
+}
@@ -20,7 +20,7 @@ use protobuf;
 use protobuf::Message;
 
 use database::error::DatabaseError;
-use database::lmdb::LmdbDatabase;
+use database::lmdb::{LmdbDatabase, LmdbDatabaseWriter};
 
 pub struct Blockstore<'a> {
     db: LmdbDatabase<'a>,
@@ -112,6 +112,43 @@ impl<'a> Blockstore<'a> {
         Ok(block)
     }
 
+    /// Get the block id stored in the block num index for HEIGHT, if any.
+    pub fn get_block_id_by_height(&self, height: u64) -> Result<Option<String>, DatabaseError> {
+        let block_num = format!("0x{:0>16x}", height);
+        self.get_block_id_from_index("index_block_num", &block_num)
+    }
+
+    /// Get the block id stored in the batch index for BATCH_ID, if any.
+    pub fn get_block_id_by_batch(&self, batch_id: &str) -> Result<Option<String>, DatabaseError> {
+        self.get_block_id_from_index("index_batch", batch_id)
+    }
+
+    /// Get the block id stored in the transaction index for TRANSACTION_ID, if
+    /// any.
+    pub fn get_block_id_by_transaction(
+        &self,
+        transaction_id: &str,
+    ) -> Result<Option<String>, DatabaseError> {
+        self.get_block_id_from_index("index_transaction", transaction_id)
+    }
+
+    fn get_block_id_from_index(
+        &self,
+        index: &str,
+        key: &str,
+    ) -> Result<Option<String>, DatabaseError> {
+        let reader = self.db.reader()?;
+        match reader.index_get(index, &key.as_bytes())? {
+            Some(block_id) => String::from_utf8(block_id).map(Some).map_err(|err| {
+                DatabaseError::CorruptionError(format!(
+                    "Block id in {} for {} is corrupt: {}",
+                    index, key, err
+                ))
+            }),
+            None => Ok(None),
+        }
+    }
+
     pub fn put(&self, block: &Block) -> Result<(), DatabaseError> {
         let block_header: BlockHeader =
             protobuf::parse_from_bytes(&block.header).map_err(|err| {
@@ -124,61 +161,105 @@ impl<'a> Blockstore<'a> {
         })?;
         writer.put(&block.header_signature.as_bytes(), &packed)?;
 
-        // Add block to block num index
-        let block_num_index = format!("0x{:0>16x}", block_header.block_num);
-        writer.index_put(
-            "index_block_num",
-            &block_num_index.as_bytes(),
-            &block.header_signature.as_bytes(),
-        )?;
+        put_indexes(&mut writer, block, &block_header)?;
 
-        for batch in block.batches.iter() {
-            for txn in batch.transactions.iter() {
-                writer.index_put(
-                    "index_transaction",
-                    &txn.header_signature.as_bytes(),
-                    &block.header_signature.as_bytes(),
-                )?;
+        writer.commit()
+    }
+
+    /// Drops the batch, transaction and block num indexes and rebuilds them
+    /// from the blocks in the main database. Blocks are indexed CHUNK_SIZE at
+    /// a time, each chunk in its own write transaction, and PROGRESS is called
+    /// with the total number of blocks indexed after every chunk is committed.
+    pub fn reindex<F: FnMut(usize)>(
+        &self,
+        chunk_size: usize,
+        mut progress: F,
+    ) -> Result<usize, DatabaseError> {
+        let mut writer = self.db.writer()?;
+        writer.index_clear("index_batch")?;
+        writer.index_clear("index_transaction")?;
+        writer.index_clear("index_block_num")?;
+        writer.commit()?;
+
+        let mut indexed = 0;
+        let mut last_key: Option<Vec<u8>> = None;
+
+        loop {
+            // Read the next chunk of blocks, releasing the reader before
+            // writing the chunk's index entries.
+            let blocks = {
+                let reader = self.db.reader()?;
+                let mut cursor = reader.cursor()?;
+                let mut entry = match last_key {
+                    Some(ref key) => cursor.seek_range(key),
+                    None => cursor.first(),
+                };
+                // The previous chunk ended on last_key, so skip over it
+                if last_key.is_some() && entry.as_ref().map(|(key, _)| key) == last_key.as_ref() {
+                    entry = cursor.next();
+                }
+
+                let mut blocks = Vec::with_capacity(chunk_size);
+                while let Some((key, packed)) = entry {
+                    let block: Block = protobuf::parse_from_bytes(&packed).map_err(|err| {
+                        DatabaseError::CorruptionError(format!(
+                            "Could not interpret stored data as a block: {}",
+                            err
+                        ))
+                    })?;
+                    blocks.push(block);
+                    last_key = Some(key);
+                    if blocks.len() >= chunk_size {
+                        break;
+                    }
+                    entry = cursor.next();
+                }
+                blocks
+            };
+
+            if blocks.is_empty() {
+                break;
             }
-        }
 
-        // Add block to batch index
-        for batch in block.batches.iter() {
-            writer.index_put(
-                "index_batch",
-                &batch.header_signature.as_bytes(),
-                &block.header_signature.as_bytes(),
-            )?;
+            let mut writer = self.db.writer()?;
+            for block in blocks.iter() {
+                let block_header: BlockHeader =
+                    protobuf::parse_from_bytes(&block.header).map_err(|err| {
+                        DatabaseError::CorruptionError(format!(
+                            "Invalid block header for {}: {}",
+                            block.header_signature, err
+                        ))
+                    })?;
+                put_indexes(&mut writer, block, &block_header)?;
+            }
+            writer.commit()?;
+
+            indexed += blocks.len();
+            progress(indexed);
         }
 
-        writer.commit()
+        Ok(indexed)
     }
 
     pub fn delete(&self, block_id: &str) -> Result<(), DatabaseError> {
-        let block = self.get(block_id)?;
-        let block_id = &block.header_signature;
-        let block_header: BlockHeader =
-            protobuf::parse_from_bytes(&block.header).map_err(|err| {
-                DatabaseError::CorruptionError(format!("Invalid block header: {}", err))
-            })?;
-        // Delete block from main db
-        let mut writer = self.db.writer()?;
-        writer.delete(&block_id.as_bytes())?;
-
-        // Delete block from block_num index
-        let block_num_index = format!("0x{:0>16x}", block_header.block_num);
-        writer.index_delete("index_block_num", &block_num_index.as_bytes())?;
+        self.delete_blocks(&[block_id])
+    }
 
-        // Delete block from transaction index
-        for batch in block.batches.iter() {
-            for txn in batch.transactions.iter() {
-                writer.index_delete("index_transaction", &txn.header_signature.as_bytes())?;
-            }
-        }
+    /// Deletes the blocks with BLOCK_IDS, and their index entries, in a single
+    /// write transaction; either all of the blocks are deleted or none are.
+    pub fn delete_blocks<S: AsRef<str>>(&self, block_ids: &[S]) -> Result<(), DatabaseError> {
+        let blocks = block_ids
+            .iter()
+            .map(|block_id| self.get(block_id.as_ref()))
+            .collect::<Result<Vec<Block>, DatabaseError>>()?;
 
-        // Delete block from batch index
-        for batch in block.batches.iter() {
-            writer.index_delete("index_batch", &batch.header_signature.as_bytes())?;
+        let mut writer = self.db.writer()?;
+        for block in blocks.iter() {
+            let block_header: BlockHeader =
+                protobuf::parse_from_bytes(&block.header).map_err(|err| {
+                    DatabaseError::CorruptionError(format!("Invalid block header: {}", err))
+                })?;
+            delete_block(&mut writer, block, &block_header)?;
         }
         writer.commit()
     }
@@ -195,6 +276,19 @@ impl<'a> Blockstore<'a> {
         })
     }
 
+    /// Get the header signature of the lowest block in the blockstore, which
+    /// is the genesis block unless the chain was restored from a snapshot.
+    pub fn get_chain_base(&self) -> Result<String, DatabaseError> {
+        let reader = self.db.reader()?;
+        let mut cursor = reader.index_cursor("index_block_num")?;
+        let (_, val) = cursor
+            .first()
+            .ok_or_else(|| DatabaseError::NotFoundError("No chain base".into()))?;
+        String::from_utf8(val).map_err(|err| {
+            DatabaseError::CorruptionError(format!("Chain base block id is corrupt: {}", err))
+        })
+    }
+
     // Get the number of blocks
     pub fn get_current_height(&self) -> Result<usize, DatabaseError> {
         let reader = self.db.reader()?;
@@ -212,6 +306,71 @@ impl<'a> Blockstore<'a> {
     }
 }
 
+/// Adds BLOCK to the block num, transaction and batch indexes.
+fn put_indexes(
+    writer: &mut LmdbDatabaseWriter,
+    block: &Block,
+    block_header: &BlockHeader,
+) -> Result<(), DatabaseError> {
+    // Add block to block num index
+    let block_num_index = format!("0x{:0>16x}", block_header.block_num);
+    writer.index_put(
+        "index_block_num",
+        &block_num_index.as_bytes(),
+        &block.header_signature.as_bytes(),
+    )?;
+
+    for batch in block.batches.iter() {
+        for txn in batch.transactions.iter() {
+            writer.index_put(
+                "index_transaction",
+                &txn.header_signature.as_bytes(),
+                &block.header_signature.as_bytes(),
+            )?;
+        }
+    }
+
+    // Add block to batch index
+    for batch in block.batches.iter() {
+        writer.index_put(
+            "index_batch",
+            &batch.header_signature.as_bytes(),
+            &block.header_signature.as_bytes(),
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Removes BLOCK from the main database and the block num, transaction and
+/// batch indexes.
+fn delete_block(
+    writer: &mut LmdbDatabaseWriter,
+    block: &Block,
+    block_header: &BlockHeader,
+) -> Result<(), DatabaseError> {
+    // Delete block from main db
+    writer.delete(&block.header_signature.as_bytes())?;
+
+    // Delete block from block_num index
+    let block_num_index = format!("0x{:0>16x}", block_header.block_num);
+    writer.index_delete("index_block_num", &block_num_index.as_bytes())?;
+
+    // Delete block from transaction index
+    for batch in block.batches.iter() {
+        for txn in batch.transactions.iter() {
+            writer.index_delete("index_transaction", &txn.header_signature.as_bytes())?;
+        }
+    }
+
+    // Delete block from batch index
+    for batch in block.batches.iter() {
+        writer.index_delete("index_batch", &batch.header_signature.as_bytes())?;
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -240,7 +399,7 @@ mod tests {
     /// blockstore contents at each step.
     #[test]
     fn test_blockstore() {
-        let path_config = config::get_path_config();
+        let path_config = config::get_path_config().unwrap();
 
         let blockstore_path = &path_config.data_dir.join(config::get_blockstore_filename());
 
@@ -275,6 +434,7 @@ mod tests {
             assert_current_height(i as usize + 1, &blockstore);
             assert_chain_head(format!("block-{}", i), &blockstore);
         }
+        assert_eq!(blockstore.get_chain_base().unwrap(), "block-0");
 
         assert_current_height(5, &blockstore);
 
@@ -320,10 +480,63 @@ mod tests {
 
         assert_header_signature(get_by_transaction, String::from("block-with-batch"));
 
+        // Look up block ids directly from the indexes.
+        assert_eq!(
+            blockstore.get_block_id_by_height(6).unwrap(),
+            Some(String::from("block-with-batch"))
+        );
+        assert_eq!(
+            blockstore.get_block_id_by_batch("batch").unwrap(),
+            Some(String::from("block-with-batch"))
+        );
+        assert_eq!(
+            blockstore
+                .get_block_id_by_transaction("transaction")
+                .unwrap(),
+            Some(String::from("block-with-batch"))
+        );
+        assert_eq!(blockstore.get_block_id_by_batch("no-batch").unwrap(), None);
+
+        // Rebuild the indexes, two blocks at a time.
+        let mut progress = vec![];
+        assert_eq!(
+            blockstore
+                .reindex(2, |indexed| progress.push(indexed))
+                .unwrap(),
+            6
+        );
+        assert_eq!(progress, vec![2, 4, 6]);
+        assert_eq!(blockstore.get_batch_count().unwrap(), 1);
+        assert_eq!(blockstore.get_transaction_count().unwrap(), 1);
+        assert_chain_head(String::from("block-with-batch"), &blockstore);
+        assert_header_signature(
+            blockstore.get_by_height(3).unwrap(),
+            String::from("block-3"),
+        );
+
         // Delete a block.
         blockstore.delete("block-with-batch").unwrap();
 
         assert_current_height(5, &blockstore);
         assert_chain_head(String::from("block-4"), &blockstore);
+        assert_eq!(blockstore.get_batch_count().unwrap(), 0);
+
+        // Deleting several blocks fails as a whole if any block is missing.
+        assert!(blockstore
+            .delete_blocks(&["block-4", "block-3", "no-block"])
+            .is_err());
+        assert_current_height(5, &blockstore);
+
+        blockstore.delete_blocks(&["block-4", "block-3"]).unwrap();
+
+        assert_current_height(3, &blockstore);
+        assert_chain_head(String::from("block-2"), &blockstore);
+
+        // The chain base is the lowest block left, as in a chain restored
+        // from a snapshot.
+        blockstore.delete_blocks(&["block-0", "block-1"]).unwrap();
+
+        assert_eq!(blockstore.get_chain_base().unwrap(), "block-2");
+        assert_chain_head(String::from("block-2"), &blockstore);
     }
 }
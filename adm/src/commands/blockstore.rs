@@ -15,27 +15,38 @@
  * ------------------------------------------------------------------------------
  */
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashSet};
 use std::fs::File;
-use std::io::{self, Read, Write};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
 
 use clap::ArgMatches;
 use protobuf;
 use protobuf::Message;
-use serde_yaml;
+use sawtooth_sdk::signing;
+use sawtooth_sdk::signing::secp256k1::Secp256k1PublicKey;
+use serde_json;
 
+use proto::batch::{Batch, BatchHeader};
 use proto::block::{Block, BlockHeader};
 use proto::transaction::TransactionHeader;
 
+use backup::{
+    BackupHeader, BackupReader, BackupWriter, Compression, BACKUP_FORMAT_VERSION, BACKUP_MAGIC,
+};
 use blockstore::Blockstore;
 use config;
 use database::error::DatabaseError;
 use database::lmdb;
 use err::CliError;
-use wrappers::Block as BlockWrapper;
+use output::{print_csv_row, print_serialized, OutputFormat};
+use wrappers::{Block as BlockWrapper, BlockRecord};
 
 const NULL_BLOCK_IDENTIFIER: &str = "0000000000000000";
 
+/// The number of blocks between progress messages for long-running commands
+const PROGRESS_INTERVAL: u64 = 10000;
+
 pub fn run<'a>(args: &ArgMatches<'a>) -> Result<(), CliError> {
     match args.subcommand() {
         ("backup", Some(args)) => run_backup_command(args),
@@ -46,6 +57,10 @@ pub fn run<'a>(args: &ArgMatches<'a>) -> Result<(), CliError> {
         ("export", Some(args)) => run_export_command(args),
         ("import", Some(args)) => run_import_command(args),
         ("stats", Some(args)) => run_stats_command(args),
+        ("verify", Some(args)) => run_verify_command(args),
+        ("reindex", Some(args)) => run_reindex_command(args),
+        ("query", Some(args)) => run_query_command(args),
+        ("diff", Some(args)) => run_diff_command(args),
         _ => {
             println!("Invalid subcommand; Pass --help for usage.");
             Ok(())
@@ -54,36 +69,152 @@ pub fn run<'a>(args: &ArgMatches<'a>) -> Result<(), CliError> {
 }
 
 fn run_backup_command<'a>(args: &ArgMatches<'a>) -> Result<(), CliError> {
-    let ctx = create_context()?;
+    let ctx = create_context(args)?;
     let blockstore = open_blockstore(&ctx)?;
 
     let filepath = args
         .value_of("output")
         .ok_or_else(|| CliError::ArgumentError("No output file".into()))?;
-    let mut file = File::create(filepath)
-        .map_err(|err| CliError::EnvironmentError(format!("Failed to create file: {}", err)))?;
+    let compression_name = args.value_of("compression").unwrap_or("none");
+    let compression = Compression::from_name(compression_name).ok_or_else(|| {
+        CliError::ArgumentError(format!("Unknown compression: {}", compression_name))
+    })?;
+    let from_height = parse_height_arg(args, "from_height")?;
+    let to_height = parse_height_arg(args, "to_height")?;
 
-    let mut current = match args.value_of("start") {
-        None => blockstore.get_chain_head().map_err(|err| {
-            CliError::EnvironmentError(format!("unable to read chain head: {}", err))
-        }),
-        Some(sig) => Ok(sig.into()),
-    }?;
+    let header = write_backup(
+        &blockstore,
+        Path::new(filepath),
+        from_height,
+        to_height,
+        compression,
+    )?;
+
+    println!(
+        "Backed up blocks {} to {} to {}",
+        header.first_block_num, header.last_block_num, filepath
+    );
+    Ok(())
+}
+
+/// Writes the blocks between FROM_HEIGHT and TO_HEIGHT, inclusive, to a
+/// backup at PATH, and returns the backup's header. The range defaults to the
+/// whole chain, from its lowest block: the genesis block, unless the chain was
+/// restored from a snapshot, whose block then identifies the chain.
+fn write_backup(
+    blockstore: &Blockstore,
+    path: &Path,
+    from_height: Option<u64>,
+    to_height: Option<u64>,
+    compression: Compression,
+) -> Result<BackupHeader, CliError> {
+    let head_id = blockstore
+        .get_chain_head()
+        .map_err(|err| CliError::EnvironmentError(format!("unable to read chain head: {}", err)))?;
+    let head = blockstore
+        .get(&head_id)
+        .map_err(|err| CliError::EnvironmentError(format!("unable to read chain head: {}", err)))?;
+    let head_header = parse_block_header(&head)?;
+    let base = blockstore
+        .get_chain_base()
+        .and_then(|base_id| blockstore.get(&base_id))
+        .map_err(|err| CliError::EnvironmentError(format!("unable to read chain base: {}", err)))?;
+    let base_header = parse_block_header(&base)?;
+
+    let from_height = from_height.unwrap_or(base_header.block_num);
+    let to_height = to_height.unwrap_or(head_header.block_num);
+    if from_height > to_height
+        || from_height < base_header.block_num
+        || to_height > head_header.block_num
+    {
+        return Err(CliError::ArgumentError(format!(
+            "Invalid height range {} to {}; the blockstore contains heights {} to {}",
+            from_height, to_height, base_header.block_num, head_header.block_num
+        )));
+    }
+
+    // The first block of an incremental backup follows the block below it
+    let mut previous_block_id = if from_height == base_header.block_num {
+        base_header.previous_block_id
+    } else {
+        blockstore
+            .get_by_height(from_height - 1)
+            .map_err(|err| {
+                CliError::EnvironmentError(format!(
+                    "Block in chain missing from blockstore: {}",
+                    err
+                ))
+            })?
+            .header_signature
+    };
+    let head_block_id = if to_height == head_header.block_num {
+        head_id
+    } else {
+        blockstore
+            .get_by_height(to_height)
+            .map_err(|err| {
+                CliError::EnvironmentError(format!(
+                    "Block in chain missing from blockstore: {}",
+                    err
+                ))
+            })?
+            .header_signature
+    };
+
+    let header = BackupHeader {
+        format_version: BACKUP_FORMAT_VERSION,
+        chain_id: base.header_signature,
+        head_block_id,
+        first_block_num: from_height,
+        last_block_num: to_height,
+        compression,
+    };
+
+    let file = File::create(path)
+        .map_err(|err| CliError::EnvironmentError(format!("Failed to create file: {}", err)))?;
+    let mut writer = BackupWriter::new(BufWriter::new(file), &header)
+        .map_err(|err| CliError::EnvironmentError(format!("Failed to write backup: {}", err)))?;
 
-    while current != NULL_BLOCK_IDENTIFIER {
-        let block = blockstore.get(&current).map_err(|err| {
+    // Write the blocks in ascending order, so that the backup can be restored
+    // in order.
+    for height in header.first_block_num..=header.last_block_num {
+        let block = blockstore.get_by_height(height).map_err(|err| {
             CliError::EnvironmentError(format!("Block in chain missing from blockstore: {}", err))
         })?;
-        backup_block(&block, &mut file)?;
-        let block_header: BlockHeader = protobuf::parse_from_bytes(&block.header)
-            .map_err(|err| CliError::ParseError(format!("Unable to read block header: {}", err)))?;
-        current = block_header.previous_block_id
+        let block_header = parse_block_header(&block)?;
+        if block_header.previous_block_id != previous_block_id {
+            return Err(CliError::EnvironmentError(format!(
+                "Block {} at height {} does not follow block {}; the blockstore may be corrupt",
+                block.header_signature, height, previous_block_id
+            )));
+        }
+
+        writer.write_block(&block).map_err(|err| {
+            CliError::EnvironmentError(format!("Failed to write backup: {}", err))
+        })?;
+        let written = height - header.first_block_num + 1;
+        if written % PROGRESS_INTERVAL == 0 {
+            eprintln!("Backed up {} blocks", written);
+        }
+        previous_block_id = block.header_signature;
     }
-    Ok(())
+
+    if previous_block_id != header.head_block_id {
+        return Err(CliError::EnvironmentError(format!(
+            "Block at height {} changed during backup, from {} to {}",
+            header.last_block_num, header.head_block_id, previous_block_id
+        )));
+    }
+
+    writer
+        .finish()
+        .map_err(|err| CliError::EnvironmentError(format!("Failed to write backup: {}", err)))?;
+
+    Ok(header)
 }
 
 fn run_restore_command<'a>(args: &ArgMatches<'a>) -> Result<(), CliError> {
-    let ctx = create_context()?;
+    let ctx = create_context(args)?;
     let blockstore = open_blockstore(&ctx)?;
 
     let filepath = args
@@ -92,18 +223,173 @@ fn run_restore_command<'a>(args: &ArgMatches<'a>) -> Result<(), CliError> {
     let mut file = File::open(filepath)
         .map_err(|err| CliError::EnvironmentError(format!("Failed to open file: {}", err)))?;
 
-    let mut source = protobuf::CodedInputStream::new(&mut file);
+    // Backups written before the backup format was versioned have no header
+    let mut magic = [0; 4];
+    let is_versioned = match file.read_exact(&mut magic) {
+        Ok(()) => &magic == BACKUP_MAGIC,
+        Err(_) => false,
+    };
+    file.seek(SeekFrom::Start(0))
+        .map_err(|err| CliError::EnvironmentError(format!("Failed to read file: {}", err)))?;
 
-    while let Some(block) = restore_block(&mut source)? {
-        blockstore
-            .put(&block)
-            .map_err(|err| CliError::EnvironmentError(format!("Failed to put block: {}", err)))?;
+    if is_versioned {
+        let from_height = parse_height_arg(args, "from_height")?;
+        let to_height = parse_height_arg(args, "to_height")?;
+        restore_backup(&blockstore, file, from_height, to_height)
+    } else if args.is_present("from_height") || args.is_present("to_height") {
+        Err(CliError::ArgumentError(
+            "Height ranges are not supported for unversioned backups".into(),
+        ))
+    } else {
+        let mut source = protobuf::CodedInputStream::new(&mut file);
+
+        while let Some(block) = restore_block(&mut source)? {
+            blockstore.put(&block).map_err(|err| {
+                CliError::EnvironmentError(format!("Failed to put block: {}", err))
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// Restores the blocks in a versioned backup that are between FROM_HEIGHT and
+/// TO_HEIGHT, inclusive. Blocks that are already in the blockstore, such as
+/// those restored by an interrupted run, are skipped; any other block must be
+/// an immediate child of the current chain head.
+fn restore_backup(
+    blockstore: &Blockstore,
+    file: File,
+    from_height: Option<u64>,
+    to_height: Option<u64>,
+) -> Result<(), CliError> {
+    let mut reader = BackupReader::new(BufReader::new(file))
+        .map_err(|err| CliError::ParseError(format!("Unable to read backup: {}", err)))?;
+    let header = reader.header().clone();
+    let from_height = from_height.unwrap_or(header.first_block_num);
+    let to_height = to_height.unwrap_or(header.last_block_num);
+    if from_height > to_height
+        || from_height < header.first_block_num
+        || to_height > header.last_block_num
+    {
+        return Err(CliError::ArgumentError(format!(
+            "Invalid height range {} to {}; the backup contains heights {} to {}",
+            from_height, to_height, header.first_block_num, header.last_block_num
+        )));
+    }
+
+    // A chain is identified by its lowest block, which for a chain restored
+    // from a snapshot is not the genesis block, so backups of the same chain
+    // may have different ids. A backup from another chain is only certain
+    // when the blockstore has its genesis block; otherwise the blocks are
+    // checked as they are restored.
+    let mut is_empty = false;
+    match blockstore.get_chain_base() {
+        Ok(base_id) => {
+            let base = blockstore.get(&base_id).map_err(|err| {
+                CliError::EnvironmentError(format!("failed to read chain base: {}", err))
+            })?;
+            if base_id != header.chain_id
+                && parse_block_header(&base)?.block_num == 0
+                && !is_stored(blockstore, &header.chain_id)?
+            {
+                return Err(CliError::ArgumentError(format!(
+                    "Backup is of chain {}, but the blockstore contains chain {}",
+                    header.chain_id, base_id
+                )));
+            }
+        }
+        Err(DatabaseError::NotFoundError(_)) => is_empty = true,
+        Err(err) => {
+            return Err(CliError::EnvironmentError(format!(
+                "failed to read chain base id: {}",
+                err
+            )));
+        }
+    }
+
+    let mut previous_block_id = String::from(NULL_BLOCK_IDENTIFIER);
+    let mut block_num = header.first_block_num;
+    let mut restored = 0;
+    let mut skipped = 0;
+
+    while let Some(block) = reader
+        .read_block()
+        .map_err(|err| CliError::ParseError(format!("Unable to read backup: {}", err)))?
+    {
+        let block_header = parse_block_header(&block)?;
+        if block_header.block_num != block_num {
+            return Err(CliError::ParseError(format!(
+                "Expected block {} in backup, found block {}",
+                block_num, block_header.block_num
+            )));
+        }
+        if (block_num > header.first_block_num || block_num == 0)
+            && block_header.previous_block_id != previous_block_id
+        {
+            return Err(CliError::ParseError(format!(
+                "Block {} in backup does not follow block {}",
+                block.header_signature, previous_block_id
+            )));
+        }
+
+        if block_num < from_height || block_num > to_height {
+            previous_block_id = block.header_signature;
+            block_num += 1;
+            continue;
+        }
+
+        match blockstore.get(&block.header_signature) {
+            Ok(ref existing) if existing == &block => skipped += 1,
+            Ok(_) => {
+                return Err(CliError::EnvironmentError(format!(
+                    "Block {} in the blockstore differs from the backup",
+                    block.header_signature
+                )));
+            }
+            Err(DatabaseError::NotFoundError(_)) => {
+                // An empty blockstore can only start a chain at its lowest
+                // block
+                if is_empty && block_num != 0 && block.header_signature != header.chain_id {
+                    return Err(CliError::ArgumentError(format!(
+                        "Cannot restore from height {} into an empty blockstore",
+                        from_height
+                    )));
+                }
+                check_extends_chain_head(blockstore, &block_header)?;
+                blockstore.put(&block).map_err(|err| {
+                    CliError::EnvironmentError(format!("Failed to put block: {}", err))
+                })?;
+                is_empty = false;
+                restored += 1;
+            }
+            Err(err) => {
+                return Err(CliError::EnvironmentError(format!(
+                    "failed to read block {}: {}",
+                    block.header_signature, err
+                )));
+            }
+        }
+        if (restored + skipped) % PROGRESS_INTERVAL == 0 {
+            eprintln!("Restored {} blocks", restored + skipped);
+        }
+
+        previous_block_id = block.header_signature;
+        block_num += 1;
     }
+
+    if previous_block_id != header.head_block_id || block_num != header.last_block_num + 1 {
+        return Err(CliError::ParseError(format!(
+            "Backup ended at block {}, but its header lists {} at height {}",
+            previous_block_id, header.head_block_id, header.last_block_num
+        )));
+    }
+
+    println!("Restored {} blocks ({} already present)", restored, skipped);
     Ok(())
 }
 
 fn run_list_command<'a>(args: &ArgMatches<'a>) -> Result<(), CliError> {
-    let ctx = create_context()?;
+    let ctx = create_context(args)?;
     let blockstore = open_blockstore(&ctx)?;
 
     let mut count = u64::from_str_radix(args.value_of("count").unwrap_or("100"), 10).unwrap();
@@ -116,9 +402,15 @@ fn run_list_command<'a>(args: &ArgMatches<'a>) -> Result<(), CliError> {
         Some(sig) => Ok(sig.into()),
     }?;
 
-    // Walk back from the chain head
+    let format = OutputFormat::from_name(args.value_of("format").unwrap_or("text"))?;
+
+    let chain_base = blockstore.get_chain_base().map_err(|err| {
+        CliError::EnvironmentError(format!("failed to get chain base id: {}", err))
+    })?;
+
+    // Walk back from the chain head to the lowest block in the blockstore
     let mut block_id = head_sig;
-    print_block_store_list_header();
+    let mut rows = vec![];
 
     while block_id != NULL_BLOCK_IDENTIFIER && count > 0 {
         let block = blockstore.get(&block_id).map_err(|err| {
@@ -136,17 +428,58 @@ fn run_list_command<'a>(args: &ArgMatches<'a>) -> Result<(), CliError> {
             .batches
             .iter()
             .fold(0, |acc, batch| acc + batch.transactions.len());
-        print_block_store_list_row(
-            block_header.block_num,
-            &block.header_signature,
+        rows.push(BlockListRow {
+            block_num: block_header.block_num,
+            block_id: block.header_signature,
             batches,
             txns,
-            &block_header.signer_public_key,
-        );
+            signer: block_header.signer_public_key,
+        });
+        if block_id == chain_base {
+            break;
+        }
         block_id = block_header.previous_block_id;
         count -= 1;
     }
-    Ok(())
+
+    match format {
+        OutputFormat::Text => {
+            print_block_store_list_header();
+            for row in &rows {
+                print_block_store_list_row(
+                    row.block_num,
+                    &row.block_id,
+                    row.batches,
+                    row.txns,
+                    &row.signer,
+                );
+            }
+            Ok(())
+        }
+        OutputFormat::Csv => {
+            print_csv_row(&["block_num", "block_id", "batches", "txns", "signer"]);
+            for row in &rows {
+                print_csv_row(&[
+                    row.block_num.to_string(),
+                    row.block_id.clone(),
+                    row.batches.to_string(),
+                    row.txns.to_string(),
+                    row.signer.clone(),
+                ]);
+            }
+            Ok(())
+        }
+        _ => print_serialized(&rows, format),
+    }
+}
+
+#[derive(Serialize)]
+struct BlockListRow {
+    block_num: u64,
+    block_id: String,
+    batches: usize,
+    txns: usize,
+    signer: String,
 }
 
 fn print_block_store_list_header() {
@@ -174,7 +507,7 @@ fn print_block_store_list_row(
 }
 
 fn run_show_command<'a>(args: &ArgMatches<'a>) -> Result<(), CliError> {
-    let ctx = create_context()?;
+    let ctx = create_context(args)?;
     let blockstore = open_blockstore(&ctx)?;
 
     let block = {
@@ -211,25 +544,482 @@ fn run_show_command<'a>(args: &ArgMatches<'a>) -> Result<(), CliError> {
         CliError::EnvironmentError(format!("failed to create block wrapper: {}", err))
     })?;
 
-    let block_yaml = serde_yaml::to_string(&block_wrapper).map_err(|err| {
-        CliError::EnvironmentError(format!("failed to serialize block wrapper: {}", err))
+    match OutputFormat::from_name(args.value_of("format").unwrap_or("yaml"))? {
+        // A block is a tree, so CSV output has one row per transaction
+        OutputFormat::Csv => {
+            print_csv_row(&[
+                "block_num",
+                "block_id",
+                "batch_id",
+                "batch_signer",
+                "transaction_id",
+                "family_name",
+                "family_version",
+                "transaction_signer",
+            ]);
+            for batch in &block_wrapper.batches {
+                for txn in &batch.transactions {
+                    print_csv_row(&[
+                        &block_wrapper.block_num.to_string(),
+                        &block_wrapper.header_signature,
+                        &batch.header_signature,
+                        &batch.signer_public_key,
+                        &txn.header_signature,
+                        &txn.family_name,
+                        &txn.family_version,
+                        &txn.signer_public_key,
+                    ]);
+                }
+            }
+            Ok(())
+        }
+        OutputFormat::Json => print_serialized(&block_wrapper, OutputFormat::Json),
+        _ => print_serialized(&block_wrapper, OutputFormat::Yaml),
+    }
+}
+
+fn run_query_command<'a>(args: &ArgMatches<'a>) -> Result<(), CliError> {
+    let ctx = create_context(args)?;
+    let blockstore = open_blockstore(&ctx)?;
+
+    let filter = QueryFilter {
+        block_signer: args.value_of("block_signer").map(String::from),
+        batch_signer: args.value_of("batch_signer").map(String::from),
+        family_name: args.value_of("family").map(String::from),
+    };
+    if filter.block_signer.is_none()
+        && filter.batch_signer.is_none()
+        && filter.family_name.is_none()
+    {
+        return Err(CliError::ArgumentError(
+            "At least one of --block-signer, --batch-signer or --family is required".into(),
+        ));
+    }
+    let format = OutputFormat::from_name(args.value_of("format").unwrap_or("text"))?;
+
+    let chain_head = blockstore.get_chain_head().map_err(|err| {
+        CliError::EnvironmentError(format!("failed to get chain head id: {}", err))
     })?;
+    let head_block_num = parse_block_header(&blockstore.get(&chain_head).map_err(|err| {
+        CliError::EnvironmentError(format!("failed to read chain head: {}", err))
+    })?)?
+    .block_num;
+    let from_height = parse_height_arg(args, "from_height")?.unwrap_or(0);
+    let to_height = parse_height_arg(args, "to_height")?.unwrap_or(head_block_num);
+    if from_height > to_height {
+        return Err(CliError::ArgumentError(format!(
+            "Invalid height range {} to {}",
+            from_height, to_height
+        )));
+    }
+    let to_height = to_height.min(head_block_num);
 
-    println!("{}", block_yaml);
-    Ok(())
+    // Scan the main chain, in ascending order, over the height range
+    let mut matches = vec![];
+    for height in from_height..=to_height {
+        let block = blockstore.get_by_height(height).map_err(|err| {
+            CliError::EnvironmentError(format!(
+                "failed to read block at height {}: {}",
+                height, err
+            ))
+        })?;
+        matches.extend(query_block(&block, &filter)?);
+    }
+
+    match format {
+        OutputFormat::Text => {
+            println!(
+                "{:<5} {:<11} {:<128} {:<16} SIGNER",
+                "NUM", "TYPE", "ID", "FAMILY"
+            );
+            for m in &matches {
+                println!(
+                    "{:<5} {:<11} {:<128} {:<16} {}",
+                    m.block_num,
+                    m.kind,
+                    m.id,
+                    m.family_name.as_ref().map(String::as_str).unwrap_or("-"),
+                    m.signer
+                );
+            }
+            Ok(())
+        }
+        OutputFormat::Csv => {
+            print_csv_row(&["block_num", "block_id", "type", "id", "family", "signer"]);
+            for m in &matches {
+                print_csv_row(&[
+                    &m.block_num.to_string(),
+                    &m.block_id,
+                    m.kind,
+                    &m.id,
+                    m.family_name.as_ref().map(String::as_str).unwrap_or(""),
+                    &m.signer,
+                ]);
+            }
+            Ok(())
+        }
+        _ => print_serialized(&matches, format),
+    }
+}
+
+/// The criteria for a blockstore query; all of the given criteria must match.
+struct QueryFilter {
+    block_signer: Option<String>,
+    batch_signer: Option<String>,
+    family_name: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct QueryMatch {
+    block_num: u64,
+    block_id: String,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    family_name: Option<String>,
+    signer: String,
+}
+
+/// Finds the parts of BLOCK that match FILTER. The matches are transactions
+/// if a family is given, batches if a batch signer is given, and blocks
+/// otherwise.
+fn query_block(block: &Block, filter: &QueryFilter) -> Result<Vec<QueryMatch>, CliError> {
+    let block_header = parse_block_header(block)?;
+    if let Some(ref signer) = filter.block_signer {
+        if &block_header.signer_public_key != signer {
+            return Ok(vec![]);
+        }
+    }
+
+    if filter.batch_signer.is_none() && filter.family_name.is_none() {
+        return Ok(vec![QueryMatch {
+            block_num: block_header.block_num,
+            block_id: block.header_signature.clone(),
+            kind: "block",
+            id: block.header_signature.clone(),
+            family_name: None,
+            signer: block_header.signer_public_key,
+        }]);
+    }
+
+    let mut matches = vec![];
+    for batch in block.batches.iter() {
+        let batch_header: BatchHeader =
+            protobuf::parse_from_bytes(&batch.header).map_err(|err| {
+                CliError::ParseError(format!(
+                    "failed to parse header for batch {}: {}",
+                    batch.header_signature, err
+                ))
+            })?;
+        if let Some(ref signer) = filter.batch_signer {
+            if &batch_header.signer_public_key != signer {
+                continue;
+            }
+        }
+
+        let family_name = match filter.family_name {
+            Some(ref family_name) => family_name,
+            None => {
+                matches.push(QueryMatch {
+                    block_num: block_header.block_num,
+                    block_id: block.header_signature.clone(),
+                    kind: "batch",
+                    id: batch.header_signature.clone(),
+                    family_name: None,
+                    signer: batch_header.signer_public_key,
+                });
+                continue;
+            }
+        };
+
+        for txn in batch.transactions.iter() {
+            let txn_header: TransactionHeader =
+                protobuf::parse_from_bytes(&txn.header).map_err(|err| {
+                    CliError::ParseError(format!(
+                        "failed to parse header for transaction {}: {}",
+                        txn.header_signature, err
+                    ))
+                })?;
+            if &txn_header.family_name == family_name {
+                matches.push(QueryMatch {
+                    block_num: block_header.block_num,
+                    block_id: block.header_signature.clone(),
+                    kind: "transaction",
+                    id: txn.header_signature.clone(),
+                    family_name: Some(txn_header.family_name),
+                    signer: txn_header.signer_public_key,
+                });
+            }
+        }
+    }
+    Ok(matches)
+}
+
+fn run_diff_command<'a>(args: &ArgMatches<'a>) -> Result<(), CliError> {
+    // Both blockstores are opened read-only, as either may belong to a
+    // running validator
+    let ctx = create_read_only_context_at(&get_blockstore_path(args)?, args)?;
+    let blockstore = open_read_only_blockstore(&ctx)?;
+
+    let other_path = Path::new(
+        args.value_of("other")
+            .ok_or_else(|| CliError::ArgumentError("No other blockstore".into()))?,
+    );
+    if !other_path.exists() {
+        return Err(CliError::ArgumentError(format!(
+            "Blockstore not found: {:?}",
+            other_path
+        )));
+    }
+    let other_ctx = create_read_only_context_at(other_path, args)?;
+    let other_blockstore = open_read_only_blockstore(&other_ctx)?;
+
+    let format = OutputFormat::from_name(args.value_of("format").unwrap_or("text"))?;
+
+    let head_height = get_head_height(&blockstore)?;
+    let other_head_height = get_head_height(&other_blockstore)?;
+
+    let common_height = find_common_height(
+        head_height.min(other_head_height),
+        |height| get_block_id_at(&blockstore, height),
+        |height| get_block_id_at(&other_blockstore, height),
+    )?;
+    let first_new_height = common_height.map(|height| height + 1).unwrap_or(0);
+
+    let mut local = get_fork_report(&blockstore, first_new_height, head_height)?;
+    let mut other = get_fork_report(&other_blockstore, first_new_height, other_head_height)?;
+
+    // Batches can be in both forks, at different heights
+    let local_batches: HashSet<String> = local.unique_batches.iter().cloned().collect();
+    let other_batches: HashSet<String> = other.unique_batches.iter().cloned().collect();
+    local
+        .unique_batches
+        .retain(|batch_id| !other_batches.contains(batch_id));
+    other
+        .unique_batches
+        .retain(|batch_id| !local_batches.contains(batch_id));
+
+    let common_ancestor = match common_height {
+        Some(height) => Some(BlockRef {
+            block_num: height,
+            block_id: get_block_id_at(&blockstore, height)?,
+        }),
+        None => None,
+    };
+    let diverged = !local.unique_blocks.is_empty() && !other.unique_blocks.is_empty();
+    let report = DiffReport {
+        common_ancestor,
+        first_differing_height: if diverged {
+            Some(first_new_height)
+        } else {
+            None
+        },
+        local,
+        other,
+    };
+
+    match format {
+        OutputFormat::Text => {
+            print_diff_text(&report, other_path);
+            Ok(())
+        }
+        _ => print_serialized(&report, format),
+    }
+}
+
+#[derive(Serialize)]
+struct DiffReport {
+    common_ancestor: Option<BlockRef>,
+    first_differing_height: Option<u64>,
+    local: ForkReport,
+    other: ForkReport,
+}
+
+#[derive(Serialize)]
+struct ForkReport {
+    chain_head: Option<BlockRef>,
+    unique_blocks: Vec<BlockRef>,
+    unique_batches: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct BlockRef {
+    block_num: u64,
+    block_id: String,
+}
+
+/// Returns the height of the chain head, or None if the blockstore is empty.
+fn get_head_height(blockstore: &Blockstore) -> Result<Option<u64>, CliError> {
+    let chain_head = match blockstore.get_chain_head() {
+        Ok(chain_head) => chain_head,
+        Err(DatabaseError::NotFoundError(_)) => return Ok(None),
+        Err(err) => {
+            return Err(CliError::EnvironmentError(format!(
+                "failed to get chain head id: {}",
+                err
+            )));
+        }
+    };
+    let block = blockstore
+        .get(&chain_head)
+        .map_err(|err| CliError::EnvironmentError(format!("failed to read chain head: {}", err)))?;
+    Ok(Some(parse_block_header(&block)?.block_num))
+}
+
+fn get_block_id_at(blockstore: &Blockstore, height: u64) -> Result<String, CliError> {
+    blockstore
+        .get_block_id_by_height(height)
+        .map_err(|err| {
+            CliError::EnvironmentError(format!("failed to read block num index: {}", err))
+        })?
+        .ok_or_else(|| CliError::EnvironmentError(format!("Block missing at height {}", height)))
+}
+
+/// Finds the height of the last block shared by two chains, given the height
+/// of the shorter chain's head and a way to look up each chain's block id by
+/// height. Since a block id covers all of the block's ancestors, the chains
+/// match at every height up to the common ancestor and at none above it.
+fn find_common_height<F, G>(
+    max_height: Option<u64>,
+    mut get_id: F,
+    mut get_other_id: G,
+) -> Result<Option<u64>, CliError>
+where
+    F: FnMut(u64) -> Result<String, CliError>,
+    G: FnMut(u64) -> Result<String, CliError>,
+{
+    let max_height = match max_height {
+        Some(max_height) => max_height,
+        None => return Ok(None),
+    };
+
+    // Binary search for the last matching height in [low, high)
+    let mut low = 0;
+    let mut high = max_height + 1;
+    while low < high {
+        let mid = low + (high - low) / 2;
+        if get_id(mid)? == get_other_id(mid)? {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+
+    Ok(low.checked_sub(1))
+}
+
+/// Reports the blocks from FIRST_HEIGHT to the chain head at HEAD_HEIGHT, and
+/// their batches.
+fn get_fork_report(
+    blockstore: &Blockstore,
+    first_height: u64,
+    head_height: Option<u64>,
+) -> Result<ForkReport, CliError> {
+    let mut report = ForkReport {
+        chain_head: None,
+        unique_blocks: vec![],
+        unique_batches: vec![],
+    };
+    let head_height = match head_height {
+        Some(head_height) => head_height,
+        None => return Ok(report),
+    };
+
+    for height in first_height..=head_height {
+        let block = blockstore.get_by_height(height).map_err(|err| {
+            CliError::EnvironmentError(format!(
+                "failed to read block at height {}: {}",
+                height, err
+            ))
+        })?;
+        report.unique_batches.extend(
+            block
+                .batches
+                .iter()
+                .map(|batch| batch.header_signature.clone()),
+        );
+        report.unique_blocks.push(BlockRef {
+            block_num: height,
+            block_id: block.header_signature,
+        });
+    }
+    report.chain_head = Some(BlockRef {
+        block_num: head_height,
+        block_id: get_block_id_at(blockstore, head_height)?,
+    });
+    Ok(report)
+}
+
+fn print_diff_text(report: &DiffReport, other_path: &Path) {
+    match report.common_ancestor {
+        Some(ref block) => println!("Common ancestor: {} {}", block.block_num, block.block_id),
+        None => println!("Common ancestor: none"),
+    }
+    match report.first_differing_height {
+        Some(height) => println!("First differing height: {}", height),
+        None => println!("The chains do not diverge"),
+    }
+
+    for (name, fork) in &[
+        (String::from("local blockstore"), &report.local),
+        (format!("{:?}", other_path), &report.other),
+    ] {
+        match fork.chain_head {
+            Some(ref head) => println!(
+                "Blocks only in {} (chain head {} {}):",
+                name, head.block_num, head.block_id
+            ),
+            None => println!("Blocks only in {} (empty):", name),
+        }
+        for block in &fork.unique_blocks {
+            println!("  {} {}", block.block_num, block.block_id);
+        }
+        println!("Batches only in {}:", name);
+        for batch_id in &fork.unique_batches {
+            println!("  {}", batch_id);
+        }
+    }
 }
 
 fn run_prune_command<'a>(args: &ArgMatches<'a>) -> Result<(), CliError> {
-    let ctx = create_context()?;
+    let ctx = create_context(args)?;
     let blockstore = open_blockstore(&ctx)?;
 
     let block_id = args
         .value_of("block")
         .ok_or_else(|| CliError::ArgumentError("No block id".into()))?;
+    let safe_depth = args
+        .value_of("safe_depth")
+        .map(|depth| {
+            depth
+                .parse::<u64>()
+                .map_err(|err| CliError::ArgumentError(format!("Invalid safe depth: {}", err)))
+        })
+        .transpose()?;
 
-    blockstore
+    let block = blockstore
         .get(block_id)
         .map_err(|_| CliError::ArgumentError(format!("Block not found: {}", block_id)))?;
+    let block_header = parse_block_header(&block)?;
+
+    // Only blocks on the main chain can be pruned, since pruning walks back
+    // from the chain head to the block
+    match blockstore.get_block_id_by_height(block_header.block_num) {
+        Ok(Some(ref id)) if id == block_id => (),
+        Ok(_) => {
+            return Err(CliError::ArgumentError(format!(
+                "Block {} is not on the chain ending at the chain head",
+                block_id
+            )));
+        }
+        Err(err) => {
+            return Err(CliError::EnvironmentError(format!(
+                "failed to read block num index: {}",
+                err
+            )));
+        }
+    }
 
     // Get the chain head
     let chain_head = blockstore.get_chain_head().map_err(|err| {
@@ -243,24 +1033,43 @@ fn run_prune_command<'a>(args: &ArgMatches<'a>) -> Result<(), CliError> {
         ))
     })?;
 
+    if let Some(safe_depth) = safe_depth {
+        let depth = parse_block_header(&current)?.block_num - block_header.block_num;
+        if depth > safe_depth {
+            return Err(CliError::ArgumentError(format!(
+                "Block {} is {} blocks below the chain head, deeper than the safe depth of {}",
+                block_id, depth, safe_depth
+            )));
+        }
+    }
+
+    let dry_run = args.is_present("dry_run");
+    let mut pruned = vec![];
+    let mut batch_count = 0;
+    let mut txn_count = 0;
+
     loop {
-        blockstore
-            .delete(&current.header_signature)
-            .map_err(|err| {
-                CliError::EnvironmentError(format!(
-                    "failed to delete block {}: {}",
-                    current.header_signature, err
-                ))
-            })?;
+        let header = parse_block_header(&current)?;
+        batch_count += current.batches.len();
+        txn_count += current
+            .batches
+            .iter()
+            .fold(0, |acc, batch| acc + batch.transactions.len());
+
+        if dry_run {
+            println!("Block {} {}", header.block_num, current.header_signature);
+            for batch in current.batches.iter() {
+                println!("  Batch {}", batch.header_signature);
+                for txn in batch.transactions.iter() {
+                    println!("    Transaction {}", txn.header_signature);
+                }
+            }
+        }
+
+        pruned.push(current.header_signature.clone());
         if current.header_signature == block_id {
             break;
         }
-        let header: BlockHeader = protobuf::parse_from_bytes(&current.header).map_err(|err| {
-            CliError::ParseError(format!(
-                "failed to parse block_header for block {}: {}",
-                current.header_signature, err
-            ))
-        })?;
 
         current = blockstore.get(&header.previous_block_id).map_err(|err| {
             CliError::EnvironmentError(format!(
@@ -269,48 +1078,137 @@ fn run_prune_command<'a>(args: &ArgMatches<'a>) -> Result<(), CliError> {
             ))
         })?;
     }
+
+    if dry_run {
+        println!(
+            "Would remove {} blocks, {} batches and {} transactions",
+            pruned.len(),
+            batch_count,
+            txn_count
+        );
+        return Ok(());
+    }
+
+    blockstore
+        .delete_blocks(&pruned)
+        .map_err(|err| CliError::EnvironmentError(format!("failed to delete blocks: {}", err)))?;
+
+    println!(
+        "Removed {} blocks, {} batches and {} transactions",
+        pruned.len(),
+        batch_count,
+        txn_count
+    );
     Ok(())
 }
 
 fn run_export_command<'a>(args: &ArgMatches<'a>) -> Result<(), CliError> {
-    let ctx = create_context()?;
+    let ctx = create_context(args)?;
     let blockstore = open_blockstore(&ctx)?;
 
-    let block_id = args
-        .value_of("block")
-        .ok_or_else(|| CliError::ArgumentError("No block id".into()))?;
+    let blocks = match args.value_of("block") {
+        Some(block_id) => vec![blockstore
+            .get(block_id)
+            .map_err(|_| CliError::ArgumentError(format!("Block not found: {}", block_id)))?],
+        None => get_block_range(&blockstore, args)?,
+    };
 
-    let block = blockstore
-        .get(block_id)
-        .map_err(|_| CliError::ArgumentError(format!("Block not found: {}", block_id)))?;
+    // A single block is written packed by default, for compatibility with
+    // earlier versions of this command; ranges need to be delimited.
+    let format = args.value_of("format").unwrap_or_else(|| {
+        if args.is_present("block") {
+            "protobuf"
+        } else {
+            "delimited"
+        }
+    });
+    if format == "protobuf" && blocks.len() != 1 {
+        return Err(CliError::ArgumentError(
+            "The protobuf format holds a single block; use the delimited or json format".into(),
+        ));
+    }
 
     match args.value_of("output") {
         Some(filepath) => {
-            let mut file = File::create(filepath).map_err(|err| {
+            let file = File::create(filepath).map_err(|err| {
                 CliError::EnvironmentError(format!("Failed to create file: {}", err))
             })?;
-            block.write_to_writer(&mut file).map_err(|err| {
-                CliError::EnvironmentError(format!(
-                    "failed to write {} to {}: {}",
-                    block_id, filepath, err
-                ))
+            write_blocks(blocks, format, &mut BufWriter::new(file)).map_err(|err| {
+                CliError::EnvironmentError(format!("failed to write to {}: {}", filepath, err))
             })
         }
         None => {
             let stdout = io::stdout();
             let mut handle = stdout.lock();
-            block.write_to_writer(&mut handle).map_err(|err| {
-                CliError::EnvironmentError(format!(
-                    "failed to write block {} to stdout: {}",
-                    block_id, err
+            write_blocks(blocks, format, &mut handle).map_err(|err| {
+                CliError::EnvironmentError(format!("failed to write to stdout: {}", err))
+            })
+        }
+    }
+}
+
+/// Reads COUNT blocks from the main chain, in ascending order, starting at
+/// the height START. By default, blocks are read up to the chain head.
+fn get_block_range<'a>(
+    blockstore: &Blockstore,
+    args: &ArgMatches<'a>,
+) -> Result<Vec<Block>, CliError> {
+    let start = parse_height_arg(args, "start")?
+        .ok_or_else(|| CliError::ArgumentError("No block id or start height".into()))?;
+    let height = blockstore
+        .get_current_height()
+        .map_err(|err| CliError::EnvironmentError(format!("failed to read block count: {}", err)))?
+        as u64;
+    if start >= height {
+        return Err(CliError::ArgumentError(format!(
+            "Start height {} is above the chain head at height {}",
+            start,
+            height.saturating_sub(1)
+        )));
+    }
+    let count = match args.value_of("count") {
+        Some(count) => count
+            .parse()
+            .map_err(|err| CliError::ArgumentError(format!("Invalid count {}: {}", count, err)))?,
+        None => height - start,
+    };
+
+    (start..height.min(start.saturating_add(count)))
+        .map(|block_num| {
+            blockstore.get_by_height(block_num).map_err(|err| {
+                CliError::EnvironmentError(format!(
+                    "failed to read block at height {}: {}",
+                    block_num, err
                 ))
             })
+        })
+        .collect()
+}
+
+fn write_blocks<W: Write>(blocks: Vec<Block>, format: &str, writer: &mut W) -> Result<(), String> {
+    match format {
+        "json" => {
+            let wrappers = blocks
+                .into_iter()
+                .map(|block| BlockWrapper::try_from(block).map(BlockRecord::from))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|err| format!("failed to create block wrapper: {}", err))?;
+            serde_json::to_writer_pretty(&mut *writer, &wrappers).map_err(|err| err.to_string())?;
+            writeln!(writer).map_err(|err| err.to_string())
         }
+        "delimited" => blocks.iter().try_for_each(|block| {
+            block
+                .write_length_delimited_to_writer(writer)
+                .map_err(|err| err.to_string())
+        }),
+        _ => blocks
+            .iter()
+            .try_for_each(|block| block.write_to_writer(writer).map_err(|err| err.to_string())),
     }
 }
 
 fn run_import_command<'a>(args: &ArgMatches<'a>) -> Result<(), CliError> {
-    let ctx = create_context()?;
+    let ctx = create_context(args)?;
     let blockstore = open_blockstore(&ctx)?;
 
     let filepath = args
@@ -318,47 +1216,104 @@ fn run_import_command<'a>(args: &ArgMatches<'a>) -> Result<(), CliError> {
         .ok_or_else(|| CliError::ArgumentError("No file".into()))?;
     let mut file = File::open(filepath)
         .map_err(|err| CliError::EnvironmentError(format!("Failed to open file: {}", err)))?;
-    let mut packed = Vec::new();
-    file.read_to_end(&mut packed)
-        .map_err(|err| CliError::EnvironmentError(format!("Failed to read file: {}", err)))?;
 
-    let block: Block = protobuf::parse_from_bytes(&packed)
-        .map_err(|err| CliError::ParseError(format!("{}", err)))?;
-    let block_header: BlockHeader = protobuf::parse_from_bytes(&block.header)
-        .map_err(|err| CliError::ParseError(format!("{}", err)))?;
-    let block_id = block.header_signature.clone();
+    let blocks = match args.value_of("format").unwrap_or("protobuf") {
+        "json" => read_json_blocks(file)?,
+        "delimited" => {
+            let mut source = protobuf::CodedInputStream::new(&mut file);
+            let mut blocks = vec![];
+            while let Some(block) = restore_block(&mut source)? {
+                blocks.push(block);
+            }
+            blocks
+        }
+        _ => {
+            let mut packed = Vec::new();
+            file.read_to_end(&mut packed).map_err(|err| {
+                CliError::EnvironmentError(format!("Failed to read file: {}", err))
+            })?;
+            vec![protobuf::parse_from_bytes(&packed)
+                .map_err(|err| CliError::ParseError(format!("{}", err)))?]
+        }
+    };
 
-    // Ensure this block is an immediate child of the current chain head
-    match blockstore.get_chain_head() {
-        Ok(chain_head) => {
-            if block_header.previous_block_id != chain_head {
+    // Check that the blocks form a chain before adding any of them
+    let mut block_headers = Vec::with_capacity(blocks.len());
+    let mut previous_block_id: Option<&str> = None;
+    for block in &blocks {
+        let block_header: BlockHeader = protobuf::parse_from_bytes(&block.header)
+            .map_err(|err| CliError::ParseError(format!("{}", err)))?;
+        if let Some(previous_block_id) = previous_block_id {
+            if block_header.previous_block_id != previous_block_id {
                 return Err(CliError::ArgumentError(format!(
-                    "New block must be an immediate child of the current chain head: {}",
-                    chain_head
+                    "Block {} does not follow block {} in {}",
+                    block.header_signature, previous_block_id, filepath
                 )));
             }
         }
-        Err(DatabaseError::NotFoundError(_)) => (),
-        Err(err) => {
-            return Err(CliError::EnvironmentError(format!(
-                "failed to read chain head id: {}",
-                err
-            )));
-        }
+        previous_block_id = Some(&block.header_signature);
+        block_headers.push(block_header);
     }
 
-    blockstore.put(&block).map_err(|err| {
-        CliError::ArgumentError(format!("Failed to put block into database: {}", err))
-    })?;
+    for (block, block_header) in blocks.iter().zip(block_headers.iter()) {
+        check_extends_chain_head(&blockstore, block_header)?;
+
+        blockstore.put(block).map_err(|err| {
+            CliError::ArgumentError(format!("Failed to put block into database: {}", err))
+        })?;
 
-    println!("Block {} added", block_id);
+        println!("Block {} added", block.header_signature);
+    }
     Ok(())
 }
 
+/// Reads blocks exported as JSON. Since the headers are re-encoded from their
+/// decoded fields, each header is checked against its signature.
+fn read_json_blocks(file: File) -> Result<Vec<Block>, CliError> {
+    let records: Vec<BlockRecord> = serde_json::from_reader(BufReader::new(file))
+        .map_err(|err| CliError::ParseError(format!("Invalid JSON blocks: {}", err)))?;
+
+    let context = signing::create_context("secp256k1")
+        .map_err(|err| CliError::EnvironmentError(format!("{}", err)))?;
+
+    records
+        .into_iter()
+        .map(|record| {
+            let block = BlockWrapper::from(record)
+                .into_proto()
+                .map_err(|err| CliError::ParseError(format!("{}", err)))?;
+            let block_header = parse_block_header(&block)?;
+
+            let mut problems = vec![];
+            if let Err(msg) = verify_signature(
+                &*context,
+                &block.header_signature,
+                &block.header,
+                &block_header.signer_public_key,
+            ) {
+                problems.push(msg);
+            }
+            for batch in block.get_batches() {
+                problems.extend(verify_batch(&*context, batch));
+            }
+            if !problems.is_empty() {
+                return Err(CliError::ParseError(format!(
+                    "Block {} does not match its signatures: {}",
+                    block.header_signature,
+                    problems.join("; ")
+                )));
+            }
+            Ok(block)
+        })
+        .collect()
+}
+
 fn run_stats_command<'a>(args: &ArgMatches<'a>) -> Result<(), CliError> {
-    let ctx = create_context()?;
+    let ctx = create_context(args)?;
     let blockstore = open_blockstore(&ctx)?;
 
+    let format = OutputFormat::from_name(args.value_of("format").unwrap_or("text"))?;
+
     let block_count = blockstore.get_current_height().map_err(|err| {
         CliError::EnvironmentError(format!("failed to read block count: {}", err))
     })?;
@@ -369,66 +1324,613 @@ fn run_stats_command<'a>(args: &ArgMatches<'a>) -> Result<(), CliError> {
         CliError::EnvironmentError(format!("failed to read transaction count: {}", err))
     })?;
 
-    if args.is_present("extended") {
-        let mut txn_family_counts = HashMap::new();
-        let chain_head = blockstore.get_chain_head().map_err(|err| {
-            CliError::EnvironmentError(format!("failed to get chain head id: {}", err))
+    let extended = if args.is_present("extended") {
+        Some(get_extended_stats(&blockstore)?)
+    } else {
+        None
+    };
+
+    let stats = Stats {
+        blocks: block_count,
+        batches: batch_count,
+        transactions: txn_count,
+        extended,
+    };
+
+    match format {
+        OutputFormat::Text => {
+            print_stats_text(&stats);
+            Ok(())
+        }
+        OutputFormat::Csv => {
+            print_stats_csv(&stats);
+            Ok(())
+        }
+        _ => print_serialized(&stats, format),
+    }
+}
+
+#[derive(Serialize)]
+struct Stats {
+    blocks: usize,
+    batches: usize,
+    transactions: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    extended: Option<ExtendedStats>,
+}
+
+#[derive(Serialize)]
+struct ExtendedStats {
+    transactions_by_family: BTreeMap<String, u64>,
+    batches_per_block: Percentiles,
+    transactions_per_block: Percentiles,
+    blocks_by_signer: BTreeMap<String, u64>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct Percentiles {
+    min: u64,
+    p50: u64,
+    p90: u64,
+    p99: u64,
+    max: u64,
+}
+
+impl Percentiles {
+    /// Computes nearest-rank percentiles of VALUES.
+    fn from_values(mut values: Vec<u64>) -> Self {
+        values.sort_unstable();
+        let rank = |percentile: usize| {
+            if values.is_empty() {
+                0
+            } else {
+                let rank = (percentile * values.len() + 99) / 100;
+                values[rank.max(1) - 1]
+            }
+        };
+        Percentiles {
+            min: rank(0),
+            p50: rank(50),
+            p90: rank(90),
+            p99: rank(99),
+            max: rank(100),
+        }
+    }
+
+    fn fields(&self) -> Vec<(&'static str, u64)> {
+        vec![
+            ("min", self.min),
+            ("p50", self.p50),
+            ("p90", self.p90),
+            ("p99", self.p99),
+            ("max", self.max),
+        ]
+    }
+}
+
+/// Walks the chain from the chain head back to its lowest block, collecting
+/// the extended stats.
+fn get_extended_stats(blockstore: &Blockstore) -> Result<ExtendedStats, CliError> {
+    let mut txn_family_counts = BTreeMap::new();
+    let mut signer_counts = BTreeMap::new();
+    let mut batches_per_block = vec![];
+    let mut txns_per_block = vec![];
+
+    let chain_head = blockstore.get_chain_head().map_err(|err| {
+        CliError::EnvironmentError(format!("failed to get chain head id: {}", err))
+    })?;
+    let chain_base = blockstore.get_chain_base().map_err(|err| {
+        CliError::EnvironmentError(format!("failed to get chain base id: {}", err))
+    })?;
+    let mut block = blockstore
+        .get(&chain_head)
+        .map_err(|err| CliError::EnvironmentError(format!("failed to read chain head: {}", err)))?;
+
+    loop {
+        let mut txns = 0;
+        for batch in &block.batches {
+            for txn in &batch.transactions {
+                let txn_header: TransactionHeader = protobuf::parse_from_bytes(&txn.header)
+                    .map_err(|err| {
+                        CliError::ParseError(format!(
+                            "failed to parse header for transaction {}: {}",
+                            txn.header_signature, err
+                        ))
+                    })?;
+                let count = txn_family_counts.entry(txn_header.family_name).or_insert(0);
+                *count += 1;
+                txns += 1;
+            }
+        }
+        batches_per_block.push(block.batches.len() as u64);
+        txns_per_block.push(txns);
+
+        let header: BlockHeader = protobuf::parse_from_bytes(&block.header).map_err(|err| {
+            CliError::ParseError(format!(
+                "failed to parse header for block {}: {}",
+                block.header_signature, err
+            ))
         })?;
-        let mut block = blockstore.get(&chain_head).map_err(|err| {
-            CliError::EnvironmentError(format!("failed to read chain head: {}", err))
+        let count = signer_counts
+            .entry(header.signer_public_key.clone())
+            .or_insert(0);
+        *count += 1;
+
+        if header.previous_block_id == NULL_BLOCK_IDENTIFIER || block.header_signature == chain_base
+        {
+            break;
+        }
+        block = blockstore.get(&header.previous_block_id).map_err(|err| {
+            CliError::EnvironmentError(format!(
+                "failed to read block {}: {}",
+                header.previous_block_id, err
+            ))
         })?;
+    }
 
-        loop {
-            for batch in &block.batches {
-                for txn in &batch.transactions {
-                    let txn_header: TransactionHeader = protobuf::parse_from_bytes(&txn.header)
-                        .map_err(|err| {
-                            CliError::ParseError(format!(
-                                "failed to parse header for transaction {}: {}",
-                                txn.header_signature, err
-                            ))
-                        })?;
-                    let count = txn_family_counts.entry(txn_header.family_name).or_insert(0);
-                    *count += 1;
-                }
-            }
-            let header: BlockHeader = protobuf::parse_from_bytes(&block.header).map_err(|err| {
-                CliError::ParseError(format!(
-                    "failed to parse header for block {}: {}",
-                    block.header_signature, err
-                ))
-            })?;
-            if header.previous_block_id == NULL_BLOCK_IDENTIFIER {
+    Ok(ExtendedStats {
+        transactions_by_family: txn_family_counts,
+        batches_per_block: Percentiles::from_values(batches_per_block),
+        transactions_per_block: Percentiles::from_values(txns_per_block),
+        blocks_by_signer: signer_counts,
+    })
+}
+
+fn print_stats_text(stats: &Stats) {
+    println!("Blocks:       {}", stats.blocks);
+    println!("Batches:      {}", stats.batches);
+    println!("Transactions: {}", stats.transactions);
+    if let Some(ref extended) = stats.extended {
+        for (family, count) in &extended.transactions_by_family {
+            println!("  {}: {}", family, count);
+        }
+        println!("Batches per block:");
+        for (name, value) in extended.batches_per_block.fields() {
+            println!("  {}: {}", name, value);
+        }
+        println!("Transactions per block:");
+        for (name, value) in extended.transactions_per_block.fields() {
+            println!("  {}: {}", name, value);
+        }
+        println!("Blocks by signer:");
+        for (signer, count) in &extended.blocks_by_signer {
+            println!("  {}: {}", signer, count);
+        }
+    }
+}
+
+/// Prints the stats as CSV records of the stat's group, name and value.
+fn print_stats_csv(stats: &Stats) {
+    print_csv_row(&["group", "name", "value"]);
+    print_csv_row(&["totals", "blocks", &stats.blocks.to_string()]);
+    print_csv_row(&["totals", "batches", &stats.batches.to_string()]);
+    print_csv_row(&["totals", "transactions", &stats.transactions.to_string()]);
+    if let Some(ref extended) = stats.extended {
+        for (family, count) in &extended.transactions_by_family {
+            print_csv_row(&["transactions_by_family", family, &count.to_string()]);
+        }
+        for (name, value) in extended.batches_per_block.fields() {
+            print_csv_row(&["batches_per_block", name, &value.to_string()]);
+        }
+        for (name, value) in extended.transactions_per_block.fields() {
+            print_csv_row(&["transactions_per_block", name, &value.to_string()]);
+        }
+        for (signer, count) in &extended.blocks_by_signer {
+            print_csv_row(&["blocks_by_signer", signer, &count.to_string()]);
+        }
+    }
+}
+
+fn run_reindex_command<'a>(args: &ArgMatches<'a>) -> Result<(), CliError> {
+    let ctx = create_context(args)?;
+    let blockstore = open_blockstore(&ctx)?;
+
+    let chunk_size: usize = args
+        .value_of("chunk_size")
+        .unwrap_or("1000")
+        .parse()
+        .map_err(|err| CliError::ArgumentError(format!("Invalid chunk size: {}", err)))?;
+    if chunk_size == 0 {
+        return Err(CliError::ArgumentError(
+            "Chunk size must be greater than 0".into(),
+        ));
+    }
+
+    let block_count = blockstore.get_current_height().map_err(|err| {
+        CliError::EnvironmentError(format!("failed to read block count: {}", err))
+    })?;
+
+    let indexed = blockstore
+        .reindex(chunk_size, |indexed| {
+            eprintln!("Indexed {} of {} blocks", indexed, block_count)
+        })
+        .map_err(|err| CliError::EnvironmentError(format!("failed to reindex: {}", err)))?;
+
+    println!("Rebuilt indexes for {} blocks", indexed);
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct VerifyReport {
+    chain_head: String,
+    blocks_checked: u64,
+    problems: Vec<VerifyProblem>,
+}
+
+#[derive(Serialize)]
+struct VerifyProblem {
+    block_id: String,
+    block_num: Option<u64>,
+    message: String,
+}
+
+fn run_verify_command<'a>(args: &ArgMatches<'a>) -> Result<(), CliError> {
+    let format = OutputFormat::from_name(args.value_of("format").unwrap_or("text"))?;
+
+    let ctx = create_context(args)?;
+    let blockstore = open_blockstore(&ctx)?;
+
+    let signing_context = signing::create_context("secp256k1")
+        .map_err(|err| CliError::EnvironmentError(format!("{}", err)))?;
+
+    let chain_head = blockstore.get_chain_head().map_err(|err| {
+        CliError::EnvironmentError(format!("failed to get chain head id: {}", err))
+    })?;
+
+    // A chain restored from a snapshot has no blocks below its lowest one
+    let chain_base = blockstore.get_chain_base().map_err(|err| {
+        CliError::EnvironmentError(format!("failed to get chain base id: {}", err))
+    })?;
+
+    let mut report = VerifyReport {
+        chain_head: chain_head.clone(),
+        blocks_checked: 0,
+        problems: vec![],
+    };
+
+    // Walk back from the chain head, expecting each block to be exactly one
+    // below the block that referenced it.
+    let mut block_id = chain_head;
+    let mut expected_block_num: Option<u64> = None;
+
+    while block_id != NULL_BLOCK_IDENTIFIER {
+        let block = match blockstore.get(&block_id) {
+            Ok(block) => block,
+            Err(err) => {
+                report.problems.push(VerifyProblem {
+                    block_id,
+                    block_num: expected_block_num,
+                    message: format!("unable to read block: {}", err),
+                });
                 break;
             }
-            block = blockstore.get(&header.previous_block_id).map_err(|err| {
-                CliError::EnvironmentError(format!(
-                    "failed to read block {}: {}",
-                    header.previous_block_id, err
-                ))
-            })?;
+        };
+        report.blocks_checked += 1;
+
+        let mut problems = vec![];
+        let header = verify_block(
+            &blockstore,
+            &*signing_context,
+            &block_id,
+            &block,
+            expected_block_num,
+            &mut problems,
+        );
+        report.problems.extend(problems.into_iter().map(|message| {
+            VerifyProblem {
+                block_id: block_id.clone(),
+                block_num: header
+                    .as_ref()
+                    .map(|header| header.block_num)
+                    .or(expected_block_num),
+                message,
+            }
+        }));
+
+        match header {
+            Some(header) => {
+                if header.previous_block_id != NULL_BLOCK_IDENTIFIER && header.block_num == 0 {
+                    report.problems.push(VerifyProblem {
+                        block_id: block_id.clone(),
+                        block_num: Some(header.block_num),
+                        message: format!(
+                            "block 0 has a previous block: {}",
+                            header.previous_block_id
+                        ),
+                    });
+                    break;
+                }
+                if block_id == chain_base {
+                    break;
+                }
+                expected_block_num = header.block_num.checked_sub(1);
+                block_id = header.previous_block_id;
+            }
+            // Without a header there is no way to find the previous block
+            None => break,
         }
+    }
 
-        println!("Blocks:       {}", block_count);
-        println!("Batches:      {}", batch_count);
-        println!("Transactions: {}", txn_count);
-        for (family, count) in &txn_family_counts {
-            println!("  {}: {}", family, count);
+    match format {
+        OutputFormat::Text => {
+            for problem in &report.problems {
+                match problem.block_num {
+                    Some(block_num) => {
+                        println!("{:<5} {}: {}", block_num, problem.block_id, problem.message)
+                    }
+                    None => println!("{:<5} {}: {}", "?", problem.block_id, problem.message),
+                }
+            }
+            println!("Chain head:     {}", report.chain_head);
+            println!("Blocks checked: {}", report.blocks_checked);
+            println!("Problems found: {}", report.problems.len());
+        }
+        OutputFormat::Csv => {
+            print_csv_row(&["block_num", "block_id", "message"]);
+            for problem in &report.problems {
+                print_csv_row(&[
+                    &problem
+                        .block_num
+                        .map(|block_num| block_num.to_string())
+                        .unwrap_or_default(),
+                    &problem.block_id,
+                    &problem.message,
+                ]);
+            }
         }
+        _ => print_serialized(&report, format)?,
+    }
+
+    if report.problems.is_empty() {
+        Ok(())
     } else {
-        println!("Blocks:       {}", block_count);
-        println!("Batches:      {}", batch_count);
-        println!("Transactions: {}", txn_count);
+        Err(CliError::EnvironmentError(format!(
+            "blockstore verification failed with {} problem(s)",
+            report.problems.len()
+        )))
     }
+}
 
-    Ok(())
+/// Checks a single block, stored under BLOCK_ID, for corruption, adding a
+/// message to PROBLEMS for every issue found. Returns the block's header if it
+/// could be parsed, so that the caller can continue walking the chain.
+fn verify_block(
+    blockstore: &Blockstore,
+    context: &dyn signing::Context,
+    block_id: &str,
+    block: &Block,
+    expected_block_num: Option<u64>,
+    problems: &mut Vec<String>,
+) -> Option<BlockHeader> {
+    let header: BlockHeader = match protobuf::parse_from_bytes(&block.header) {
+        Ok(header) => header,
+        Err(err) => {
+            problems.push(format!("unable to parse block header: {}", err));
+            return None;
+        }
+    };
+
+    if block.header_signature != block_id {
+        problems.push(format!(
+            "block is stored under {} but has header signature {}",
+            block_id, block.header_signature
+        ));
+    }
+
+    if let Some(expected_block_num) = expected_block_num {
+        if header.block_num != expected_block_num {
+            problems.push(format!(
+                "block num is {} but expected {}",
+                header.block_num, expected_block_num
+            ));
+        }
+    }
+
+    if header.previous_block_id == NULL_BLOCK_IDENTIFIER && header.block_num != 0 {
+        problems.push(format!("block {} has no previous block", header.block_num));
+    }
+
+    verify_block_contents(context, block, &header, problems);
+
+    match blockstore.get_block_id_by_height(header.block_num) {
+        Ok(Some(ref indexed_id)) if indexed_id == block_id => (),
+        Ok(Some(indexed_id)) => problems.push(format!(
+            "index_block_num maps {} to block {}",
+            header.block_num, indexed_id
+        )),
+        Ok(None) => problems.push("block is missing from index_block_num".into()),
+        Err(err) => problems.push(format!("unable to read index_block_num: {}", err)),
+    }
+
+    for batch in block.batches.iter() {
+        match blockstore.get_block_id_by_batch(&batch.header_signature) {
+            Ok(Some(ref indexed_id)) if indexed_id == block_id => (),
+            Ok(Some(indexed_id)) => problems.push(format!(
+                "index_batch maps batch {} to block {}",
+                batch.header_signature, indexed_id
+            )),
+            Ok(None) => problems.push(format!(
+                "batch {} is missing from index_batch",
+                batch.header_signature
+            )),
+            Err(err) => problems.push(format!("unable to read index_batch: {}", err)),
+        }
+
+        for txn in batch.transactions.iter() {
+            match blockstore.get_block_id_by_transaction(&txn.header_signature) {
+                Ok(Some(ref indexed_id)) if indexed_id == block_id => (),
+                Ok(Some(indexed_id)) => problems.push(format!(
+                    "index_transaction maps transaction {} to block {}",
+                    txn.header_signature, indexed_id
+                )),
+                Ok(None) => problems.push(format!(
+                    "transaction {} is missing from index_transaction",
+                    txn.header_signature
+                )),
+                Err(err) => problems.push(format!("unable to read index_transaction: {}", err)),
+            }
+        }
+    }
+
+    Some(header)
+}
+
+/// Checks the signature and batches of BLOCK, whose header is HEADER, adding
+/// a message to PROBLEMS for every issue found.
+pub fn verify_block_contents(
+    context: &dyn signing::Context,
+    block: &Block,
+    header: &BlockHeader,
+    problems: &mut Vec<String>,
+) {
+    if let Err(msg) = verify_signature(
+        context,
+        &block.header_signature,
+        &block.header,
+        &header.signer_public_key,
+    ) {
+        problems.push(format!("invalid block signature: {}", msg));
+    }
+
+    let batch_ids: Vec<&str> = block
+        .batches
+        .iter()
+        .map(|batch| batch.header_signature.as_str())
+        .collect();
+    if header.batch_ids.iter().map(String::as_str).ne(batch_ids) {
+        problems.push("batch ids in header do not match the block's batches".into());
+    }
+
+    for batch in block.batches.iter() {
+        problems.extend(
+            verify_batch(context, batch)
+                .into_iter()
+                .map(|msg| format!("batch {}: {}", batch.header_signature, msg)),
+        );
+    }
+}
+
+/// Checks a batch's header, signature and transactions, returning a message
+/// for every problem found.
+fn verify_batch(context: &dyn signing::Context, batch: &Batch) -> Vec<String> {
+    let mut problems = vec![];
+
+    let header: BatchHeader = match protobuf::parse_from_bytes(&batch.header) {
+        Ok(header) => header,
+        Err(err) => {
+            problems.push(format!("unable to parse batch header: {}", err));
+            return problems;
+        }
+    };
+
+    if let Err(msg) = verify_signature(
+        context,
+        &batch.header_signature,
+        &batch.header,
+        &header.signer_public_key,
+    ) {
+        problems.push(format!("invalid batch signature: {}", msg));
+    }
+
+    let txn_ids: Vec<&str> = batch
+        .transactions
+        .iter()
+        .map(|txn| txn.header_signature.as_str())
+        .collect();
+    if header
+        .transaction_ids
+        .iter()
+        .map(String::as_str)
+        .ne(txn_ids)
+    {
+        problems.push("transaction ids in header do not match the batch's transactions".into());
+    }
+
+    for txn in batch.transactions.iter() {
+        let txn_header: TransactionHeader = match protobuf::parse_from_bytes(&txn.header) {
+            Ok(txn_header) => txn_header,
+            Err(err) => {
+                problems.push(format!(
+                    "unable to parse header for transaction {}: {}",
+                    txn.header_signature, err
+                ));
+                continue;
+            }
+        };
+
+        if txn_header.batcher_public_key != header.signer_public_key {
+            problems.push(format!(
+                "transaction {} was batched by {}, not the batch signer",
+                txn.header_signature, txn_header.batcher_public_key
+            ));
+        }
+
+        if let Err(msg) = verify_signature(
+            context,
+            &txn.header_signature,
+            &txn.header,
+            &txn_header.signer_public_key,
+        ) {
+            problems.push(format!(
+                "invalid signature for transaction {}: {}",
+                txn.header_signature, msg
+            ));
+        }
+    }
+
+    problems
 }
 
-fn create_context() -> Result<lmdb::LmdbContext, CliError> {
-    let path_config = config::get_path_config();
-    let blockstore_path = &path_config.data_dir.join(config::get_blockstore_filename());
+fn verify_signature(
+    context: &dyn signing::Context,
+    signature: &str,
+    message: &[u8],
+    public_key: &str,
+) -> Result<(), String> {
+    let public_key = Secp256k1PublicKey::from_hex(public_key)
+        .map_err(|err| format!("invalid public key {}: {}", public_key, err))?;
+    match context.verify(signature, message, &public_key) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(format!("{} does not match the header", signature)),
+        Err(err) => Err(format!("unable to verify {}: {}", signature, err)),
+    }
+}
 
-    lmdb::LmdbContext::new(blockstore_path, 3, None).map_err(|err| {
+/// Returns the path of the blockstore. The data directory comes from
+/// --data-dir if it is given, or from the path config otherwise.
+fn get_blockstore_path<'a>(args: &ArgMatches<'a>) -> Result<PathBuf, CliError> {
+    let data_dir = match args.value_of("data_dir") {
+        Some(data_dir) => PathBuf::from(data_dir),
+        None => config::get_path_config()?.data_dir,
+    };
+    Ok(data_dir.join(config::get_blockstore_filename()))
+}
+
+/// Returns the map size from --map-size, if it is given.
+fn get_map_size<'a>(args: &ArgMatches<'a>) -> Result<Option<usize>, CliError> {
+    args.value_of("map_size")
+        .map(|size| {
+            size.parse::<usize>().map_err(|err| {
+                CliError::ArgumentError(format!("Invalid map size {}: {}", size, err))
+            })
+        })
+        .transpose()
+}
+
+/// Creates the blockstore context.
+fn create_context<'a>(args: &ArgMatches<'a>) -> Result<lmdb::LmdbContext, CliError> {
+    lmdb::LmdbContext::new(&get_blockstore_path(args)?, 3, get_map_size(args)?).map_err(|err| {
+        CliError::EnvironmentError(format!("failed to create block store context: {}", err))
+    })
+}
+
+/// Creates a read-only context for the blockstore at BLOCKSTORE_PATH, so that
+/// it can be read while a validator has it open.
+fn create_read_only_context_at<'a>(
+    blockstore_path: &Path,
+    args: &ArgMatches<'a>,
+) -> Result<lmdb::LmdbContext, CliError> {
+    lmdb::LmdbContext::new_read_only(blockstore_path, 3, get_map_size(args)?).map_err(|err| {
         CliError::EnvironmentError(format!("failed to create block store context: {}", err))
     })
 }
@@ -443,12 +1945,75 @@ fn open_blockstore(ctx: &lmdb::LmdbContext) -> Result<Blockstore, CliError> {
     Ok(Blockstore::new(blockstore_db))
 }
 
-fn backup_block<W: Write>(block: &Block, writer: &mut W) -> Result<(), CliError> {
-    block
-        .write_length_delimited_to_writer(writer)
-        .map_err(|err| CliError::EnvironmentError(format!("{}", err)))
+/// Opens the blockstore of a read-only context, which can not create its
+/// databases.
+fn open_read_only_blockstore(ctx: &lmdb::LmdbContext) -> Result<Blockstore, CliError> {
+    let blockstore_db = lmdb::LmdbDatabase::open(
+        ctx,
+        &["index_batch", "index_transaction", "index_block_num"],
+    )
+    .map_err(|err| CliError::EnvironmentError(format!("failed to open block store DB: {}", err)))?;
+
+    Ok(Blockstore::new(blockstore_db))
+}
+
+/// Ensures that the block with BLOCK_HEADER is an immediate child of the
+/// current chain head, if there is one.
+fn check_extends_chain_head(
+    blockstore: &Blockstore,
+    block_header: &BlockHeader,
+) -> Result<(), CliError> {
+    match blockstore.get_chain_head() {
+        Ok(chain_head) => {
+            if block_header.previous_block_id != chain_head {
+                return Err(CliError::ArgumentError(format!(
+                    "New block must be an immediate child of the current chain head: {}",
+                    chain_head
+                )));
+            }
+            Ok(())
+        }
+        Err(DatabaseError::NotFoundError(_)) => Ok(()),
+        Err(err) => Err(CliError::EnvironmentError(format!(
+            "failed to read chain head id: {}",
+            err
+        ))),
+    }
+}
+
+/// Whether the block BLOCK_ID is in BLOCKSTORE.
+fn is_stored(blockstore: &Blockstore, block_id: &str) -> Result<bool, CliError> {
+    match blockstore.get(block_id) {
+        Ok(_) => Ok(true),
+        Err(DatabaseError::NotFoundError(_)) => Ok(false),
+        Err(err) => Err(CliError::EnvironmentError(format!(
+            "failed to read block {}: {}",
+            block_id, err
+        ))),
+    }
 }
 
+fn parse_height_arg<'a>(args: &ArgMatches<'a>, name: &str) -> Result<Option<u64>, CliError> {
+    args.value_of(name)
+        .map(|height| {
+            height.parse().map_err(|err| {
+                CliError::ArgumentError(format!("Invalid height {}: {}", height, err))
+            })
+        })
+        .transpose()
+}
+
+fn parse_block_header(block: &Block) -> Result<BlockHeader, CliError> {
+    protobuf::parse_from_bytes(&block.header).map_err(|err| {
+        CliError::ParseError(format!(
+            "failed to parse header for block {}: {}",
+            block.header_signature, err
+        ))
+    })
+}
+
+/// Reads a block from a backup written before the backup format was
+/// versioned, where blocks are length-delimited with no header or checksum.
 fn restore_block(source: &mut protobuf::CodedInputStream) -> Result<Option<Block>, CliError> {
     let eof = source
         .eof()
@@ -467,8 +2032,165 @@ fn restore_block(source: &mut protobuf::CodedInputStream) -> Result<Option<Block
 mod tests {
     use super::*;
 
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    use proto::transaction::Transaction;
     use protobuf::CodedInputStream;
 
+    fn backup_block<W: Write>(block: &Block, writer: &mut W) -> Result<(), CliError> {
+        block
+            .write_length_delimited_to_writer(writer)
+            .map_err(|err| CliError::EnvironmentError(format!("{}", err)))
+    }
+
+    #[test]
+    fn percentiles() {
+        assert_eq!(
+            Percentiles {
+                min: 0,
+                p50: 0,
+                p90: 0,
+                p99: 0,
+                max: 0,
+            },
+            Percentiles::from_values(vec![])
+        );
+        assert_eq!(
+            Percentiles {
+                min: 1,
+                p50: 50,
+                p90: 90,
+                p99: 99,
+                max: 100,
+            },
+            Percentiles::from_values((1..=100).rev().collect())
+        );
+        assert_eq!(
+            Percentiles {
+                min: 3,
+                p50: 3,
+                p90: 7,
+                p99: 7,
+                max: 7,
+            },
+            Percentiles::from_values(vec![7, 3])
+        );
+    }
+
+    /// Creates a block at BLOCK_NUM, signed by SIGNER, holding BATCHES of
+    /// (batch signer, transaction families).
+    fn create_query_block(block_num: u64, signer: &str, batches: &[(&str, &[&str])]) -> Block {
+        let mut block = Block::new();
+        block.set_header_signature(format!("block-{}", block_num));
+        let mut block_header = BlockHeader::new();
+        block_header.set_block_num(block_num);
+        block_header.set_signer_public_key(signer.into());
+        block.set_header(block_header.write_to_bytes().unwrap());
+
+        for (i, (batch_signer, families)) in batches.iter().enumerate() {
+            let mut batch = Batch::new();
+            batch.set_header_signature(format!("batch-{}-{}", block_num, i));
+            let mut batch_header = BatchHeader::new();
+            batch_header.set_signer_public_key(batch_signer.to_string());
+            batch.set_header(batch_header.write_to_bytes().unwrap());
+            for (j, family) in families.iter().enumerate() {
+                let mut txn = Transaction::new();
+                txn.set_header_signature(format!("txn-{}-{}-{}", block_num, i, j));
+                let mut txn_header = TransactionHeader::new();
+                txn_header.set_family_name(family.to_string());
+                txn_header.set_signer_public_key(batch_signer.to_string());
+                txn.set_header(txn_header.write_to_bytes().unwrap());
+                batch.mut_transactions().push(txn);
+            }
+            block.mut_batches().push(batch);
+        }
+        block
+    }
+
+    fn ids(matches: Vec<QueryMatch>) -> Vec<String> {
+        matches.into_iter().map(|m| m.id).collect()
+    }
+
+    #[test]
+    fn query_filters() {
+        let block = create_query_block(
+            3,
+            "validator",
+            &[("alice", &["intkey", "xo"]), ("bob", &["intkey"])],
+        );
+
+        let filter = QueryFilter {
+            block_signer: Some("validator".into()),
+            batch_signer: None,
+            family_name: None,
+        };
+        assert_eq!(vec!["block-3"], ids(query_block(&block, &filter).unwrap()));
+
+        let filter = QueryFilter {
+            block_signer: Some("other".into()),
+            batch_signer: None,
+            family_name: Some("intkey".into()),
+        };
+        assert!(query_block(&block, &filter).unwrap().is_empty());
+
+        let filter = QueryFilter {
+            block_signer: None,
+            batch_signer: Some("bob".into()),
+            family_name: None,
+        };
+        assert_eq!(
+            vec!["batch-3-1"],
+            ids(query_block(&block, &filter).unwrap())
+        );
+
+        let filter = QueryFilter {
+            block_signer: None,
+            batch_signer: None,
+            family_name: Some("intkey".into()),
+        };
+        assert_eq!(
+            vec!["txn-3-0-0", "txn-3-1-0"],
+            ids(query_block(&block, &filter).unwrap())
+        );
+
+        let filter = QueryFilter {
+            block_signer: None,
+            batch_signer: Some("alice".into()),
+            family_name: Some("xo".into()),
+        };
+        assert_eq!(
+            vec!["txn-3-0-1"],
+            ids(query_block(&block, &filter).unwrap())
+        );
+    }
+
+    fn common_height(local: &[&str], other: &[&str]) -> Option<u64> {
+        let max_height = (local.len().min(other.len()) as u64).checked_sub(1);
+        find_common_height(
+            max_height,
+            |height| Ok(local[height as usize].to_string()),
+            |height| Ok(other[height as usize].to_string()),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn common_ancestor() {
+        assert_eq!(
+            Some(1),
+            common_height(&["a", "b", "c"], &["a", "b", "d", "e"])
+        );
+        assert_eq!(
+            Some(2),
+            common_height(&["a", "b", "c"], &["a", "b", "c", "d"])
+        );
+        assert_eq!(Some(0), common_height(&["a", "b"], &["a", "c"]));
+        assert_eq!(None, common_height(&["a"], &["b", "c"]));
+        assert_eq!(None, common_height(&[], &["a"]));
+    }
+
     #[test]
     fn backup_and_restore() {
         let mut buffer: Vec<u8> = vec![];
@@ -485,4 +2207,172 @@ mod tests {
 
         assert_eq!(None, restore_block(&mut is).unwrap());
     }
+
+    /// Creates a chain of COUNT blocks, starting with the genesis block.
+    fn create_chain(count: u64) -> Vec<Block> {
+        let mut previous_block_id = String::from(NULL_BLOCK_IDENTIFIER);
+        (0..count)
+            .map(|block_num| {
+                let mut block_header = BlockHeader::new();
+                block_header.set_block_num(block_num);
+                block_header.set_previous_block_id(previous_block_id.clone());
+                let mut block = Block::new();
+                block.set_header(block_header.write_to_bytes().unwrap());
+                block.set_header_signature(format!("block-{}", block_num));
+                previous_block_id = block.header_signature.clone();
+                block
+            })
+            .collect()
+    }
+
+    /// Writes BLOCKS, starting with the genesis block, as a backup at PATH.
+    fn write_backup_file(path: &Path, blocks: &[Block]) {
+        let header = BackupHeader {
+            format_version: BACKUP_FORMAT_VERSION,
+            chain_id: blocks[0].header_signature.clone(),
+            head_block_id: blocks[blocks.len() - 1].header_signature.clone(),
+            first_block_num: 0,
+            last_block_num: blocks.len() as u64 - 1,
+            compression: Compression::None,
+        };
+        let mut writer = BackupWriter::new(File::create(path).unwrap(), &header).unwrap();
+        for block in blocks {
+            writer.write_block(block).unwrap();
+        }
+        writer.finish().unwrap();
+    }
+
+    /// Asserts that restoring a backup rejects height ranges outside of the
+    /// backup, and ranges that do not start at genesis when the blockstore is
+    /// empty, and that a backup can be restored in segments.
+    #[test]
+    fn restore_height_range() {
+        let temp_dir = env::temp_dir();
+        let backup_path = temp_dir.join(format!("sawadm-restore-{}.backup", process::id()));
+        let blockstore_path = temp_dir.join(format!("sawadm-restore-{}.lmdb", process::id()));
+        write_backup_file(&backup_path, &create_chain(3));
+
+        {
+            let ctx = lmdb::LmdbContext::new(&blockstore_path, 3, Some(10 * 1024 * 1024)).unwrap();
+            let blockstore = Blockstore::new(
+                lmdb::LmdbDatabase::new(
+                    &ctx,
+                    &["index_batch", "index_transaction", "index_block_num"],
+                )
+                .unwrap(),
+            );
+            let restore = |from_height, to_height| {
+                restore_backup(
+                    &blockstore,
+                    File::open(&backup_path).unwrap(),
+                    from_height,
+                    to_height,
+                )
+            };
+
+            for &(from_height, to_height) in &[
+                (Some(2), Some(1)),
+                (None, Some(3)),
+                (Some(4), None),
+                // Block 1 can not be restored without the blocks before it
+                (Some(1), None),
+            ] {
+                match restore(from_height, to_height) {
+                    Err(CliError::ArgumentError(_)) => (),
+                    res => panic!(
+                        "Expected an argument error for heights {:?} to {:?}, got {:?}",
+                        from_height, to_height, res
+                    ),
+                }
+            }
+            assert!(blockstore.get_chain_head().is_err());
+
+            restore(None, Some(1)).unwrap();
+            assert_eq!("block-1", blockstore.get_chain_head().unwrap());
+            restore(Some(2), None).unwrap();
+            assert_eq!("block-2", blockstore.get_chain_head().unwrap());
+        }
+
+        fs::remove_file(&backup_path).unwrap();
+        fs::remove_file(&blockstore_path).unwrap();
+        let _ = fs::remove_file(format!("{}-lock", blockstore_path.display()));
+    }
+
+    /// Asserts that a chain restored from a snapshot, which has no blocks
+    /// below the snapshot's block, can be backed up and restored, and that
+    /// blocks of the full chain can be added to it.
+    #[test]
+    fn backup_snapshot_chain() {
+        let temp_dir = env::temp_dir();
+        let backup_path = temp_dir.join(format!("sawadm-snapshot-{}.backup", process::id()));
+        let full_backup_path =
+            temp_dir.join(format!("sawadm-snapshot-{}-full.backup", process::id()));
+        let source_path = temp_dir.join(format!("sawadm-snapshot-{}.lmdb", process::id()));
+        let target_path = temp_dir.join(format!("sawadm-snapshot-{}-restored.lmdb", process::id()));
+        let chain = create_chain(6);
+        write_backup_file(&full_backup_path, &chain);
+
+        {
+            let source_ctx =
+                lmdb::LmdbContext::new(&source_path, 3, Some(10 * 1024 * 1024)).unwrap();
+            let source = Blockstore::new(
+                lmdb::LmdbDatabase::new(
+                    &source_ctx,
+                    &["index_batch", "index_transaction", "index_block_num"],
+                )
+                .unwrap(),
+            );
+            // As restored from a snapshot of block 2, and then extended
+            for block in &chain[2..5] {
+                source.put(block).unwrap();
+            }
+            assert_eq!("block-2", source.get_chain_base().unwrap());
+
+            match write_backup(&source, &backup_path, Some(1), None, Compression::None) {
+                Err(CliError::ArgumentError(_)) => (),
+                res => panic!("Expected an argument error, got {:?}", res),
+            }
+            let header =
+                write_backup(&source, &backup_path, None, None, Compression::None).unwrap();
+            assert_eq!("block-2", header.chain_id);
+            assert_eq!((2, 4), (header.first_block_num, header.last_block_num));
+
+            let target_ctx =
+                lmdb::LmdbContext::new(&target_path, 3, Some(10 * 1024 * 1024)).unwrap();
+            let target = Blockstore::new(
+                lmdb::LmdbDatabase::new(
+                    &target_ctx,
+                    &["index_batch", "index_transaction", "index_block_num"],
+                )
+                .unwrap(),
+            );
+            // The chain can only start at its lowest block
+            match restore_backup(&target, File::open(&backup_path).unwrap(), Some(3), None) {
+                Err(CliError::ArgumentError(_)) => (),
+                res => panic!("Expected an argument error, got {:?}", res),
+            }
+            restore_backup(&target, File::open(&backup_path).unwrap(), None, None).unwrap();
+            assert_eq!("block-2", target.get_chain_base().unwrap());
+            assert_eq!("block-4", target.get_chain_head().unwrap());
+
+            // A backup of the full chain, which has a different chain id,
+            // extends the restored chain
+            restore_backup(
+                &target,
+                File::open(&full_backup_path).unwrap(),
+                Some(5),
+                None,
+            )
+            .unwrap();
+            assert_eq!("block-5", target.get_chain_head().unwrap());
+        }
+
+        for path in &[backup_path, full_backup_path] {
+            fs::remove_file(path).unwrap();
+        }
+        for path in &[source_path, target_path] {
+            fs::remove_file(path).unwrap();
+            let _ = fs::remove_file(format!("{}-lock", path.display()));
+        }
+    }
 }
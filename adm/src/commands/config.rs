@@ -0,0 +1,306 @@
+/*
+ * Copyright 2018 Intel Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * ------------------------------------------------------------------------------
+ */
+
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clap::ArgMatches;
+use serde_yaml;
+use toml;
+use toml::value::{Table, Value};
+
+use config;
+use config::PathConfig;
+use config_schema;
+use config_schema::{ConfigProblem, Severity};
+use err::CliError;
+
+pub fn run<'a>(args: &ArgMatches<'a>) -> Result<(), CliError> {
+    match args.subcommand() {
+        ("validate", Some(args)) => run_validate_command(args),
+        _ => {
+            println!("Invalid subcommand; Pass --help for usage.");
+            Ok(())
+        }
+    }
+}
+
+/// The problems found in each file, counted by severity.
+#[derive(Default)]
+struct Report {
+    errors: usize,
+    warnings: usize,
+}
+
+impl Report {
+    fn add(&mut self, path: &Path, problems: &[ConfigProblem]) {
+        for problem in problems {
+            match problem.severity {
+                Severity::Error => self.errors += 1,
+                Severity::Warning => self.warnings += 1,
+            }
+            println!("{}: {}", path.display(), problem);
+        }
+    }
+}
+
+fn run_validate_command<'a>(args: &ArgMatches<'a>) -> Result<(), CliError> {
+    let config_dir = match args.value_of("config_dir") {
+        Some(config_dir) => PathBuf::from(config_dir),
+        None => config::get_default_config_dir(),
+    };
+    let mut report = Report::default();
+
+    let path_config_path = config_dir.join(config::get_path_config_filename());
+    let path_config = match load_config(&path_config_path, parse_toml) {
+        Ok(Some(file)) => {
+            let problems = config_schema::check_path_config(&file);
+            report.add(&path_config_path, &problems);
+            // The directories can only be checked once path.toml loads
+            if problems.is_empty() {
+                config::load_path_config(&config_dir).ok()
+            } else {
+                None
+            }
+        }
+        Ok(None) => config::load_path_config(&config_dir).ok(),
+        Err(problem) => {
+            report.add(&path_config_path, &[problem]);
+            None
+        }
+    };
+    if let Some(ref path_config) = path_config {
+        report.add(&path_config_path, &check_directories(path_config));
+    }
+
+    let validator_config_path = config_dir.join(config::get_validator_config_filename());
+    let file = match load_config(&validator_config_path, parse_toml) {
+        Ok(file) => file.unwrap_or_default(),
+        Err(problem) => {
+            report.add(&validator_config_path, &[problem]);
+            Table::new()
+        }
+    };
+    let mut problems = config_schema::check_validator_config(&file, &get_flags(args));
+    if let Some(ref path_config) = path_config {
+        problems.extend(check_policies(&file, &path_config.policy_dir));
+    }
+    report.add(&validator_config_path, &problems);
+
+    // The validator looks for its log config in the default config directory,
+    // even if it is started with --config-dir
+    let log_config_dir = config::get_default_config_dir();
+    let log_config_paths: Vec<PathBuf> = config::get_log_config_filenames()
+        .iter()
+        .map(|filename| log_config_dir.join(filename))
+        .filter(|path| path.exists())
+        .collect();
+    if let Some(log_config_path) = log_config_paths.first() {
+        let parse = if log_config_path.extension() == Some(OsStr::new("yaml")) {
+            parse_yaml
+        } else {
+            parse_toml
+        };
+        match load_config(log_config_path, parse) {
+            Ok(Some(log_config)) => report.add(
+                log_config_path,
+                &config_schema::check_log_config(&log_config),
+            ),
+            Ok(None) => (),
+            Err(problem) => report.add(log_config_path, &[problem]),
+        }
+    }
+    for ignored_path in log_config_paths.iter().skip(1) {
+        report.add(
+            ignored_path,
+            &[ConfigProblem::warning(
+                "",
+                format!("is ignored, since {:?} exists", log_config_paths[0]),
+            )],
+        );
+    }
+
+    println!(
+        "Checked {:?}: {} error(s), {} warning(s)",
+        config_dir, report.errors, report.warnings
+    );
+
+    if report.errors == 0 {
+        Ok(())
+    } else {
+        Err(CliError::EnvironmentError(format!(
+            "configuration validation failed with {} error(s)",
+            report.errors
+        )))
+    }
+}
+
+/// Returns the options set by the validator command line flags in ARGS, keyed
+/// as they are in validator.toml, and parsed as the validator parses them.
+fn get_flags<'a>(args: &ArgMatches<'a>) -> Table {
+    let mut flags = Table::new();
+
+    if let Some(bindings) = args.values_of("bind") {
+        flags.insert(
+            "bind".into(),
+            Value::Array(
+                bindings
+                    .map(|binding| Value::String(binding.into()))
+                    .collect(),
+            ),
+        );
+    }
+
+    for key in &[
+        "endpoint",
+        "peering",
+        "scheduler",
+        "opentsdb_url",
+        "opentsdb_db",
+    ] {
+        if let Some(value) = args.value_of(key) {
+            flags.insert(key.to_string(), Value::String(value.into()));
+        }
+    }
+
+    // Both comma-separated lists and repeated flags are accepted
+    for key in &["seeds", "peers"] {
+        if let Some(values) = args.values_of(key) {
+            let values = values.collect::<Vec<_>>().join(",");
+            flags.insert(
+                key.to_string(),
+                Value::Array(
+                    values
+                        .split(',')
+                        .map(|value| Value::String(value.into()))
+                        .collect(),
+                ),
+            );
+        }
+    }
+
+    for key in &[
+        "minimum_peer_connectivity",
+        "maximum_peer_connectivity",
+        "state_pruning_block_depth",
+        "fork_cache_keep_time",
+    ] {
+        if let Some(value) = args.value_of(key) {
+            // A value that is not an integer is kept as a string, so that it
+            // is reported along with the other problems
+            let value = value
+                .parse()
+                .map(Value::Integer)
+                .unwrap_or_else(|_| Value::String(value.into()));
+            flags.insert(key.to_string(), value);
+        }
+    }
+
+    if let Some(network_auth) = args.value_of("network_auth") {
+        let mut roles = Table::new();
+        roles.insert("network".into(), Value::String(network_auth.into()));
+        flags.insert("roles".into(), Value::Table(roles));
+    }
+
+    flags
+}
+
+/// Checks the directories the validator needs when it starts.
+fn check_directories(path_config: &PathConfig) -> Vec<ConfigProblem> {
+    let mut problems = vec![];
+    for (key, dir) in &[
+        ("data_dir", &path_config.data_dir),
+        ("log_dir", &path_config.log_dir),
+    ] {
+        if !dir.is_dir() {
+            problems.push(ConfigProblem::error(
+                key,
+                format!("the directory {:?} does not exist", dir),
+            ));
+        }
+    }
+
+    let key_path = path_config.key_dir.join("validator.priv");
+    if !key_path.exists() {
+        problems.push(ConfigProblem::error(
+            "key_dir",
+            format!(
+                "the validator key {:?} does not exist; create it with 'sawadm keygen'",
+                key_path
+            ),
+        ));
+    }
+    problems
+}
+
+/// Checks that the policy files named in the permissions of a validator.toml,
+/// given as FILE, exist in POLICY_DIR.
+fn check_policies(file: &Table, policy_dir: &Path) -> Vec<ConfigProblem> {
+    let permissions = match file.get("permissions").and_then(Value::as_table) {
+        Some(permissions) => permissions,
+        None => return vec![],
+    };
+
+    permissions
+        .iter()
+        .filter_map(|(role, policy)| {
+            let policy_path = policy_dir.join(policy.as_str()?);
+            if policy_path.exists() {
+                None
+            } else {
+                // The validator only logs a warning, and leaves the role unset
+                Some(ConfigProblem::warning(
+                    &format!("permissions.{}", role),
+                    format!(
+                        "the policy {:?} does not exist, so the role will not be set",
+                        policy_path
+                    ),
+                ))
+            }
+        })
+        .collect()
+}
+
+/// Loads the config at PATH with PARSE, returning None if it does not exist,
+/// as the validator then uses its defaults.
+fn load_config(
+    path: &Path,
+    parse: fn(&str) -> Result<Table, String>,
+) -> Result<Option<Table>, ConfigProblem> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let raw_config = fs::read_to_string(path)
+        .map_err(|err| ConfigProblem::error("", format!("unable to read the file: {}", err)))?;
+    parse(&raw_config)
+        .map(Some)
+        .map_err(|err| ConfigProblem::error("", err))
+}
+
+fn parse_toml(raw_config: &str) -> Result<Table, String> {
+    toml::from_str(raw_config).map_err(|err| format!("invalid TOML: {}", err))
+}
+
+fn parse_yaml(raw_config: &str) -> Result<Table, String> {
+    match serde_yaml::from_str(raw_config) {
+        Ok(Value::Table(table)) => Ok(table),
+        Ok(value) => Err(format!("expected a mapping, found {}", value.type_str())),
+        Err(err) => Err(format!("invalid YAML: {}", err)),
+    }
+}
@@ -15,6 +15,8 @@
  * ------------------------------------------------------------------------------
  */
 
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
 use std::fs::{File, OpenOptions};
 use std::io::{Read, Write};
 use std::os::unix::fs::OpenOptionsExt;
@@ -23,36 +25,68 @@ use std::path::Path;
 use clap::ArgMatches;
 use protobuf;
 use protobuf::Message;
+use sawtooth_sdk::signing;
 
-use proto::batch::{Batch, BatchList};
+use proto::batch::{Batch, BatchHeader, BatchList};
 use proto::genesis::GenesisData;
-use proto::settings::{SettingProposal, SettingsPayload, SettingsPayload_Action};
-use proto::transaction::TransactionHeader;
+use proto::settings::{SettingProposal, SettingVote, SettingsPayload, SettingsPayload_Action};
+use proto::transaction::{Transaction, TransactionHeader};
 
+#[cfg(feature = "simulate")]
+use commands::state::print_entry;
 use config;
 use err::CliError;
+use network_spec::NetworkSpec;
+#[cfg(feature = "simulate")]
+use simulator;
+use transactions::read_private_key;
 
 pub fn run<'a>(args: &ArgMatches<'a>) -> Result<(), CliError> {
+    if let ("inspect", Some(args)) = args.subcommand() {
+        return run_inspect_command(args);
+    }
+
     let genesis_file_path = if args.is_present("output") {
         args.value_of("output")
             .ok_or_else(|| CliError::ArgumentError("Failed to read `output` arg".into()))
             .map(|pathstr| Path::new(pathstr).to_path_buf())
     } else {
-        Ok(config::get_path_config().data_dir.join("genesis.batch"))
+        config::get_path_config().map(|path_config| path_config.data_dir.join("genesis.batch"))
     }?;
 
-    if genesis_file_path.exists() {
+    if !args.is_present("simulate") && genesis_file_path.exists() {
         return Err(CliError::EnvironmentError(format!(
             "File already exists: {:?}",
             genesis_file_path
         )));
     }
 
-    let input_files = args
-        .values_of("input_file")
-        .ok_or_else(|| CliError::ArgumentError("No input files passed".into()))?;
+    // Batches described by a spec come before those from the input files
+    let mut batches = match args.value_of("spec") {
+        Some(spec_path) => {
+            let spec = NetworkSpec::load(Path::new(spec_path))?;
+            let key_path = match args.value_of("key") {
+                Some(key_path) => Path::new(key_path).to_path_buf(),
+                None => config::get_path_config()?.key_dir.join("validator.priv"),
+            };
+            let private_key = read_private_key(&key_path)?;
+            let context = signing::create_context("secp256k1")
+                .map_err(|err| CliError::EnvironmentError(format!("{}", err)))?;
+            let factory = signing::CryptoFactory::new(&*context);
+            let signer = factory.new_signer(&private_key);
+            spec.build_batches(&signer)?
+        }
+        None => vec![],
+    };
+
+    let input_files = match args.values_of("input_file") {
+        Some(input_files) => input_files.collect(),
+        None if !batches.is_empty() => vec![],
+        None => return Err(CliError::ArgumentError("No input files passed".into())),
+    };
 
     let batch_lists = input_files
+        .into_iter()
         .map(|filepath| {
             let mut file = File::open(filepath).map_err(|err| {
                 CliError::EnvironmentError(format!("Failed to open file: {}", err))
@@ -68,18 +102,23 @@ pub fn run<'a>(args: &ArgMatches<'a>) -> Result<(), CliError> {
         })
         .collect::<Result<Vec<BatchList>, CliError>>()?;
 
-    let batches = batch_lists
-        .into_iter()
-        .fold(Vec::new(), |mut batches, batch_list| {
-            batches.extend(batch_list.batches.into_iter());
-            batches
-        });
+    for batch_list in batch_lists {
+        batches.extend(batch_list.batches.into_iter());
+    }
+
+    if args.is_present("reorder") {
+        batches = reorder_batches(batches)?;
+    }
 
-    validate_depedencies(&batches)?;
+    validate_dependencies(&batches)?;
     if !args.is_present("ignore_required_settings") {
         check_required_settings(&batches)?;
     }
 
+    if args.is_present("simulate") {
+        return simulate(&batches);
+    }
+
     let mut genesis_data = GenesisData::new();
     genesis_data.set_batches(protobuf::RepeatedField::from_vec(batches));
 
@@ -104,9 +143,158 @@ pub fn run<'a>(args: &ArgMatches<'a>) -> Result<(), CliError> {
     Ok(())
 }
 
-fn validate_depedencies(batches: &[Batch]) -> Result<(), CliError> {
-    let mut txn_ids: Vec<String> = Vec::new();
-    for batch in batches.iter() {
+fn run_inspect_command<'a>(args: &ArgMatches<'a>) -> Result<(), CliError> {
+    let filepath = args
+        .value_of("genesis_file")
+        .ok_or_else(|| CliError::ArgumentError("No genesis file".into()))?;
+    let mut file = File::open(filepath)
+        .map_err(|err| CliError::EnvironmentError(format!("Failed to open file: {}", err)))?;
+    let mut packed = Vec::new();
+    file.read_to_end(&mut packed)
+        .map_err(|err| CliError::EnvironmentError(format!("Failed to read file: {}", err)))?;
+    let genesis_data: GenesisData = protobuf::parse_from_bytes(&packed)
+        .map_err(|err| CliError::ArgumentError(format!("Unable to read {}: {}", filepath, err)))?;
+
+    for batch in genesis_data.get_batches() {
+        print_batch(batch)?;
+    }
+
+    // Apply the same checks as when the file is created
+    let batches = genesis_data.get_batches();
+    let mut failed = false;
+    for (check, result) in &[
+        ("Dependencies", validate_dependencies(batches)),
+        ("Required settings", check_required_settings(batches)),
+    ] {
+        match result {
+            Ok(()) => println!("{}: ok", check),
+            Err(err) => {
+                println!("{}: failed: {}", check, err);
+                failed = true;
+            }
+        }
+    }
+
+    if failed {
+        Err(CliError::ArgumentError(format!(
+            "{} failed the genesis checks",
+            filepath
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+fn print_batch(batch: &Batch) -> Result<(), CliError> {
+    let batch_header: BatchHeader = protobuf::parse_from_bytes(&batch.header).map_err(|err| {
+        CliError::ArgumentError(format!(
+            "Invalid batch header for batch {}: {}",
+            &batch.header_signature, err
+        ))
+    })?;
+    println!("Batch {}", batch.header_signature);
+    println!("  signer: {}", batch_header.signer_public_key);
+
+    for txn in batch.get_transactions() {
+        let txn_header: TransactionHeader =
+            protobuf::parse_from_bytes(&txn.header).map_err(|err| {
+                CliError::ArgumentError(format!(
+                    "Invalid transaction header for txn {}: {}",
+                    &txn.header_signature, err
+                ))
+            })?;
+        println!("  Transaction {}", txn.header_signature);
+        println!(
+            "    family: {} {}",
+            txn_header.family_name, txn_header.family_version
+        );
+        println!("    signer: {}", txn_header.signer_public_key);
+        println!("    inputs: {}", txn_header.inputs.join(", "));
+        println!("    outputs: {}", txn_header.outputs.join(", "));
+        println!("    dependencies: {}", txn_header.dependencies.join(", "));
+        if txn_header.family_name == "sawtooth_settings" {
+            println!("    {}", describe_settings_payload(txn)?);
+        }
+    }
+    Ok(())
+}
+
+/// Applies BATCHES in-process and prints each address of the resulting state
+/// with its value. Settings are decoded; other values are printed as hex.
+#[cfg(feature = "simulate")]
+fn simulate(batches: &[Batch]) -> Result<(), CliError> {
+    for (address, value) in simulator::simulate(batches)?.iter() {
+        print_entry(address, value)?;
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "simulate"))]
+fn simulate(_batches: &[Batch]) -> Result<(), CliError> {
+    Err(CliError::EnvironmentError(
+        "--simulate requires sawadm to be built with the simulate feature".into(),
+    ))
+}
+
+/// Describes the proposal or vote in a sawtooth_settings transaction.
+fn describe_settings_payload(txn: &Transaction) -> Result<String, CliError> {
+    let settings_payload: SettingsPayload =
+        protobuf::parse_from_bytes(&txn.payload).map_err(|err| {
+            CliError::ArgumentError(format!(
+                "Invalid payload for settings txn {}: {}",
+                &txn.header_signature, err
+            ))
+        })?;
+    match settings_payload.action {
+        SettingsPayload_Action::PROPOSE => {
+            let proposal: SettingProposal = protobuf::parse_from_bytes(&settings_payload.data)
+                .map_err(|err| {
+                    CliError::ArgumentError(format!(
+                        "Invalid proposal for settings payload: {}",
+                        err
+                    ))
+                })?;
+            Ok(format!(
+                "proposal: {} = {:?}",
+                proposal.setting, proposal.value
+            ))
+        }
+        SettingsPayload_Action::VOTE => {
+            let vote: SettingVote =
+                protobuf::parse_from_bytes(&settings_payload.data).map_err(|err| {
+                    CliError::ArgumentError(format!("Invalid vote for settings payload: {}", err))
+                })?;
+            Ok(format!("vote: {:?} {}", vote.vote, vote.proposal_id))
+        }
+        SettingsPayload_Action::ACTION_UNSET => Ok("action: unset".into()),
+    }
+}
+
+/// Checks that the transaction dependencies in BATCHES form a DAG, in which
+/// every dependency is satisfied by an earlier transaction. Every problem
+/// found is reported, rather than only the first.
+fn validate_dependencies(batches: &[Batch]) -> Result<(), CliError> {
+    let problems = find_dependency_problems(batches)?;
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(CliError::ArgumentError(format!(
+            "Invalid transaction dependencies:\n  {}",
+            problems.join("\n  ")
+        )))
+    }
+}
+
+/// The id and dependencies of a transaction, and the index of its batch.
+struct TxnNode {
+    id: String,
+    batch_index: usize,
+    dependencies: Vec<String>,
+}
+
+fn get_txn_nodes(batches: &[Batch]) -> Result<Vec<TxnNode>, CliError> {
+    let mut txns = vec![];
+    for (batch_index, batch) in batches.iter().enumerate() {
         for txn in batch.transactions.iter() {
             let header: TransactionHeader =
                 protobuf::parse_from_bytes(&txn.header).map_err(|err| {
@@ -115,18 +303,177 @@ fn validate_depedencies(batches: &[Batch]) -> Result<(), CliError> {
                         &txn.header_signature, err
                     ))
                 })?;
-            for dep in header.dependencies.iter() {
-                if !txn_ids.contains(dep) {
-                    return Err(CliError::ArgumentError(format!(
-                        "Unsatisfied dependency in given transaction {}: {}",
-                        &txn.header_signature, dep
-                    )));
+            txns.push(TxnNode {
+                id: txn.header_signature.clone(),
+                batch_index,
+                dependencies: header.dependencies.into_vec(),
+            });
+        }
+    }
+    Ok(txns)
+}
+
+fn find_dependency_problems(batches: &[Batch]) -> Result<Vec<String>, CliError> {
+    let mut problems = vec![];
+
+    let mut batch_ids = HashSet::new();
+    for batch in batches.iter() {
+        if !batch_ids.insert(batch.header_signature.as_str()) {
+            problems.push(format!("Duplicate batch id: {}", batch.header_signature));
+        }
+    }
+
+    // The position of each transaction id, keeping the first of duplicates
+    let txns = get_txn_nodes(batches)?;
+    let mut positions: HashMap<&str, usize> = HashMap::new();
+    for (position, txn) in txns.iter().enumerate() {
+        if positions.contains_key(txn.id.as_str()) {
+            problems.push(format!("Duplicate transaction id: {}", txn.id));
+        } else {
+            positions.insert(&txn.id, position);
+        }
+    }
+
+    let mut edges = vec![vec![]; txns.len()];
+    for (position, txn) in txns.iter().enumerate() {
+        for dep in txn.dependencies.iter() {
+            match positions.get(dep.as_str()) {
+                Some(&dep_position) => {
+                    if dep_position >= position {
+                        problems.push(format!(
+                            "Transaction {} depends on {}, which does not come before it",
+                            txn.id, dep
+                        ));
+                    }
+                    edges[position].push(dep_position);
                 }
-                txn_ids.push(dep.clone())
+                None => problems.push(format!(
+                    "Unsatisfied dependency in transaction {}: {}",
+                    txn.id, dep
+                )),
             }
         }
     }
-    Ok(())
+
+    for cycle in find_cycles(&edges) {
+        problems.push(format!(
+            "Dependency cycle: {}",
+            cycle
+                .iter()
+                .map(|&position| txns[position].id.as_str())
+                .collect::<Vec<_>>()
+                .join(" -> ")
+        ));
+    }
+
+    Ok(problems)
+}
+
+/// Finds cycles in a graph, given the edges from each node, with a
+/// depth-first search. Each cycle is listed from a node back to itself.
+fn find_cycles(edges: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    #[derive(Clone, Copy, PartialEq)]
+    enum Mark {
+        New,
+        Active,
+        Done,
+    }
+
+    let mut marks = vec![Mark::New; edges.len()];
+    let mut cycles = vec![];
+    for root in 0..edges.len() {
+        if marks[root] != Mark::New {
+            continue;
+        }
+        // The path from the root, with the next edge to follow from each node
+        let mut path = vec![(root, 0)];
+        marks[root] = Mark::Active;
+        while let Some(&(node, next)) = path.last() {
+            if next == edges[node].len() {
+                marks[node] = Mark::Done;
+                path.pop();
+                continue;
+            }
+            if let Some(last) = path.last_mut() {
+                last.1 += 1;
+            }
+            let child = edges[node][next];
+            match marks[child] {
+                Mark::New => {
+                    marks[child] = Mark::Active;
+                    path.push((child, 0));
+                }
+                Mark::Active => {
+                    let start = path.iter().position(|&(n, _)| n == child).unwrap_or(0);
+                    let mut cycle: Vec<usize> = path[start..].iter().map(|&(n, _)| n).collect();
+                    cycle.push(child);
+                    cycles.push(cycle);
+                }
+                Mark::Done => (),
+            }
+        }
+    }
+    cycles
+}
+
+/// Sorts BATCHES so that every dependency comes before the transactions that
+/// depend on it, keeping the given order where possible. Batches are moved
+/// whole, since the transactions in a batch cannot be reordered without
+/// signing it again.
+fn reorder_batches(batches: Vec<Batch>) -> Result<Vec<Batch>, CliError> {
+    let txns = get_txn_nodes(&batches)?;
+    let mut batch_indexes: HashMap<&str, usize> = HashMap::new();
+    for txn in txns.iter() {
+        batch_indexes.entry(&txn.id).or_insert(txn.batch_index);
+    }
+
+    // Unsatisfied dependencies are left for validation to report
+    let mut dependents: Vec<Vec<usize>> = vec![vec![]; batches.len()];
+    let mut dependency_counts = vec![0; batches.len()];
+    for txn in txns.iter() {
+        for dep in txn.dependencies.iter() {
+            if let Some(&dep_index) = batch_indexes.get(dep.as_str()) {
+                if dep_index != txn.batch_index && !dependents[dep_index].contains(&txn.batch_index)
+                {
+                    dependents[dep_index].push(txn.batch_index);
+                    dependency_counts[txn.batch_index] += 1;
+                }
+            }
+        }
+    }
+
+    // Kahn's algorithm, always taking the earliest batch that is ready
+    let mut ready: BinaryHeap<Reverse<usize>> = (0..batches.len())
+        .filter(|&index| dependency_counts[index] == 0)
+        .map(Reverse)
+        .collect();
+    let mut order = Vec::with_capacity(batches.len());
+    while let Some(Reverse(index)) = ready.pop() {
+        order.push(index);
+        for &dependent in dependents[index].iter() {
+            dependency_counts[dependent] -= 1;
+            if dependency_counts[dependent] == 0 {
+                ready.push(Reverse(dependent));
+            }
+        }
+    }
+
+    if order.len() < batches.len() {
+        let unordered = (0..batches.len())
+            .filter(|&index| dependency_counts[index] > 0)
+            .map(|index| batches[index].header_signature.as_str())
+            .collect::<Vec<_>>();
+        return Err(CliError::ArgumentError(format!(
+            "Unable to reorder batches; these batches are in or depend on a dependency cycle: {}",
+            unordered.join(", ")
+        )));
+    }
+
+    let mut batches: Vec<Option<Batch>> = batches.into_iter().map(Some).collect();
+    Ok(order
+        .into_iter()
+        .filter_map(|index| batches[index].take())
+        .collect())
 }
 
 fn check_required_settings(batches: &[Batch]) -> Result<(), CliError> {
@@ -183,8 +530,7 @@ mod tests {
 
     use protobuf::RepeatedField;
 
-    use proto::batch::BatchHeader;
-    use proto::transaction::Transaction;
+    use proto::settings::SettingVote_Vote;
 
     fn get_required_settings_batch() -> Batch {
         let required_settings = vec![
@@ -238,6 +584,124 @@ mod tests {
         batch
     }
 
+    #[test]
+    fn test_describe_settings_payload() {
+        let batch = get_required_settings_batch();
+        assert_eq!(
+            "proposal: sawtooth.consensus.algorithm.name = \"\"",
+            describe_settings_payload(&batch.transactions[0]).unwrap()
+        );
+
+        let mut vote = SettingVote::new();
+        vote.set_proposal_id("proposal".into());
+        vote.set_vote(SettingVote_Vote::ACCEPT);
+        let mut payload = SettingsPayload::new();
+        payload.set_action(SettingsPayload_Action::VOTE);
+        payload.set_data(vote.write_to_bytes().unwrap());
+        let mut txn = Transaction::new();
+        txn.set_payload(payload.write_to_bytes().unwrap());
+        assert_eq!(
+            "vote: ACCEPT proposal",
+            describe_settings_payload(&txn).unwrap()
+        );
+    }
+
+    /// Creates a batch BATCH_ID of transactions with the given ids and
+    /// dependencies.
+    fn create_batch_with_dependencies(batch_id: &str, txns: &[(&str, &[&str])]) -> Batch {
+        let txns = txns
+            .iter()
+            .map(|&(txn_id, deps)| {
+                let mut header = TransactionHeader::new();
+                header.set_dependencies(RepeatedField::from_vec(
+                    deps.iter().map(|dep| dep.to_string()).collect(),
+                ));
+                let mut txn = Transaction::new();
+                txn.set_header(header.write_to_bytes().expect("Failed to serialize header"));
+                txn.set_header_signature(txn_id.into());
+                txn
+            })
+            .collect();
+
+        let mut batch = Batch::new();
+        batch.set_header_signature(batch_id.into());
+        batch.set_transactions(RepeatedField::from_vec(txns));
+        batch
+    }
+
+    fn get_batch_ids(batches: &[Batch]) -> Vec<&str> {
+        batches
+            .iter()
+            .map(|batch| batch.header_signature.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_validate_dependencies() {
+        let batches = vec![
+            create_batch_with_dependencies("b0", &[("t0", &[]), ("t1", &["t0"])]),
+            create_batch_with_dependencies("b1", &[("t2", &["t0", "t1"])]),
+        ];
+        assert!(validate_dependencies(&batches).is_ok());
+
+        // Every problem is found, not only the first
+        let batches = vec![
+            create_batch_with_dependencies("b0", &[("t0", &["t1"]), ("t1", &["t0"])]),
+            create_batch_with_dependencies("b0", &[("t1", &[]), ("t2", &["missing"])]),
+        ];
+        assert!(validate_dependencies(&batches).is_err());
+        assert_eq!(
+            find_dependency_problems(&batches).unwrap(),
+            vec![
+                "Duplicate batch id: b0",
+                "Duplicate transaction id: t1",
+                "Transaction t0 depends on t1, which does not come before it",
+                "Unsatisfied dependency in transaction t2: missing",
+                "Dependency cycle: t0 -> t1 -> t0",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_find_cycles() {
+        assert!(find_cycles(&[vec![], vec![0], vec![0, 1]]).is_empty());
+        assert_eq!(vec![vec![0, 0]], find_cycles(&[vec![0]]));
+        assert_eq!(
+            vec![vec![1, 2, 3, 1]],
+            find_cycles(&[vec![1], vec![2], vec![3], vec![1]])
+        );
+    }
+
+    #[test]
+    fn test_reorder_batches() {
+        let batches = vec![
+            create_batch_with_dependencies("b0", &[("t0", &["t2"])]),
+            create_batch_with_dependencies("b1", &[("t1", &[])]),
+            create_batch_with_dependencies("b2", &[("t2", &["t3"])]),
+            create_batch_with_dependencies("b3", &[("t3", &[])]),
+        ];
+        let batches = reorder_batches(batches).unwrap();
+        assert_eq!(vec!["b1", "b3", "b2", "b0"], get_batch_ids(&batches));
+        assert!(validate_dependencies(&batches).is_ok());
+
+        // Dependencies within a batch do not constrain the order
+        let batches = vec![
+            create_batch_with_dependencies("b0", &[("t0", &[]), ("t1", &["t0"])]),
+            create_batch_with_dependencies("b1", &[("t2", &[])]),
+        ];
+        assert_eq!(
+            vec!["b0", "b1"],
+            get_batch_ids(&reorder_batches(batches).unwrap())
+        );
+
+        let batches = vec![
+            create_batch_with_dependencies("b0", &[("t0", &["t1"])]),
+            create_batch_with_dependencies("b1", &[("t1", &["t0"])]),
+            create_batch_with_dependencies("b2", &[("t2", &[])]),
+        ];
+        assert!(reorder_batches(batches).is_err());
+    }
+
     #[test]
     fn test_check_required_settings() {
         assert!(check_required_settings(&[get_required_settings_batch()]).is_ok());
@@ -0,0 +1,332 @@
+/*
+ * Copyright 2018 Intel Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * ------------------------------------------------------------------------------
+ */
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+
+use clap::ArgMatches;
+use protobuf;
+use protobuf::Message;
+use sawtooth_sdk::signing;
+use sawtooth_sdk::signing::secp256k1::Secp256k1PrivateKey;
+
+use proto::batch::BatchList;
+
+use commands::keygen::{check_key_files_absent, get_key_dir, get_key_paths, write_key_files};
+use err::CliError;
+use key_file::{
+    decrypt_private_key, encrypt_private_key, get_new_passphrase, get_passphrase, is_encrypted,
+    parse_private_key, read_key_file, read_private_key_hex, replace_key_file,
+};
+use transactions::{
+    create_batch, create_setting_proposal_txn, ALLOWED_IDENTITY_KEYS_SETTING,
+    AUTHORIZED_KEYS_SETTING,
+};
+
+pub fn run<'a>(args: &ArgMatches<'a>) -> Result<(), CliError> {
+    match args.subcommand() {
+        ("show", Some(args)) => run_show_command(args),
+        ("import", Some(args)) => run_import_command(args),
+        ("rotate", Some(args)) => run_rotate_command(args),
+        ("decrypt", Some(args)) => run_decrypt_command(args),
+        ("rotate-passphrase", Some(args)) => run_rotate_passphrase_command(args),
+        _ => {
+            println!("Invalid subcommand; Pass --help for usage.");
+            Ok(())
+        }
+    }
+}
+
+fn run_show_command<'a>(args: &ArgMatches<'a>) -> Result<(), CliError> {
+    let key_dir = get_key_dir()?;
+    let (private_key_path, public_key_path) =
+        get_key_paths(&key_dir, args.value_of("key_name").unwrap_or("validator"));
+
+    let private_key_hex = read_private_key_hex(
+        &private_key_path,
+        args.value_of("passphrase_file").map(Path::new),
+    )?;
+    let public_key_hex = get_public_key_hex(&private_key_hex)?;
+    println!("{}", public_key_hex);
+
+    if public_key_path.exists() {
+        let stored_public_key = fs::read_to_string(&public_key_path).map_err(|err| {
+            CliError::EnvironmentError(format!("Unable to read {:?}: {}", public_key_path, err))
+        })?;
+        if stored_public_key.trim() != public_key_hex {
+            return Err(CliError::EnvironmentError(format!(
+                "{:?} does not match the private key; it holds {}",
+                public_key_path,
+                stored_public_key.trim()
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn run_import_command<'a>(args: &ArgMatches<'a>) -> Result<(), CliError> {
+    let key_dir = get_key_dir()?;
+    let (private_key_path, public_key_path) =
+        get_key_paths(&key_dir, args.value_of("key_name").unwrap_or("validator"));
+    if !args.is_present("force") {
+        check_key_files_absent(&private_key_path, &public_key_path)?;
+    }
+
+    let source = args
+        .value_of("key_file")
+        .ok_or_else(|| CliError::ArgumentError("No key file".into()))?;
+    let mut key = Vec::new();
+    if source == "-" {
+        io::stdin().read_to_end(&mut key)
+    } else {
+        File::open(source).and_then(|mut file| file.read_to_end(&mut key))
+    }
+    .map_err(|err| CliError::EnvironmentError(format!("Unable to read {}: {}", source, err)))?;
+
+    let private_key_hex = parse_private_key(&key)?;
+    let public_key_hex = get_public_key_hex(&private_key_hex)?;
+
+    let private_key_contents = if args.is_present("encrypt") {
+        let passphrase_file = args.value_of("passphrase_file").map(Path::new);
+        encrypt_private_key(&private_key_hex, &get_new_passphrase(passphrase_file)?)?
+    } else {
+        private_key_hex
+    };
+
+    write_key_files(
+        &key_dir,
+        &private_key_path,
+        &private_key_contents,
+        &public_key_path,
+        &public_key_hex,
+    )
+}
+
+/// Replaces the validator's key with a new one. The old key files are kept
+/// with a .old suffix, and a batch of settings proposals, signed with the old
+/// key, is written to swap the old public key for the new one on chain.
+fn run_rotate_command<'a>(args: &ArgMatches<'a>) -> Result<(), CliError> {
+    let key_dir = get_key_dir()?;
+    let (private_key_path, public_key_path) =
+        get_key_paths(&key_dir, args.value_of("key_name").unwrap_or("validator"));
+    let old_private_key_path = private_key_path.with_extension("priv.old");
+    let old_public_key_path = public_key_path.with_extension("pub.old");
+    check_key_files_absent(&old_private_key_path, &old_public_key_path)?;
+
+    let output_path = Path::new(
+        args.value_of("output")
+            .ok_or_else(|| CliError::ArgumentError("No output file".into()))?,
+    );
+    if output_path.exists() {
+        return Err(CliError::EnvironmentError(format!(
+            "File already exists: {:?}",
+            output_path
+        )));
+    }
+
+    // A new key is encrypted with the old key's passphrase
+    let contents = read_key_file(&private_key_path)?;
+    let (old_private_key_hex, passphrase) = if is_encrypted(&contents) {
+        let passphrase = get_passphrase(
+            args.value_of("passphrase_file").map(Path::new),
+            &format!("Passphrase for {}: ", private_key_path.display()),
+        )?;
+        (
+            decrypt_private_key(&contents, &passphrase)?,
+            Some(passphrase),
+        )
+    } else {
+        (contents.trim().to_string(), None)
+    };
+
+    let context = signing::create_context("secp256k1")
+        .map_err(|err| CliError::EnvironmentError(format!("{}", err)))?;
+    let old_private_key = Secp256k1PrivateKey::from_hex(&old_private_key_hex).map_err(|err| {
+        CliError::ArgumentError(format!("Invalid key in {:?}: {}", private_key_path, err))
+    })?;
+    let factory = signing::CryptoFactory::new(&*context);
+    let signer = factory.new_signer(&old_private_key);
+    let old_public_key_hex = get_public_key_hex(&old_private_key_hex)?;
+
+    let new_private_key = context
+        .new_random_private_key()
+        .map_err(|err| CliError::EnvironmentError(format!("{}", err)))?;
+    let new_public_key_hex = context
+        .get_public_key(&*new_private_key)
+        .map_err(|err| CliError::EnvironmentError(format!("{}", err)))?
+        .as_hex();
+
+    // The allowed keys are changed first, since the old key cannot propose
+    // anything once it is no longer an authorized key
+    let mut txns = vec![];
+    if let Some(allowed_keys) = args.value_of("allowed_keys") {
+        txns.push(create_setting_proposal_txn(
+            &signer,
+            ALLOWED_IDENTITY_KEYS_SETTING,
+            &swap_key(allowed_keys, &old_public_key_hex, &new_public_key_hex)?,
+        )?);
+    }
+    let authorized_keys = args
+        .value_of("authorized_keys")
+        .ok_or_else(|| CliError::ArgumentError("No authorized keys".into()))?;
+    txns.push(create_setting_proposal_txn(
+        &signer,
+        AUTHORIZED_KEYS_SETTING,
+        &swap_key(authorized_keys, &old_public_key_hex, &new_public_key_hex)?,
+    )?);
+
+    let mut batch_list = BatchList::new();
+    batch_list.set_batches(protobuf::RepeatedField::from_vec(vec![create_batch(
+        &signer, txns,
+    )?]));
+    let batch_list_bytes = batch_list
+        .write_to_bytes()
+        .map_err(|err| CliError::EnvironmentError(format!("Unable to serialize batch: {}", err)))?;
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o644)
+        .open(output_path)
+        .and_then(|mut file| file.write_all(&batch_list_bytes))
+        .map_err(|err| {
+            CliError::EnvironmentError(format!("Unable to write {:?}: {}", output_path, err))
+        })?;
+    println!("writing file: {:?}", output_path);
+
+    for (path, old_path) in &[
+        (&private_key_path, &old_private_key_path),
+        (&public_key_path, &old_public_key_path),
+    ] {
+        if path.exists() {
+            println!("moving file: {:?} to {:?}", path, old_path);
+            fs::rename(path, old_path).map_err(|err| {
+                CliError::EnvironmentError(format!("Unable to move {:?}: {}", path, err))
+            })?;
+        }
+    }
+
+    let new_private_key_contents = match passphrase {
+        Some(passphrase) => encrypt_private_key(&new_private_key.as_hex(), &passphrase)?,
+        None => new_private_key.as_hex(),
+    };
+    write_key_files(
+        &key_dir,
+        &private_key_path,
+        &new_private_key_contents,
+        &public_key_path,
+        &new_public_key_hex,
+    )
+}
+
+/// Replaces OLD_KEY with NEW_KEY in the comma-separated list KEYS.
+fn swap_key(keys: &str, old_key: &str, new_key: &str) -> Result<String, CliError> {
+    let keys = keys
+        .split(',')
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+        .collect::<Vec<_>>();
+    if !keys.contains(&old_key) {
+        return Err(CliError::ArgumentError(format!(
+            "The current key {} is not in the key list {}",
+            old_key,
+            keys.join(",")
+        )));
+    }
+    Ok(keys
+        .iter()
+        .map(|&key| if key == old_key { new_key } else { key })
+        .collect::<Vec<_>>()
+        .join(","))
+}
+
+fn get_public_key_hex(private_key_hex: &str) -> Result<String, CliError> {
+    let private_key = Secp256k1PrivateKey::from_hex(private_key_hex)
+        .map_err(|err| CliError::ArgumentError(format!("Invalid private key: {}", err)))?;
+    let context = signing::create_context("secp256k1")
+        .map_err(|err| CliError::EnvironmentError(format!("{}", err)))?;
+    context
+        .get_public_key(&private_key)
+        .map(|public_key| public_key.as_hex())
+        .map_err(|err| CliError::EnvironmentError(format!("{}", err)))
+}
+
+fn run_decrypt_command<'a>(args: &ArgMatches<'a>) -> Result<(), CliError> {
+    let private_key_path = get_private_key_path(args)?;
+    let contents = read_key_file(&private_key_path)?;
+    if !is_encrypted(&contents) {
+        return Err(CliError::ArgumentError(format!(
+            "Key is not encrypted: {:?}",
+            private_key_path
+        )));
+    }
+
+    let passphrase = get_passphrase(
+        args.value_of("passphrase_file").map(Path::new),
+        &format!("Passphrase for {}: ", private_key_path.display()),
+    )?;
+    let key_hex = decrypt_private_key(&contents, &passphrase)?;
+
+    replace_key_file(&private_key_path, &key_hex)?;
+    println!("decrypted file: {:?}", private_key_path);
+    Ok(())
+}
+
+fn run_rotate_passphrase_command<'a>(args: &ArgMatches<'a>) -> Result<(), CliError> {
+    let private_key_path = get_private_key_path(args)?;
+    let contents = read_key_file(&private_key_path)?;
+    if !is_encrypted(&contents) {
+        return Err(CliError::ArgumentError(format!(
+            "Key is not encrypted: {:?}",
+            private_key_path
+        )));
+    }
+
+    let passphrase = get_passphrase(
+        args.value_of("passphrase_file").map(Path::new),
+        &format!("Current passphrase for {}: ", private_key_path.display()),
+    )?;
+    let key_hex = decrypt_private_key(&contents, &passphrase)?;
+
+    let new_passphrase = get_new_passphrase(args.value_of("new_passphrase_file").map(Path::new))?;
+    replace_key_file(
+        &private_key_path,
+        &encrypt_private_key(&key_hex, &new_passphrase)?,
+    )?;
+    println!("changed passphrase: {:?}", private_key_path);
+    Ok(())
+}
+
+fn get_private_key_path<'a>(args: &ArgMatches<'a>) -> Result<PathBuf, CliError> {
+    let key_dir = get_key_dir()?;
+    let (private_key_path, _) =
+        get_key_paths(&key_dir, args.value_of("key_name").unwrap_or("validator"));
+    Ok(private_key_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn swap_keys() {
+        assert_eq!("a,new,c", swap_key("a, old ,c", "old", "new").unwrap());
+        assert_eq!("new", swap_key("old", "old", "new").unwrap());
+        assert!(swap_key("a,c", "old", "new").is_err());
+    }
+}
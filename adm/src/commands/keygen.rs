@@ -15,7 +15,6 @@
  * ------------------------------------------------------------------------------
  */
 
-use std::ffi::CString;
 use std::fs::{metadata, OpenOptions};
 use std::io::prelude::*;
 #[cfg(target_os = "linux")]
@@ -23,43 +22,23 @@ use std::os::linux::fs::MetadataExt;
 #[cfg(not(target_os = "linux"))]
 use std::os::unix::fs::MetadataExt;
 use std::os::unix::fs::OpenOptionsExt;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use clap::ArgMatches;
-use libc;
 
 use sawtooth_sdk::signing;
 
 use config;
 use err::CliError;
+use key_file::{chown, encrypt_private_key, get_new_passphrase, write_private_key_file};
 
 pub fn run<'a>(args: &ArgMatches<'a>) -> Result<(), CliError> {
-    let path_config = config::get_path_config();
-    let key_dir = &path_config.key_dir;
-    if !key_dir.exists() {
-        return Err(CliError::EnvironmentError(format!(
-            "Key directory does not exist: {:?}",
-            key_dir
-        )));
-    }
-
+    let key_dir = get_key_dir()?;
     let key_name = args.value_of("key_name").unwrap_or("validator");
-    let private_key_path = key_dir.join(key_name).with_extension("priv");
-    let public_key_path = key_dir.join(key_name).with_extension("pub");
+    let (private_key_path, public_key_path) = get_key_paths(&key_dir, key_name);
 
     if !args.is_present("force") {
-        if private_key_path.exists() {
-            return Err(CliError::EnvironmentError(format!(
-                "file exists: {:?}",
-                private_key_path
-            )));
-        }
-        if public_key_path.exists() {
-            return Err(CliError::EnvironmentError(format!(
-                "file exists: {:?}",
-                public_key_path
-            )));
-        }
+        check_key_files_absent(&private_key_path, &public_key_path)?;
     }
 
     let context = signing::create_context("secp256k1")
@@ -72,6 +51,69 @@ pub fn run<'a>(args: &ArgMatches<'a>) -> Result<(), CliError> {
         .get_public_key(&*private_key)
         .map_err(|err| CliError::EnvironmentError(format!("{}", err)))?;
 
+    let private_key_contents = if args.is_present("encrypt") {
+        let passphrase_file = args.value_of("passphrase_file").map(Path::new);
+        encrypt_private_key(&private_key.as_hex(), &get_new_passphrase(passphrase_file)?)?
+    } else {
+        private_key.as_hex()
+    };
+
+    write_key_files(
+        &key_dir,
+        &private_key_path,
+        &private_key_contents,
+        &public_key_path,
+        &public_key.as_hex(),
+    )
+}
+
+/// Gets the key directory from the path config, which must exist.
+pub fn get_key_dir() -> Result<PathBuf, CliError> {
+    let key_dir = config::get_path_config()?.key_dir;
+    if !key_dir.exists() {
+        return Err(CliError::EnvironmentError(format!(
+            "Key directory does not exist: {:?}",
+            key_dir
+        )));
+    }
+    Ok(key_dir)
+}
+
+/// Gets the paths of the private and public key files for KEY_NAME.
+pub fn get_key_paths(key_dir: &Path, key_name: &str) -> (PathBuf, PathBuf) {
+    (
+        key_dir.join(key_name).with_extension("priv"),
+        key_dir.join(key_name).with_extension("pub"),
+    )
+}
+
+pub fn check_key_files_absent(
+    private_key_path: &Path,
+    public_key_path: &Path,
+) -> Result<(), CliError> {
+    if private_key_path.exists() {
+        return Err(CliError::EnvironmentError(format!(
+            "file exists: {:?}",
+            private_key_path
+        )));
+    }
+    if public_key_path.exists() {
+        return Err(CliError::EnvironmentError(format!(
+            "file exists: {:?}",
+            public_key_path
+        )));
+    }
+    Ok(())
+}
+
+/// Writes a private and public key pair, owned by the owner of KEY_DIR.
+pub fn write_key_files(
+    key_dir: &Path,
+    private_key_path: &Path,
+    private_key_contents: &str,
+    public_key_path: &Path,
+    public_key_hex: &str,
+) -> Result<(), CliError> {
     let key_dir_info =
         metadata(key_dir).map_err(|err| CliError::EnvironmentError(format!("{}", err)))?;
 
@@ -86,16 +128,7 @@ pub fn run<'a>(args: &ArgMatches<'a>) -> Result<(), CliError> {
         } else {
             println!("writing file: {:?}", private_key_path);
         }
-        let mut private_key_file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .mode(0o640)
-            .open(private_key_path.as_path())
-            .map_err(|err| CliError::EnvironmentError(format!("{}", err)))?;
-
-        private_key_file
-            .write(private_key.as_hex().as_bytes())
-            .map_err(|err| CliError::EnvironmentError(format!("{}", err)))?;
+        write_private_key_file(private_key_path, private_key_contents)?;
     }
 
     {
@@ -107,32 +140,17 @@ pub fn run<'a>(args: &ArgMatches<'a>) -> Result<(), CliError> {
         let mut public_key_file = OpenOptions::new()
             .write(true)
             .create(true)
+            .truncate(true)
             .mode(0o644)
-            .open(public_key_path.as_path())
+            .open(public_key_path)
             .map_err(|err| CliError::EnvironmentError(format!("{}", err)))?;
 
         public_key_file
-            .write(public_key.as_hex().as_bytes())
+            .write(public_key_hex.as_bytes())
             .map_err(|err| CliError::EnvironmentError(format!("{}", err)))?;
     }
 
-    chown(private_key_path.as_path(), key_dir_uid, key_dir_gid)?;
-    chown(public_key_path.as_path(), key_dir_uid, key_dir_gid)?;
+    chown(private_key_path, key_dir_uid, key_dir_gid)?;
+    chown(public_key_path, key_dir_uid, key_dir_gid)?;
     Ok(())
 }
-
-fn chown(path: &Path, uid: u32, gid: u32) -> Result<(), CliError> {
-    let pathstr = path
-        .to_str()
-        .ok_or_else(|| CliError::EnvironmentError(format!("Invalid path: {:?}", path)))?;
-    let cpath =
-        CString::new(pathstr).map_err(|err| CliError::EnvironmentError(format!("{}", err)))?;
-    let result = unsafe { libc::chown(cpath.as_ptr(), uid, gid) };
-    match result {
-        0 => Ok(()),
-        code => Err(CliError::EnvironmentError(format!(
-            "Error chowning file {}: {}",
-            pathstr, code
-        ))),
-    }
-}
@@ -16,5 +16,9 @@
  */
 
 pub mod blockstore;
+pub mod config;
 pub mod genesis;
+pub mod key;
 pub mod keygen;
+pub mod snapshot;
+pub mod state;
@@ -0,0 +1,294 @@
+/*
+ * Copyright 2018 Intel Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * ------------------------------------------------------------------------------
+ */
+
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+use clap::ArgMatches;
+use protobuf;
+use sawtooth_sdk::signing;
+
+use proto::block::{Block, BlockHeader};
+
+use backup::Compression;
+use blockstore::Blockstore;
+use commands::blockstore::verify_block_contents;
+use commands::state;
+use config;
+use database::error::DatabaseError;
+use database::lmdb;
+use err::CliError;
+use merkle;
+use merkle::TrieBuilder;
+use snapshot::{SnapshotHeader, SnapshotReader, SnapshotWriter, SNAPSHOT_FORMAT_VERSION};
+
+/// The number of state entries between progress messages
+const PROGRESS_INTERVAL: u64 = 100_000;
+
+/// The number of state entries restored in each write transaction
+const RESTORE_CHUNK_SIZE: u64 = 10_000;
+
+pub fn run<'a>(args: &ArgMatches<'a>) -> Result<(), CliError> {
+    match args.subcommand() {
+        ("create", Some(args)) => run_create_command(args),
+        ("restore", Some(args)) => run_restore_command(args),
+        _ => {
+            println!("Invalid subcommand; Pass --help for usage.");
+            Ok(())
+        }
+    }
+}
+
+fn run_create_command<'a>(args: &ArgMatches<'a>) -> Result<(), CliError> {
+    let filepath = args
+        .value_of("output")
+        .ok_or_else(|| CliError::ArgumentError("No output file".into()))?;
+    if Path::new(filepath).exists() {
+        return Err(CliError::ArgumentError(format!(
+            "File already exists: {:?}",
+            filepath
+        )));
+    }
+    let compression_name = args.value_of("compression").unwrap_or("none");
+    let compression = Compression::from_name(compression_name).ok_or_else(|| {
+        CliError::ArgumentError(format!("Unknown compression: {}", compression_name))
+    })?;
+
+    let blockstore_ctx = state::create_blockstore_context(args)?;
+    let blockstore = state::open_blockstore(&blockstore_ctx)?;
+    let block_id = match args.value_of("block") {
+        Some(block_id) => block_id.to_string(),
+        None => state::get_chain_head(&blockstore)?,
+    };
+    let block = blockstore
+        .get(&block_id)
+        .map_err(|err| CliError::ArgumentError(format!("Error getting block: {}", err)))?;
+    let block_header = parse_block_header(&block)?;
+
+    let ctx = state::create_context(args)?;
+    let state_db = state::open_state(&ctx)?;
+    let reader = state_db
+        .reader()
+        .map_err(|err| CliError::EnvironmentError(format!("{}", err)))?;
+    if !merkle::contains_root(&reader, &block_header.state_root_hash) {
+        return Err(CliError::ArgumentError(format!(
+            "The state of block {} has been pruned",
+            block_id
+        )));
+    }
+
+    let header = SnapshotHeader {
+        format_version: SNAPSHOT_FORMAT_VERSION,
+        block_id: block_id.clone(),
+        block_num: block_header.block_num,
+        state_root_hash: block_header.state_root_hash.clone(),
+        compression,
+    };
+
+    let file = File::create(filepath)
+        .map_err(|err| CliError::EnvironmentError(format!("Failed to create file: {}", err)))?;
+    let mut writer = SnapshotWriter::new(BufWriter::new(file), &header, &block)
+        .map_err(|err| CliError::EnvironmentError(format!("Failed to write snapshot: {}", err)))?;
+
+    // The reader sees the state as of when it was created, so the snapshot
+    // is consistent even if the validator is running
+    let mut entry_count = 0;
+    for leaf in merkle::leaves(&reader, &header.state_root_hash, "") {
+        let (address, value) = leaf.map_err(state::state_error)?;
+        writer.write_entry(&address, &value).map_err(|err| {
+            CliError::EnvironmentError(format!("Failed to write snapshot: {}", err))
+        })?;
+        entry_count += 1;
+        if entry_count % PROGRESS_INTERVAL == 0 {
+            eprintln!("Wrote {} state entries", entry_count);
+        }
+    }
+
+    writer
+        .finish()
+        .map_err(|err| CliError::EnvironmentError(format!("Failed to write snapshot: {}", err)))?;
+
+    println!(
+        "Wrote {} state entries of block {} ({}) to {}",
+        entry_count, header.block_num, block_id, filepath
+    );
+    Ok(())
+}
+
+fn run_restore_command<'a>(args: &ArgMatches<'a>) -> Result<(), CliError> {
+    let filepath = args
+        .value_of("input")
+        .ok_or_else(|| CliError::ArgumentError("No input file".into()))?;
+    let file = File::open(filepath)
+        .map_err(|err| CliError::EnvironmentError(format!("Failed to open file: {}", err)))?;
+    let mut reader = SnapshotReader::new(BufReader::new(file))
+        .map_err(|err| CliError::ParseError(format!("Failed to read snapshot: {}", err)))?;
+
+    let header = reader.header().clone();
+    let block = reader.block().clone();
+    let block_header = parse_block_header(&block)?;
+    if block.header_signature != header.block_id
+        || block_header.state_root_hash != header.state_root_hash
+    {
+        return Err(CliError::ParseError(
+            "The snapshot's block does not match its header".into(),
+        ));
+    }
+    let signing_context = signing::create_context("secp256k1")
+        .map_err(|err| CliError::EnvironmentError(format!("{}", err)))?;
+    let mut problems = vec![];
+    verify_block_contents(&*signing_context, &block, &block_header, &mut problems);
+    if !problems.is_empty() {
+        return Err(CliError::ParseError(format!(
+            "The snapshot's block {} is invalid: {}",
+            block.header_signature,
+            problems.join("; ")
+        )));
+    }
+
+    // Both databases must be new, since the restored block becomes the
+    // chain head, with no blocks below it
+    let data_dir = state::get_data_dir(args)?;
+    let state_path = data_dir.join(config::get_state_filename());
+    if state_path.exists() {
+        return Err(CliError::EnvironmentError(format!(
+            "State database already exists: {:?}",
+            state_path
+        )));
+    }
+    let blockstore_ctx =
+        lmdb::LmdbContext::new(&data_dir.join(config::get_blockstore_filename()), 3, None)
+            .map_err(|err| {
+                CliError::EnvironmentError(format!("failed to create block store context: {}", err))
+            })?;
+    let blockstore = create_blockstore(&blockstore_ctx)?;
+    match blockstore.get_chain_head() {
+        Err(DatabaseError::NotFoundError(_)) => (),
+        Ok(_) => {
+            return Err(CliError::EnvironmentError(
+                "The blockstore is not empty".into(),
+            ));
+        }
+        Err(err) => {
+            return Err(CliError::EnvironmentError(format!(
+                "failed to read chain head id: {}",
+                err
+            )));
+        }
+    }
+
+    // Build the state in a separate file, and only move it into place once
+    // its root matches the block's
+    let partial_path = PathBuf::from(format!("{}.partial", state_path.display()));
+    remove_lmdb_files(&partial_path)?;
+    let (root, entry_count) = restore_state(&partial_path, &mut reader)?;
+    if root != header.state_root_hash {
+        remove_lmdb_files(&partial_path)?;
+        return Err(CliError::ParseError(format!(
+            "The restored state root {} does not match the state root {} of block {}",
+            root, header.state_root_hash, header.block_id
+        )));
+    }
+    fs::rename(&partial_path, &state_path).map_err(|err| {
+        CliError::EnvironmentError(format!("Failed to move {:?}: {}", partial_path, err))
+    })?;
+    remove_lmdb_files(&partial_path)?;
+
+    blockstore.put(&block).map_err(|err| {
+        CliError::EnvironmentError(format!("failed to add block to blockstore: {}", err))
+    })?;
+
+    println!(
+        "Restored {} state entries of block {} ({}); the validator will start from this block",
+        entry_count, header.block_num, header.block_id
+    );
+    Ok(())
+}
+
+/// Writes the state entries from READER to a new state database at PATH, and
+/// returns the root of the state and the number of entries.
+fn restore_state(path: &Path, reader: &mut SnapshotReader) -> Result<(String, u64), CliError> {
+    let ctx = lmdb::LmdbContext::new(path, 2, None).map_err(|err| {
+        CliError::EnvironmentError(format!("failed to create state context: {}", err))
+    })?;
+    let state_db = lmdb::LmdbDatabase::new(
+        &ctx,
+        &[merkle::CHANGE_LOG_INDEX, merkle::DUPLICATE_LOG_INDEX],
+    )
+    .map_err(|err| CliError::EnvironmentError(format!("failed to open state DB: {}", err)))?;
+    let write_error =
+        |err: DatabaseError| CliError::EnvironmentError(format!("failed to write state: {}", err));
+
+    let mut builder = TrieBuilder::new();
+    let mut entry_count = 0;
+    let mut writer = state_db.writer().map_err(write_error)?;
+    while let Some((address, value)) = reader
+        .read_entry()
+        .map_err(|err| CliError::ParseError(format!("Failed to read snapshot: {}", err)))?
+    {
+        builder
+            .add(&mut writer, &address, value)
+            .map_err(write_error)?;
+        entry_count += 1;
+
+        if entry_count % RESTORE_CHUNK_SIZE == 0 {
+            writer.commit().map_err(write_error)?;
+            writer = state_db.writer().map_err(write_error)?;
+        }
+        if entry_count % PROGRESS_INTERVAL == 0 {
+            eprintln!("Restored {} state entries", entry_count);
+        }
+    }
+
+    let root = builder.finish(&mut writer).map_err(write_error)?;
+    merkle::put_root_change_log(&mut writer, &root).map_err(write_error)?;
+    writer.commit().map_err(write_error)?;
+    Ok((root, entry_count))
+}
+
+/// Removes the LMDB file at PATH and its lock file, if they exist.
+fn remove_lmdb_files(path: &Path) -> Result<(), CliError> {
+    let lock_path = PathBuf::from(format!("{}-lock", path.display()));
+    for path in &[path.to_path_buf(), lock_path] {
+        if path.exists() {
+            fs::remove_file(path).map_err(|err| {
+                CliError::EnvironmentError(format!("Failed to remove {:?}: {}", path, err))
+            })?;
+        }
+    }
+    Ok(())
+}
+
+fn create_blockstore(ctx: &lmdb::LmdbContext) -> Result<Blockstore, CliError> {
+    let blockstore_db = lmdb::LmdbDatabase::new(
+        ctx,
+        &["index_batch", "index_transaction", "index_block_num"],
+    )
+    .map_err(|err| CliError::EnvironmentError(format!("failed to open block store DB: {}", err)))?;
+
+    Ok(Blockstore::new(blockstore_db))
+}
+
+fn parse_block_header(block: &Block) -> Result<BlockHeader, CliError> {
+    protobuf::parse_from_bytes(&block.header).map_err(|err| {
+        CliError::ParseError(format!(
+            "failed to parse header for block {}: {}",
+            block.header_signature, err
+        ))
+    })
+}
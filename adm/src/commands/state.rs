@@ -0,0 +1,473 @@
+/*
+ * Copyright 2018 Intel Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * ------------------------------------------------------------------------------
+ */
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clap::ArgMatches;
+use hex;
+use protobuf;
+
+use proto::block::BlockHeader;
+use proto::setting::Setting;
+
+use blockstore::Blockstore;
+use config;
+use database::error::DatabaseError;
+use database::lmdb;
+use err::CliError;
+use merkle;
+use merkle::StateChange;
+use transactions::SETTING_NAMESPACE;
+
+/// The number of state roots listed by default
+const DEFAULT_ROOTS_COUNT: u64 = 10;
+
+/// The number of state roots between progress messages while pruning
+const PROGRESS_INTERVAL: usize = 1000;
+
+pub fn run<'a>(args: &ArgMatches<'a>) -> Result<(), CliError> {
+    match args.subcommand() {
+        ("get", Some(args)) => run_get_command(args),
+        ("list", Some(args)) => run_list_command(args),
+        ("roots", Some(args)) => run_roots_command(args),
+        ("diff", Some(args)) => run_diff_command(args),
+        ("prune", Some(args)) => run_prune_command(args),
+        ("compact", Some(args)) => run_compact_command(args),
+        _ => {
+            println!("Invalid subcommand; Pass --help for usage.");
+            Ok(())
+        }
+    }
+}
+
+fn run_get_command<'a>(args: &ArgMatches<'a>) -> Result<(), CliError> {
+    let address = args
+        .value_of("address")
+        .ok_or_else(|| CliError::ArgumentError("No address".into()))?;
+    check_hex(address, "address")?;
+    if address.len() % 2 != 0 {
+        return Err(CliError::ArgumentError(format!(
+            "Invalid address {}: an address has an even number of characters",
+            address
+        )));
+    }
+
+    let root = get_state_root(args)?;
+    let ctx = create_context(args)?;
+    let state_db = open_state(&ctx)?;
+    let reader = state_db
+        .reader()
+        .map_err(|err| CliError::EnvironmentError(format!("{}", err)))?;
+
+    match merkle::get_value(&reader, &root, address).map_err(state_error)? {
+        Some(value) => print_entry(address, &value),
+        None => Err(CliError::ArgumentError(format!(
+            "No value at {} in state {}",
+            address, root
+        ))),
+    }
+}
+
+fn run_list_command<'a>(args: &ArgMatches<'a>) -> Result<(), CliError> {
+    let prefix = args.value_of("prefix").unwrap_or("");
+    check_hex(prefix, "prefix")?;
+
+    let root = get_state_root(args)?;
+    let ctx = create_context(args)?;
+    let state_db = open_state(&ctx)?;
+    let reader = state_db
+        .reader()
+        .map_err(|err| CliError::EnvironmentError(format!("{}", err)))?;
+
+    for leaf in merkle::leaves(&reader, &root, prefix) {
+        let (address, value) = leaf.map_err(state_error)?;
+        print_entry(&address, &value)?;
+    }
+    Ok(())
+}
+
+fn run_roots_command<'a>(args: &ArgMatches<'a>) -> Result<(), CliError> {
+    let count = args
+        .value_of("count")
+        .map(|count| {
+            count
+                .parse::<u64>()
+                .map_err(|err| CliError::ArgumentError(format!("Invalid count {}: {}", count, err)))
+        })
+        .transpose()?
+        .unwrap_or(DEFAULT_ROOTS_COUNT);
+
+    let blockstore_ctx = create_blockstore_context(args)?;
+    let blockstore = open_blockstore(&blockstore_ctx)?;
+    let ctx = create_context(args)?;
+    let state_db = open_state(&ctx)?;
+    let reader = state_db
+        .reader()
+        .map_err(|err| CliError::EnvironmentError(format!("{}", err)))?;
+
+    println!(
+        "{:<8} {:<128} {:<64} STATUS",
+        "NUM", "BLOCK_ID", "STATE_ROOT",
+    );
+    let base_num = get_base_block_num(&blockstore)?;
+    let mut block_id = get_chain_head(&blockstore)?;
+    for _ in 0..count {
+        let header = get_block_header(&blockstore, &block_id)?;
+        let status = if merkle::contains_root(&reader, &header.state_root_hash) {
+            "present"
+        } else {
+            "pruned"
+        };
+        println!(
+            "{:<8} {:<128} {:<64} {}",
+            header.block_num, block_id, header.state_root_hash, status
+        );
+
+        if header.block_num <= base_num {
+            break;
+        }
+        block_id = header.previous_block_id;
+    }
+    Ok(())
+}
+
+fn run_diff_command<'a>(args: &ArgMatches<'a>) -> Result<(), CliError> {
+    let root_a = args
+        .value_of("root_a")
+        .ok_or_else(|| CliError::ArgumentError("No first state root".into()))?;
+    let root_b = args
+        .value_of("root_b")
+        .ok_or_else(|| CliError::ArgumentError("No second state root".into()))?;
+    check_state_root(root_a)?;
+    check_state_root(root_b)?;
+
+    let ctx = create_context(args)?;
+    let state_db = open_state(&ctx)?;
+    let reader = state_db
+        .reader()
+        .map_err(|err| CliError::EnvironmentError(format!("{}", err)))?;
+
+    for change in merkle::diff(&reader, root_a, root_b).map_err(state_error)? {
+        match change {
+            StateChange::Added(address, value) => {
+                println!("+ {}", format_entry(&address, &value)?);
+            }
+            StateChange::Removed(address, value) => {
+                println!("- {}", format_entry(&address, &value)?);
+            }
+            StateChange::Changed(address, old, new) => {
+                println!("- {}", format_entry(&address, &old)?);
+                println!("+ {}", format_entry(&address, &new)?);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn run_prune_command<'a>(args: &ArgMatches<'a>) -> Result<(), CliError> {
+    let keep_depth = args
+        .value_of("keep_depth")
+        .ok_or_else(|| CliError::ArgumentError("No keep depth".into()))?;
+    let keep_depth: u64 = keep_depth.parse().map_err(|err| {
+        CliError::ArgumentError(format!("Invalid keep depth {}: {}", keep_depth, err))
+    })?;
+    if keep_depth == 0 {
+        return Err(CliError::ArgumentError(
+            "The keep depth must be at least 1, to keep the chain head's state".into(),
+        ));
+    }
+
+    let blockstore_ctx = create_blockstore_context(args)?;
+    let blockstore = open_blockstore(&blockstore_ctx)?;
+    let state_path = get_state_path(args)?;
+    let ctx = lmdb::LmdbContext::new(&state_path, 2, None).map_err(|err| {
+        CliError::EnvironmentError(format!("failed to create state context: {}", err))
+    })?;
+    let state_db = open_state(&ctx)?;
+
+    // Walk back from the chain head, collecting the state roots that are at
+    // least KEEP_DEPTH blocks deep, until reaching one that is already pruned
+    // or the lowest block in the blockstore.
+    // A root that a more recent block also has is kept.
+    let mut chain_roots = HashSet::new();
+    let mut prunable = vec![];
+    {
+        let reader = state_db
+            .reader()
+            .map_err(|err| CliError::EnvironmentError(format!("{}", err)))?;
+        let base_num = get_base_block_num(&blockstore)?;
+        let mut block_id = get_chain_head(&blockstore)?;
+        let mut head_num = None;
+        loop {
+            let header = get_block_header(&blockstore, &block_id)?;
+            let head_num = *head_num.get_or_insert(header.block_num);
+            let root = header.state_root_hash;
+            if head_num - header.block_num >= keep_depth && !chain_roots.contains(&root) {
+                if merkle::get_change_log(&reader, &root)
+                    .map_err(state_error)?
+                    .is_none()
+                {
+                    break;
+                }
+                prunable.push(root.clone());
+            }
+            chain_roots.insert(root);
+
+            if header.block_num <= base_num {
+                break;
+            }
+            block_id = header.previous_block_id;
+        }
+    }
+
+    // Prune the oldest roots first, each in its own write transaction, so an
+    // interrupted run leaves the unpruned roots at the top of the chain, where
+    // the next run finds them
+    let mut root_count = 0;
+    let mut node_count = 0;
+    for (i, root) in prunable.iter().rev().enumerate() {
+        let changes = {
+            let reader = state_db
+                .reader()
+                .map_err(|err| CliError::EnvironmentError(format!("{}", err)))?;
+            merkle::plan_prune(&reader, root, &chain_roots).map_err(state_error)?
+        };
+        if let Some(changes) = changes {
+            let mut writer = state_db
+                .writer()
+                .map_err(|err| CliError::EnvironmentError(format!("{}", err)))?;
+            merkle::apply_prune(&mut writer, &changes)
+                .and_then(|_| writer.commit())
+                .map_err(|err| {
+                    CliError::EnvironmentError(format!("failed to prune state {}: {}", root, err))
+                })?;
+            root_count += changes.roots.len();
+            node_count += changes.nodes.len();
+        }
+
+        if (i + 1) % PROGRESS_INTERVAL == 0 {
+            eprintln!("Pruned {} of {} state roots", i + 1, prunable.len());
+        }
+    }
+
+    println!(
+        "Pruned {} state roots, including abandoned forks, and deleted {} nodes",
+        root_count, node_count
+    );
+    Ok(())
+}
+
+fn run_compact_command<'a>(args: &ArgMatches<'a>) -> Result<(), CliError> {
+    let output = args
+        .value_of("output")
+        .map(Path::new)
+        .ok_or_else(|| CliError::ArgumentError("No output file".into()))?;
+    if output.exists() {
+        return Err(CliError::ArgumentError(format!(
+            "File already exists: {:?}",
+            output
+        )));
+    }
+
+    let state_path = get_state_path(args)?;
+    let ctx = create_context(args)?;
+    ctx.copy_compacted(output).map_err(|err| {
+        CliError::EnvironmentError(format!("failed to compact {:?}: {}", state_path, err))
+    })?;
+
+    let file_size = |path: &Path| {
+        fs::metadata(path)
+            .map(|metadata| metadata.len())
+            .map_err(|err| {
+                CliError::EnvironmentError(format!("Unable to read size of {:?}: {}", path, err))
+            })
+    };
+    println!(
+        "Compacted {:?} ({} bytes) to {:?} ({} bytes)",
+        state_path,
+        file_size(&state_path)?,
+        output,
+        file_size(output)?
+    );
+    Ok(())
+}
+
+/// Prints the state entry at ADDRESS with VALUE, one line per setting for a
+/// settings entry.
+pub fn print_entry(address: &str, value: &[u8]) -> Result<(), CliError> {
+    println!("{}", format_entry(address, value)?);
+    Ok(())
+}
+
+/// Describes the state entry at ADDRESS. Settings are decoded; other values
+/// are shown as hex.
+fn format_entry(address: &str, value: &[u8]) -> Result<String, CliError> {
+    if address.starts_with(SETTING_NAMESPACE) {
+        let setting: Setting = protobuf::parse_from_bytes(value).map_err(|err| {
+            CliError::ParseError(format!("Invalid setting at {}: {}", address, err))
+        })?;
+        Ok(setting
+            .get_entries()
+            .iter()
+            .map(|entry| format!("{}: {} = {:?}", address, entry.key, entry.value))
+            .collect::<Vec<_>>()
+            .join("\n"))
+    } else {
+        Ok(format!("{}: {}", address, hex::encode(value)))
+    }
+}
+
+/// Finds the state root to read: the one given with --root, the one in the
+/// header of the block given with --block, or the chain head's.
+fn get_state_root<'a>(args: &ArgMatches<'a>) -> Result<String, CliError> {
+    if let Some(root) = args.value_of("root") {
+        check_state_root(root)?;
+        return Ok(root.into());
+    }
+
+    let ctx = create_blockstore_context(args)?;
+    let blockstore = open_blockstore(&ctx)?;
+    let block_id = match args.value_of("block") {
+        Some(block_id) => block_id.to_string(),
+        None => get_chain_head(&blockstore)?,
+    };
+    Ok(get_block_header(&blockstore, &block_id)?.state_root_hash)
+}
+
+pub fn get_chain_head(blockstore: &Blockstore) -> Result<String, CliError> {
+    blockstore.get_chain_head().map_err(|err| match err {
+        DatabaseError::NotFoundError(_) => {
+            CliError::EnvironmentError("The blockstore is empty".into())
+        }
+        err => CliError::EnvironmentError(format!("failed to read chain head id: {}", err)),
+    })
+}
+
+/// Finds the number of the lowest block in the blockstore, where walks back
+/// along the chain end. A chain restored from a snapshot has no blocks below
+/// the snapshot's block.
+pub fn get_base_block_num(blockstore: &Blockstore) -> Result<u64, CliError> {
+    let block_id = blockstore.get_chain_base().map_err(|err| match err {
+        DatabaseError::NotFoundError(_) => {
+            CliError::EnvironmentError("The blockstore is empty".into())
+        }
+        err => CliError::EnvironmentError(format!("failed to read chain base id: {}", err)),
+    })?;
+    Ok(get_block_header(blockstore, &block_id)?.block_num)
+}
+
+pub fn get_block_header(blockstore: &Blockstore, block_id: &str) -> Result<BlockHeader, CliError> {
+    let block = blockstore
+        .get(block_id)
+        .map_err(|err| CliError::ArgumentError(format!("Error getting block: {}", err)))?;
+    protobuf::parse_from_bytes(&block.header).map_err(|err| {
+        CliError::ParseError(format!(
+            "failed to parse header for block {}: {}",
+            block.header_signature, err
+        ))
+    })
+}
+
+fn check_hex(value: &str, name: &str) -> Result<(), CliError> {
+    if value
+        .chars()
+        .all(|c| c.is_ascii_hexdigit() && !c.is_ascii_uppercase())
+    {
+        Ok(())
+    } else {
+        Err(CliError::ArgumentError(format!(
+            "Invalid {} {}: expected lowercase hex",
+            name, value
+        )))
+    }
+}
+
+fn check_state_root(root: &str) -> Result<(), CliError> {
+    check_hex(root, "state root")?;
+    if root.len() != 64 {
+        return Err(CliError::ArgumentError(format!(
+            "Invalid state root {}: expected 64 characters",
+            root
+        )));
+    }
+    Ok(())
+}
+
+pub fn state_error(err: DatabaseError) -> CliError {
+    match err {
+        DatabaseError::NotFoundError(msg) => {
+            CliError::ArgumentError(format!("State not found: {}", msg))
+        }
+        err => CliError::EnvironmentError(format!("failed to read state: {}", err)),
+    }
+}
+
+pub fn get_data_dir<'a>(args: &ArgMatches<'a>) -> Result<PathBuf, CliError> {
+    match args.value_of("data_dir") {
+        Some(data_dir) => Ok(PathBuf::from(data_dir)),
+        None => Ok(config::get_path_config()?.data_dir),
+    }
+}
+
+/// Finds the state database, which must exist; opening a missing database
+/// would create an empty one.
+fn get_state_path<'a>(args: &ArgMatches<'a>) -> Result<PathBuf, CliError> {
+    let state_path = get_data_dir(args)?.join(config::get_state_filename());
+    if !state_path.exists() {
+        return Err(CliError::EnvironmentError(format!(
+            "State database not found: {:?}",
+            state_path
+        )));
+    }
+    Ok(state_path)
+}
+
+/// Opens the state database read-only, so that it can be inspected while the
+/// validator is running.
+pub fn create_context<'a>(args: &ArgMatches<'a>) -> Result<lmdb::LmdbContext, CliError> {
+    let state_path = get_state_path(args)?;
+    lmdb::LmdbContext::new_read_only(&state_path, 2, None).map_err(|err| {
+        CliError::EnvironmentError(format!("failed to create state context: {}", err))
+    })
+}
+
+pub fn open_state(ctx: &lmdb::LmdbContext) -> Result<lmdb::LmdbDatabase, CliError> {
+    lmdb::LmdbDatabase::open(
+        ctx,
+        &[merkle::CHANGE_LOG_INDEX, merkle::DUPLICATE_LOG_INDEX],
+    )
+    .map_err(|err| CliError::EnvironmentError(format!("failed to open state DB: {}", err)))
+}
+
+pub fn create_blockstore_context<'a>(args: &ArgMatches<'a>) -> Result<lmdb::LmdbContext, CliError> {
+    let blockstore_path = get_data_dir(args)?.join(config::get_blockstore_filename());
+    lmdb::LmdbContext::new_read_only(&blockstore_path, 3, None).map_err(|err| {
+        CliError::EnvironmentError(format!("failed to create block store context: {}", err))
+    })
+}
+
+pub fn open_blockstore(ctx: &lmdb::LmdbContext) -> Result<Blockstore, CliError> {
+    let blockstore_db = lmdb::LmdbDatabase::open(
+        ctx,
+        &["index_batch", "index_transaction", "index_block_num"],
+    )
+    .map_err(|err| CliError::EnvironmentError(format!("failed to open block store DB: {}", err)))?;
+
+    Ok(Blockstore::new(blockstore_db))
+}
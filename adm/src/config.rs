@@ -16,8 +16,13 @@
  */
 
 use std::env;
+use std::fs;
 use std::path::{Path, PathBuf};
 
+use toml;
+
+use err::CliError;
+
 const DEFAULT_CONFIG_DIR: &str = "/etc/sawtooth";
 const DEFAULT_LOG_DIR: &str = "/var/log/sawtooth";
 const DEFAULT_DATA_DIR: &str = "/var/lib/sawtooth";
@@ -25,6 +30,12 @@ const DEFAULT_KEY_DIR: &str = "/etc/sawtooth/keys";
 const DEFAULT_POLICY_DIR: &str = "/etc/sawtooth/policy";
 
 const DEFAULT_BLOCKSTORE_FILENAME: &str = "block-00.lmdb";
+const DEFAULT_STATE_FILENAME: &str = "merkle-00.lmdb";
+
+const PATH_CONFIG_FILENAME: &str = "path.toml";
+const VALIDATOR_CONFIG_FILENAME: &str = "validator.toml";
+const LOG_CONFIG_TOML_FILENAME: &str = "log_config.toml";
+const LOG_CONFIG_YAML_FILENAME: &str = "log_config.yaml";
 
 pub struct PathConfig {
     pub config_dir: PathBuf,
@@ -34,7 +45,44 @@ pub struct PathConfig {
     pub policy_dir: PathBuf,
 }
 
-pub fn get_path_config() -> PathConfig {
+/// The directories that can be set in path.toml; any that are not set keep
+/// their default.
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TomlPathConfig {
+    data_dir: Option<PathBuf>,
+    key_dir: Option<PathBuf>,
+    log_dir: Option<PathBuf>,
+    policy_dir: Option<PathBuf>,
+}
+
+/// Returns the path config, loading path.toml from the config directory in the
+/// same way as the validator, if the file exists.
+pub fn get_path_config() -> Result<PathConfig, CliError> {
+    load_path_config(&get_default_config_dir())
+}
+
+/// Returns the path config, loading path.toml from CONFIG_DIR, as the
+/// validator does when started with --config-dir.
+pub fn load_path_config(config_dir: &Path) -> Result<PathConfig, CliError> {
+    let default_config = get_default_path_config();
+    let toml_config = load_toml_path_config(&config_dir.join(PATH_CONFIG_FILENAME))?;
+
+    Ok(PathConfig {
+        config_dir: config_dir.to_path_buf(),
+        log_dir: toml_config.log_dir.unwrap_or(default_config.log_dir),
+        data_dir: toml_config.data_dir.unwrap_or(default_config.data_dir),
+        key_dir: toml_config.key_dir.unwrap_or(default_config.key_dir),
+        policy_dir: toml_config.policy_dir.unwrap_or(default_config.policy_dir),
+    })
+}
+
+/// Returns the config directory, which is not affected by path.toml.
+pub fn get_default_config_dir() -> PathBuf {
+    get_default_path_config().config_dir
+}
+
+fn get_default_path_config() -> PathConfig {
     match env::var("SAWTOOTH_HOME") {
         Ok(prefix) => PathConfig {
             config_dir: Path::new(&prefix).join("etc"),
@@ -53,6 +101,67 @@ pub fn get_path_config() -> PathConfig {
     }
 }
 
+fn load_toml_path_config(filename: &Path) -> Result<TomlPathConfig, CliError> {
+    if !filename.exists() {
+        return Ok(TomlPathConfig::default());
+    }
+
+    let raw_config = fs::read_to_string(filename).map_err(|err| {
+        CliError::EnvironmentError(format!(
+            "Unable to load path configuration file {:?}: {}",
+            filename, err
+        ))
+    })?;
+    parse_toml_path_config(&raw_config).map_err(|err| {
+        CliError::EnvironmentError(format!("Invalid path config {:?}: {}", filename, err))
+    })
+}
+
+fn parse_toml_path_config(raw_config: &str) -> Result<TomlPathConfig, toml::de::Error> {
+    toml::from_str(raw_config)
+}
+
+pub fn get_path_config_filename() -> String {
+    String::from(PATH_CONFIG_FILENAME)
+}
+
+pub fn get_validator_config_filename() -> String {
+    String::from(VALIDATOR_CONFIG_FILENAME)
+}
+
+/// Returns the log config filenames, in the order the validator looks for
+/// them; only the first that exists is used.
+pub fn get_log_config_filenames() -> Vec<String> {
+    vec![
+        String::from(LOG_CONFIG_TOML_FILENAME),
+        String::from(LOG_CONFIG_YAML_FILENAME),
+    ]
+}
+
 pub fn get_blockstore_filename() -> String {
     String::from(DEFAULT_BLOCKSTORE_FILENAME)
 }
+
+pub fn get_state_filename() -> String {
+    String::from(DEFAULT_STATE_FILENAME)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toml_path_config() {
+        let config = parse_toml_path_config(
+            "data_dir = \"/srv/sawtooth/data\"\nkey_dir = \"/srv/sawtooth/keys\"\n",
+        )
+        .unwrap();
+        assert_eq!(config.data_dir, Some(PathBuf::from("/srv/sawtooth/data")));
+        assert_eq!(config.key_dir, Some(PathBuf::from("/srv/sawtooth/keys")));
+        assert_eq!(config.log_dir, None);
+        assert_eq!(config.policy_dir, None);
+
+        // The validator rejects unknown keys, so sawadm does too
+        assert!(parse_toml_path_config("config_dir = \"/etc\"\n").is_err());
+    }
+}
@@ -0,0 +1,971 @@
+/*
+ * Copyright 2018 Intel Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * ------------------------------------------------------------------------------
+ */
+
+//! The schema of the validator's configuration files.
+//!
+//! The validator only checks the keys of validator.toml and path.toml when it
+//! starts; the values are passed on as they are, so a value of the wrong type
+//! fails later, wherever it is first used. These checks mirror the options of
+//! the validator's command line (validator/src/server/cli.rs) and the way it
+//! merges them with validator.toml, along with the parts of a log config that
+//! Python's `logging.config.dictConfig` rejects.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use toml::value::{Table, Value};
+
+/// The endpoints that can be bound, as `<kind>:<endpoint>`
+const BIND_KINDS: &[&str] = &["network", "component", "consensus"];
+
+const PEERING_MODES: &[&str] = &["static", "dynamic"];
+const SCHEDULERS: &[&str] = &["serial", "parallel"];
+const AUTHORIZATION_TYPES: &[&str] = &["trust", "challenge"];
+
+/// The defaults the validator uses for options that are not set
+const DEFAULT_BIND_NETWORK: &str = "tcp://127.0.0.1:8800";
+const DEFAULT_PEERING: &str = "static";
+
+/// The network hosts that bind every interface, which can not be advertised
+/// as the endpoint
+const ALL_INTERFACES: &[&str] = &["*", "0.0.0.0"];
+
+/// The length of a Z85-encoded CURVE key
+const CURVE_KEY_LENGTH: usize = 40;
+
+const PATH_OPTIONS: &[&str] = &["data_dir", "key_dir", "log_dir", "policy_dir"];
+
+const LOG_CONFIG_KEYS: &[&str] = &[
+    "version",
+    "formatters",
+    "filters",
+    "handlers",
+    "loggers",
+    "root",
+    "incremental",
+    "disable_existing_loggers",
+];
+const LOG_LEVELS: &[&str] = &[
+    "CRITICAL", "FATAL", "ERROR", "WARN", "WARNING", "INFO", "DEBUG", "NOTSET",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    /// The validator will fail to start, or fail once the option is used
+    Error,
+    /// The validator will start, but probably not as intended
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigProblem {
+    pub severity: Severity,
+    /// The key of the problem option, dotted for nested keys, or the command
+    /// line flag that set it; empty for problems with the whole file
+    pub key: String,
+    pub message: String,
+}
+
+impl ConfigProblem {
+    pub fn error(key: &str, message: String) -> Self {
+        ConfigProblem {
+            severity: Severity::Error,
+            key: key.to_string(),
+            message,
+        }
+    }
+
+    pub fn warning(key: &str, message: String) -> Self {
+        ConfigProblem {
+            severity: Severity::Warning,
+            key: key.to_string(),
+            message,
+        }
+    }
+}
+
+impl fmt::Display for ConfigProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.key.is_empty() {
+            write!(f, "{}: {}", self.severity, self.message)
+        } else {
+            write!(f, "{}: {}: {}", self.severity, self.key, self.message)
+        }
+    }
+}
+
+enum ValueType {
+    String,
+    /// One of the listed strings
+    Choice(&'static [&'static str]),
+    /// An integer from MIN up to the largest `u32`, which is what the
+    /// validator's command line accepts
+    Integer {
+        min: i64,
+    },
+    StringList,
+    /// A table of strings, limited to the listed keys and values, if given
+    StringTable {
+        keys: Option<&'static [&'static str]>,
+        values: Option<&'static [&'static str]>,
+    },
+}
+
+struct ConfigOption {
+    key: &'static str,
+    value_type: ValueType,
+    /// The validator's command line flag for the option, if it has one
+    flag: Option<&'static str>,
+}
+
+/// Every key the validator accepts in validator.toml
+const VALIDATOR_OPTIONS: &[ConfigOption] = &[
+    ConfigOption {
+        key: "bind",
+        value_type: ValueType::StringList,
+        flag: Some("--bind"),
+    },
+    ConfigOption {
+        key: "endpoint",
+        value_type: ValueType::String,
+        flag: Some("--endpoint"),
+    },
+    ConfigOption {
+        key: "peering",
+        value_type: ValueType::Choice(PEERING_MODES),
+        flag: Some("--peering"),
+    },
+    ConfigOption {
+        key: "seeds",
+        value_type: ValueType::StringList,
+        flag: Some("--seeds"),
+    },
+    ConfigOption {
+        key: "peers",
+        value_type: ValueType::StringList,
+        flag: Some("--peers"),
+    },
+    ConfigOption {
+        key: "network_public_key",
+        value_type: ValueType::String,
+        flag: None,
+    },
+    ConfigOption {
+        key: "network_private_key",
+        value_type: ValueType::String,
+        flag: None,
+    },
+    ConfigOption {
+        key: "scheduler",
+        value_type: ValueType::Choice(SCHEDULERS),
+        flag: Some("--scheduler"),
+    },
+    ConfigOption {
+        key: "permissions",
+        value_type: ValueType::StringTable {
+            keys: None,
+            values: None,
+        },
+        flag: None,
+    },
+    ConfigOption {
+        key: "roles",
+        value_type: ValueType::StringTable {
+            keys: Some(&["network"]),
+            values: Some(AUTHORIZATION_TYPES),
+        },
+        flag: Some("--network-auth"),
+    },
+    ConfigOption {
+        key: "opentsdb_url",
+        value_type: ValueType::String,
+        flag: Some("--opentsdb-url"),
+    },
+    ConfigOption {
+        key: "opentsdb_db",
+        value_type: ValueType::String,
+        flag: Some("--opentsdb-db"),
+    },
+    ConfigOption {
+        key: "opentsdb_username",
+        value_type: ValueType::String,
+        flag: None,
+    },
+    ConfigOption {
+        key: "opentsdb_password",
+        value_type: ValueType::String,
+        flag: None,
+    },
+    ConfigOption {
+        key: "minimum_peer_connectivity",
+        value_type: ValueType::Integer { min: 1 },
+        flag: Some("--minimum-peer-connectivity"),
+    },
+    ConfigOption {
+        key: "maximum_peer_connectivity",
+        value_type: ValueType::Integer { min: 1 },
+        flag: Some("--maximum-peer-connectivity"),
+    },
+    ConfigOption {
+        key: "state_pruning_block_depth",
+        value_type: ValueType::Integer { min: 1 },
+        flag: Some("--state-pruning-block-depth"),
+    },
+    ConfigOption {
+        key: "fork_cache_keep_time",
+        value_type: ValueType::Integer { min: 0 },
+        flag: Some("--fork-cache-keep-time"),
+    },
+    ConfigOption {
+        key: "component_thread_pool_workers",
+        value_type: ValueType::Integer { min: 1 },
+        flag: None,
+    },
+    ConfigOption {
+        key: "network_thread_pool_workers",
+        value_type: ValueType::Integer { min: 1 },
+        flag: None,
+    },
+    ConfigOption {
+        key: "signature_thread_pool_workers",
+        value_type: ValueType::Integer { min: 1 },
+        flag: None,
+    },
+];
+
+/// Checks a validator.toml, given as FILE, and the options set by the
+/// validator's command line flags, given as FLAGS with the same keys. As in
+/// the validator, a flag takes priority over the file, except that each
+/// bound endpoint is merged separately.
+pub fn check_validator_config(file: &Table, flags: &Table) -> Vec<ConfigProblem> {
+    let mut problems = vec![];
+
+    for (key, value) in file {
+        match find_option(key) {
+            Some(option) => check_value(key, &option.value_type, value, &mut problems),
+            None => problems.push(ConfigProblem::error(key, "unknown key".into())),
+        }
+    }
+
+    let mut merged = file.clone();
+    for (key, value) in flags {
+        let option = match find_option(key) {
+            Some(option) => option,
+            None => continue,
+        };
+        let flag = option.flag.unwrap_or(key);
+        check_value(flag, &option.value_type, value, &mut problems);
+
+        if key == "bind" {
+            continue;
+        }
+        if let Some(file_value) = file.get(key) {
+            if file_value != value {
+                problems.push(ConfigProblem::warning(
+                    flag,
+                    format!("overrides the {} set in validator.toml", key),
+                ));
+            }
+        }
+        merged.insert(key.clone(), value.clone());
+    }
+
+    let mut bindings = check_bindings("bind", file.get("bind"), &mut problems);
+    for (kind, endpoint) in check_bindings("--bind", flags.get("bind"), &mut problems) {
+        if let Some(file_endpoint) = bindings.get(kind) {
+            if *file_endpoint != endpoint {
+                problems.push(ConfigProblem::warning(
+                    "--bind",
+                    format!("overrides the {} endpoint set in validator.toml", kind),
+                ));
+            }
+        }
+        bindings.insert(kind, endpoint);
+    }
+
+    check_settings(&merged, &bindings, &mut problems);
+    problems
+}
+
+/// Checks a path.toml, given as FILE; the directories themselves are not
+/// checked.
+pub fn check_path_config(file: &Table) -> Vec<ConfigProblem> {
+    let mut problems = vec![];
+    for (key, value) in file {
+        if PATH_OPTIONS.contains(&key.as_str()) {
+            check_value(key, &ValueType::String, value, &mut problems);
+        } else {
+            problems.push(ConfigProblem::error(key, "unknown key".into()));
+        }
+    }
+    problems
+}
+
+/// Checks a log config, loaded from TOML or YAML, as it is passed to
+/// `logging.config.dictConfig`.
+pub fn check_log_config(config: &Table) -> Vec<ConfigProblem> {
+    let mut problems = vec![];
+
+    for key in config.keys() {
+        if !LOG_CONFIG_KEYS.contains(&key.as_str()) {
+            problems.push(ConfigProblem::error(key, "unknown key".into()));
+        }
+    }
+
+    match config.get("version") {
+        Some(Value::Integer(1)) => (),
+        Some(value) => problems.push(ConfigProblem::error(
+            "version",
+            format!("the only supported version is 1, found {}", value),
+        )),
+        None => problems.push(ConfigProblem::error(
+            "version",
+            "is required, and must be 1".into(),
+        )),
+    }
+
+    for key in &["incremental", "disable_existing_loggers"] {
+        if let Some(value) = config.get(*key) {
+            if !value.is_bool() {
+                problems.push(type_error(key, "a boolean", value));
+            }
+        }
+    }
+
+    let formatters = get_log_sections(config, "formatters", &mut problems);
+    let filters = get_log_sections(config, "filters", &mut problems);
+    let handlers = get_log_sections(config, "handlers", &mut problems);
+
+    for (name, handler) in &handlers {
+        let key = format!("handlers.{}", name);
+        match handler.get("class") {
+            Some(Value::String(_)) => (),
+            Some(value) => problems.push(type_error(&format!("{}.class", key), "a string", value)),
+            // A handler can be created by a factory instead of its class
+            None if handler.contains_key("()") => (),
+            None => problems.push(ConfigProblem::error(&key, "is missing its class".into())),
+        }
+        check_log_level(&key, handler.get("level"), &mut problems);
+        match handler.get("formatter") {
+            Some(Value::String(formatter)) if !formatters.contains_key(formatter.as_str()) => {
+                problems.push(ConfigProblem::error(
+                    &format!("{}.formatter", key),
+                    format!("\"{}\" is not defined in formatters", formatter),
+                ))
+            }
+            Some(Value::String(_)) | None => (),
+            Some(value) => {
+                problems.push(type_error(&format!("{}.formatter", key), "a string", value))
+            }
+        }
+        check_log_references(&key, handler, "filters", &filters, &mut problems);
+    }
+
+    for (name, logger) in get_log_sections(config, "loggers", &mut problems) {
+        check_logger(
+            &format!("loggers.{}", name),
+            logger,
+            &handlers,
+            &filters,
+            &mut problems,
+        );
+    }
+
+    match config.get("root") {
+        Some(Value::Table(root)) => check_logger("root", root, &handlers, &filters, &mut problems),
+        Some(value) => problems.push(type_error("root", "a table", value)),
+        None => (),
+    }
+
+    problems
+}
+
+fn find_option(key: &str) -> Option<&'static ConfigOption> {
+    VALIDATOR_OPTIONS.iter().find(|option| option.key == key)
+}
+
+/// Checks that VALUE, set for KEY, is of VALUE_TYPE.
+fn check_value(
+    key: &str,
+    value_type: &ValueType,
+    value: &Value,
+    problems: &mut Vec<ConfigProblem>,
+) {
+    match *value_type {
+        ValueType::String => {
+            if !value.is_str() {
+                problems.push(type_error(key, "a string", value));
+            }
+        }
+        ValueType::Choice(choices) => check_choice(key, choices, value, problems),
+        ValueType::Integer { min } => match value.as_integer() {
+            Some(integer) if integer >= min && integer <= i64::from(u32::MAX) => (),
+            Some(integer) => problems.push(ConfigProblem::error(
+                key,
+                format!(
+                    "expected an integer from {} to {}, found {}",
+                    min,
+                    u32::MAX,
+                    integer
+                ),
+            )),
+            None => problems.push(type_error(key, "an integer", value)),
+        },
+        ValueType::StringList => match value.as_array() {
+            Some(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    if !item.is_str() {
+                        problems.push(type_error(&format!("{}[{}]", key, i), "a string", item));
+                    }
+                }
+            }
+            None => problems.push(type_error(key, "an array of strings", value)),
+        },
+        ValueType::StringTable { keys, values } => match value.as_table() {
+            Some(table) => {
+                for (name, item) in table {
+                    let item_key = format!("{}.{}", key, name);
+                    if let Some(keys) = keys {
+                        if !keys.contains(&name.as_str()) {
+                            problems.push(ConfigProblem::error(&item_key, "unknown key".into()));
+                            continue;
+                        }
+                    }
+                    match values {
+                        Some(values) => check_choice(&item_key, values, item, problems),
+                        None => check_value(&item_key, &ValueType::String, item, problems),
+                    }
+                }
+            }
+            None => problems.push(type_error(key, "a table of strings", value)),
+        },
+    }
+}
+
+fn check_choice(key: &str, choices: &[&str], value: &Value, problems: &mut Vec<ConfigProblem>) {
+    match value.as_str() {
+        Some(choice) if choices.contains(&choice) => (),
+        Some(choice) => problems.push(ConfigProblem::error(
+            key,
+            format!(
+                "expected one of {}, found \"{}\"",
+                format_choices(choices),
+                choice
+            ),
+        )),
+        None => problems.push(type_error(
+            key,
+            &format!("one of {}", format_choices(choices)),
+            value,
+        )),
+    }
+}
+
+fn format_choices(choices: &[&str]) -> String {
+    choices
+        .iter()
+        .map(|choice| format!("\"{}\"", choice))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn type_error(key: &str, expected: &str, value: &Value) -> ConfigProblem {
+    ConfigProblem::error(
+        key,
+        format!("expected {}, found {}", expected, value.type_str()),
+    )
+}
+
+/// Returns the endpoints bound by the `<kind>:<endpoint>` entries of BIND,
+/// set for KEY, keyed by kind.
+fn check_bindings<'a>(
+    key: &str,
+    bind: Option<&'a Value>,
+    problems: &mut Vec<ConfigProblem>,
+) -> BTreeMap<&'a str, &'a str> {
+    let mut bindings = BTreeMap::new();
+    let entries = match bind.and_then(Value::as_array) {
+        Some(entries) => entries,
+        None => return bindings,
+    };
+
+    for entry in entries.iter().filter_map(Value::as_str) {
+        let mut parts = entry.splitn(2, ':');
+        let kind = parts.next().unwrap_or("");
+        let endpoint = match parts.next() {
+            Some(endpoint) if BIND_KINDS.contains(&kind) => endpoint,
+            _ => {
+                problems.push(ConfigProblem::error(
+                    key,
+                    format!(
+                        "expected network:<endpoint>, component:<endpoint> or \
+                         consensus:<endpoint>, found \"{}\"",
+                        entry
+                    ),
+                ));
+                continue;
+            }
+        };
+
+        if get_port(endpoint).is_none() {
+            problems.push(ConfigProblem::error(
+                key,
+                format!("the {} endpoint \"{}\" has no port", kind, endpoint),
+            ));
+        }
+        if bindings.insert(kind, endpoint).is_some() {
+            problems.push(ConfigProblem::error(
+                key,
+                format!("the {} endpoint is bound more than once", kind),
+            ));
+        }
+    }
+    bindings
+}
+
+/// Checks the options that depend on each other, once the command line flags
+/// have been merged into MERGED, and the bound endpoints into BINDINGS.
+fn check_settings(
+    merged: &Table,
+    bindings: &BTreeMap<&str, &str>,
+    problems: &mut Vec<ConfigProblem>,
+) {
+    let get_str = |key: &str| merged.get(key).and_then(Value::as_str);
+    let get_integer = |key: &str| merged.get(key).and_then(Value::as_integer);
+    let get_list_len = |key: &str| {
+        merged
+            .get(key)
+            .and_then(Value::as_array)
+            .map_or(0, |items| items.len())
+    };
+
+    let bind_network = bindings
+        .get("network")
+        .cloned()
+        .unwrap_or(DEFAULT_BIND_NETWORK);
+    if get_str("endpoint").is_none() && ALL_INTERFACES.contains(&get_host(bind_network)) {
+        problems.push(ConfigProblem::error(
+            "endpoint",
+            format!(
+                "must be set when the network endpoint, {}, binds every interface",
+                bind_network
+            ),
+        ));
+    }
+
+    if get_str("peering").unwrap_or(DEFAULT_PEERING) == "static" && get_list_len("seeds") > 0 {
+        problems.push(ConfigProblem::warning(
+            "seeds",
+            "seeds are only used with dynamic peering".into(),
+        ));
+    }
+
+    if let (Some(minimum), Some(maximum)) = (
+        get_integer("minimum_peer_connectivity"),
+        get_integer("maximum_peer_connectivity"),
+    ) {
+        if minimum > maximum {
+            problems.push(ConfigProblem::error(
+                "minimum_peer_connectivity",
+                format!(
+                    "is greater than maximum_peer_connectivity ({} > {})",
+                    minimum, maximum
+                ),
+            ));
+        }
+    }
+
+    match get_str("opentsdb_url") {
+        Some(url) => {
+            if !url.contains("://") || get_port(url).is_none() {
+                problems.push(ConfigProblem::error(
+                    "opentsdb_url",
+                    format!(
+                        "expected a URL with a port, such as http://localhost:8086, found \"{}\"",
+                        url
+                    ),
+                ));
+            }
+            if get_str("opentsdb_username").is_some() != get_str("opentsdb_password").is_some() {
+                problems.push(ConfigProblem::warning(
+                    "opentsdb_username",
+                    "opentsdb_username and opentsdb_password should be set together".into(),
+                ));
+            }
+        }
+        None => {
+            for key in &["opentsdb_db", "opentsdb_username", "opentsdb_password"] {
+                if merged.contains_key(*key) {
+                    problems.push(ConfigProblem::warning(
+                        key,
+                        "is ignored without opentsdb_url".into(),
+                    ));
+                }
+            }
+        }
+    }
+
+    let public_key = get_str("network_public_key");
+    let private_key = get_str("network_private_key");
+    for (key, value) in &[
+        ("network_public_key", public_key),
+        ("network_private_key", private_key),
+    ] {
+        if let Some(value) = value {
+            if value.len() != CURVE_KEY_LENGTH {
+                problems.push(ConfigProblem::error(
+                    key,
+                    format!(
+                        "expected a {} character Z85-encoded key, found {} characters",
+                        CURVE_KEY_LENGTH,
+                        value.len()
+                    ),
+                ));
+            }
+        }
+    }
+    if public_key.is_some() != private_key.is_some() {
+        problems.push(ConfigProblem::warning(
+            if public_key.is_some() {
+                "network_private_key"
+            } else {
+                "network_public_key"
+            },
+            "is not set, so network communications will not be authenticated or encrypted".into(),
+        ));
+    }
+}
+
+/// Returns the host of an endpoint such as tcp://127.0.0.1:8800; the scheme
+/// is optional, as the validator adds tcp:// if it is missing.
+fn get_host(endpoint: &str) -> &str {
+    let address = endpoint.splitn(2, "://").last().unwrap_or(endpoint);
+    address.rsplitn(2, ':').last().unwrap_or(address)
+}
+
+fn get_port(endpoint: &str) -> Option<u16> {
+    let address = endpoint.splitn(2, "://").last().unwrap_or(endpoint);
+    let mut parts = address.rsplitn(2, ':');
+    let port = parts.next()?;
+    parts.next()?;
+    port.trim_end_matches('/').parse().ok()
+}
+
+/// Returns the entries of the SECTION table of a log config, which must each
+/// be tables, keyed by name.
+fn get_log_sections<'a>(
+    config: &'a Table,
+    section: &str,
+    problems: &mut Vec<ConfigProblem>,
+) -> BTreeMap<&'a str, &'a Table> {
+    let mut entries = BTreeMap::new();
+    match config.get(section) {
+        Some(Value::Table(table)) => {
+            for (name, value) in table {
+                match value.as_table() {
+                    Some(entry) => {
+                        entries.insert(name.as_str(), entry);
+                    }
+                    None => problems.push(type_error(
+                        &format!("{}.{}", section, name),
+                        "a table",
+                        value,
+                    )),
+                }
+            }
+        }
+        Some(value) => problems.push(type_error(section, "a table", value)),
+        None => (),
+    }
+    entries
+}
+
+fn check_logger(
+    key: &str,
+    logger: &Table,
+    handlers: &BTreeMap<&str, &Table>,
+    filters: &BTreeMap<&str, &Table>,
+    problems: &mut Vec<ConfigProblem>,
+) {
+    check_log_level(key, logger.get("level"), problems);
+    if let Some(propagate) = logger.get("propagate") {
+        if !propagate.is_bool() {
+            problems.push(type_error(
+                &format!("{}.propagate", key),
+                "a boolean",
+                propagate,
+            ));
+        }
+    }
+    check_log_references(key, logger, "handlers", handlers, problems);
+    check_log_references(key, logger, "filters", filters, problems);
+}
+
+fn check_log_level(key: &str, level: Option<&Value>, problems: &mut Vec<ConfigProblem>) {
+    let key = format!("{}.level", key);
+    match level {
+        Some(Value::String(level)) if !LOG_LEVELS.contains(&level.as_str()) => {
+            problems.push(ConfigProblem::error(
+                &key,
+                format!(
+                    "expected one of {}, found \"{}\"",
+                    format_choices(LOG_LEVELS),
+                    level
+                ),
+            ))
+        }
+        Some(Value::String(_)) | Some(Value::Integer(_)) | None => (),
+        Some(value) => problems.push(type_error(&key, "a level name", value)),
+    }
+}
+
+/// Checks that the names in the SECTION list of ENTRY, such as a logger's
+/// handlers, are defined in NAMES.
+fn check_log_references(
+    key: &str,
+    entry: &Table,
+    section: &str,
+    names: &BTreeMap<&str, &Table>,
+    problems: &mut Vec<ConfigProblem>,
+) {
+    let key = format!("{}.{}", key, section);
+    let references = match entry.get(section) {
+        Some(Value::Array(references)) => references,
+        Some(value) => {
+            problems.push(type_error(&key, "an array of strings", value));
+            return;
+        }
+        None => return,
+    };
+
+    for reference in references {
+        match reference.as_str() {
+            Some(name) if !names.contains_key(name) => problems.push(ConfigProblem::error(
+                &key,
+                format!("\"{}\" is not defined in {}", name, section),
+            )),
+            Some(_) => (),
+            None => problems.push(type_error(&key, "an array of strings", reference)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use toml;
+
+    fn parse(raw: &str) -> Table {
+        toml::from_str(raw).unwrap()
+    }
+
+    /// Returns the severity and key of each problem, which the tests check
+    /// instead of the messages.
+    fn summarize(problems: &[ConfigProblem]) -> Vec<(Severity, &str)> {
+        problems
+            .iter()
+            .map(|problem| (problem.severity, problem.key.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn validator_config_types() {
+        let file = parse(
+            r#"
+            bind = ["network:tcp://127.0.0.1:8800", "component:tcp://127.0.0.1:4004"]
+            endpoint = "tcp://127.0.0.1:8800"
+            peering = "dynamic"
+            seeds = ["tcp://10.0.0.2:8800"]
+            scheduler = "parallel"
+            minimum_peer_connectivity = 3
+            maximum_peer_connectivity = 10
+            fork_cache_keep_time = 0
+
+            [roles]
+            network = "challenge"
+
+            [permissions]
+            "transactor.transaction_signer" = "policy.example"
+            "#,
+        );
+        assert_eq!(check_validator_config(&file, &Table::new()), vec![]);
+
+        let file = parse(
+            r#"
+            peering = "Static"
+            scheduler = 1
+            peers = "tcp://10.0.0.2:8800"
+            state_pruning_block_depth = 0
+            network_thread_pool_workers = "10"
+            fork_cache_keep_time = 5000000000
+            consensus = "devmode"
+
+            [roles]
+            network = "trust"
+            component = "trust"
+            "#,
+        );
+        assert_eq!(
+            summarize(&check_validator_config(&file, &Table::new())),
+            vec![
+                (Severity::Error, "consensus"),
+                (Severity::Error, "fork_cache_keep_time"),
+                (Severity::Error, "network_thread_pool_workers"),
+                (Severity::Error, "peering"),
+                (Severity::Error, "peers"),
+                (Severity::Error, "roles.component"),
+                (Severity::Error, "scheduler"),
+                (Severity::Error, "state_pruning_block_depth"),
+            ]
+        );
+    }
+
+    #[test]
+    fn validator_config_conflicts() {
+        let file = parse(
+            r#"
+            bind = ["network:tcp://0.0.0.0:8800", "network:tcp://0.0.0.0:8801", "gossip:8802"]
+            seeds = ["tcp://10.0.0.2:8800"]
+            minimum_peer_connectivity = 5
+            maximum_peer_connectivity = 4
+            opentsdb_db = "metrics"
+            network_public_key = "short"
+            "#,
+        );
+        assert_eq!(
+            summarize(&check_validator_config(&file, &Table::new())),
+            vec![
+                (Severity::Error, "bind"),
+                (Severity::Error, "bind"),
+                (Severity::Error, "endpoint"),
+                (Severity::Warning, "seeds"),
+                (Severity::Error, "minimum_peer_connectivity"),
+                (Severity::Warning, "opentsdb_db"),
+                (Severity::Error, "network_public_key"),
+                (Severity::Warning, "network_private_key"),
+            ]
+        );
+
+        // The flags take priority, so they can resolve the problems in the
+        // file, but a flag with a bad value is reported with the flag's name
+        let flags = parse(
+            r#"
+            bind = ["network:tcp://127.0.0.1:8800"]
+            peering = "dynamic"
+            maximum_peer_connectivity = 10
+            state_pruning_block_depth = "deep"
+            "#,
+        );
+        let file = parse(
+            r#"
+            bind = ["network:tcp://0.0.0.0:8800", "component:tcp://127.0.0.1:4004"]
+            seeds = ["tcp://10.0.0.2:8800"]
+            minimum_peer_connectivity = 5
+            maximum_peer_connectivity = 4
+            "#,
+        );
+        assert_eq!(
+            summarize(&check_validator_config(&file, &flags)),
+            vec![
+                (Severity::Warning, "--maximum-peer-connectivity"),
+                (Severity::Error, "--state-pruning-block-depth"),
+                (Severity::Warning, "--bind"),
+            ]
+        );
+    }
+
+    #[test]
+    fn path_config() {
+        let file = parse("data_dir = \"/srv/sawtooth/data\"\nkey_dir = 7\nconfig_dir = \"/etc\"\n");
+        assert_eq!(
+            summarize(&check_path_config(&file)),
+            vec![
+                (Severity::Error, "config_dir"),
+                (Severity::Error, "key_dir")
+            ]
+        );
+    }
+
+    #[test]
+    fn log_config() {
+        let mut config = parse(
+            r#"
+            version = 1
+            disable_existing_loggers = false
+
+            [formatters.simple]
+            format = "[%(asctime)s %(levelname)s] %(message)s"
+
+            [handlers.debug]
+            level = "DEBUG"
+            formatter = "simple"
+            class = "logging.FileHandler"
+            filename = "/var/log/sawtooth/validator-debug.log"
+
+            [loggers."sawtooth_validator.networking.interconnect"]
+            level = "DEBUG"
+            propagate = true
+            handlers = ["debug"]
+
+            [root]
+            level = "INFO"
+            handlers = ["debug"]
+            "#,
+        );
+        assert_eq!(check_log_config(&config), vec![]);
+
+        let broken = parse(
+            r#"
+            handler = "debug"
+
+            [handlers.debug]
+            level = "debug"
+            formatter = "detailed"
+
+            [root]
+            handlers = ["debug", "error"]
+            propagate = "yes"
+            "#,
+        );
+        for (key, value) in broken {
+            config.insert(key, value);
+        }
+        config.remove("version");
+        assert_eq!(
+            summarize(&check_log_config(&config)),
+            vec![
+                (Severity::Error, "handler"),
+                (Severity::Error, "version"),
+                (Severity::Error, "handlers.debug"),
+                (Severity::Error, "handlers.debug.level"),
+                (Severity::Error, "handlers.debug.formatter"),
+                (Severity::Error, "root.propagate"),
+                (Severity::Error, "root.handlers"),
+            ]
+        );
+    }
+}
@@ -30,8 +30,35 @@ pub struct LmdbContext {
 
 impl LmdbContext {
     pub fn new(filepath: &Path, indexes: u32, size: Option<usize>) -> Result<Self, DatabaseError> {
-        let flags = lmdb::open::MAPASYNC | lmdb::open::WRITEMAP | lmdb::open::NOSUBDIR;
+        Self::open(
+            filepath,
+            indexes,
+            size,
+            lmdb::open::MAPASYNC | lmdb::open::WRITEMAP | lmdb::open::NOSUBDIR,
+        )
+    }
+
+    /// Opens the environment at FILEPATH without write access, so that it can
+    /// be read safely while a validator has it open.
+    pub fn new_read_only(
+        filepath: &Path,
+        indexes: u32,
+        size: Option<usize>,
+    ) -> Result<Self, DatabaseError> {
+        Self::open(
+            filepath,
+            indexes,
+            size,
+            lmdb::open::RDONLY | lmdb::open::NOSUBDIR,
+        )
+    }
 
+    fn open(
+        filepath: &Path,
+        indexes: u32,
+        size: Option<usize>,
+        flags: lmdb::open::Flags,
+    ) -> Result<Self, DatabaseError> {
         let filepath_str = filepath
             .to_str()
             .ok_or_else(|| DatabaseError::InitError(format!("Invalid filepath: {:?}", filepath)))?;
@@ -53,6 +80,20 @@ impl LmdbContext {
         }?;
         Ok(LmdbContext { env })
     }
+
+    /// Copies the environment to a new file at FILEPATH, leaving out free
+    /// pages and renumbering the used ones, so that the copy is as small as
+    /// the data allows.
+    pub fn copy_compacted(&self, filepath: &Path) -> Result<(), DatabaseError> {
+        let filepath_str = filepath.to_str().ok_or_else(|| {
+            DatabaseError::WriterError(format!("Invalid filepath: {:?}", filepath))
+        })?;
+        self.env
+            .copy(filepath_str, lmdb::copy::COMPACT)
+            .map_err(|err| {
+                DatabaseError::WriterError(format!("Failed to copy environment: {}", err))
+            })
+    }
 }
 
 pub struct LmdbDatabase<'e> {
@@ -63,23 +104,31 @@ pub struct LmdbDatabase<'e> {
 
 impl<'e> LmdbDatabase<'e> {
     pub fn new(ctx: &'e LmdbContext, indexes: &[&str]) -> Result<Self, DatabaseError> {
-        let main = lmdb::Database::open(
-            &ctx.env,
-            Some("main"),
-            &lmdb::DatabaseOptions::new(lmdb::db::CREATE),
-        )
-        .map_err(|err| DatabaseError::InitError(format!("Failed to open database: {:?}", err)))?;
+        Self::open_with_flags(ctx, indexes, lmdb::db::CREATE)
+    }
 
-        let mut index_dbs = HashMap::with_capacity(indexes.len());
-        for name in indexes {
-            let db = lmdb::Database::open(
-                &ctx.env,
-                Some(name),
-                &lmdb::DatabaseOptions::new(lmdb::db::CREATE),
-            )
+    /// Opens the main database and INDEXES, which must already exist. This is
+    /// the only way to open the databases of a read-only context.
+    pub fn open(ctx: &'e LmdbContext, indexes: &[&str]) -> Result<Self, DatabaseError> {
+        Self::open_with_flags(ctx, indexes, lmdb::db::Flags::empty())
+    }
+
+    fn open_with_flags(
+        ctx: &'e LmdbContext,
+        indexes: &[&str],
+        flags: lmdb::db::Flags,
+    ) -> Result<Self, DatabaseError> {
+        let main = lmdb::Database::open(&ctx.env, Some("main"), &lmdb::DatabaseOptions::new(flags))
             .map_err(|err| {
                 DatabaseError::InitError(format!("Failed to open database: {:?}", err))
             })?;
+
+        let mut index_dbs = HashMap::with_capacity(indexes.len());
+        for name in indexes {
+            let db = lmdb::Database::open(&ctx.env, Some(name), &lmdb::DatabaseOptions::new(flags))
+                .map_err(|err| {
+                    DatabaseError::InitError(format!("Failed to open database: {:?}", err))
+                })?;
             index_dbs.insert(String::from(*name), db);
         }
         Ok(LmdbDatabase {
@@ -127,6 +176,15 @@ impl<'a> LmdbDatabaseReader<'a> {
         Ok(val.ok().map(Vec::from))
     }
 
+    pub fn cursor(&self) -> Result<LmdbDatabaseReaderCursor, DatabaseError> {
+        let cursor = self
+            .txn
+            .cursor(&self.db.main)
+            .map_err(|err| DatabaseError::ReaderError(format!("{}", err)))?;
+        let access = self.txn.access();
+        Ok(LmdbDatabaseReaderCursor { access, cursor })
+    }
+
     pub fn index_cursor(&self, index: &str) -> Result<LmdbDatabaseReaderCursor, DatabaseError> {
         let index = self
             .db
@@ -171,12 +229,35 @@ pub struct LmdbDatabaseReaderCursor<'a> {
 }
 
 impl<'a> LmdbDatabaseReaderCursor<'a> {
+    pub fn first(&mut self) -> Option<(Vec<u8>, Vec<u8>)> {
+        self.cursor
+            .first(&self.access)
+            .ok()
+            .map(|(key, value): (&[u8], &[u8])| (Vec::from(key), Vec::from(value)))
+    }
+
     pub fn last(&mut self) -> Option<(Vec<u8>, Vec<u8>)> {
         self.cursor
             .last(&self.access)
             .ok()
             .map(|(key, value): (&[u8], &[u8])| (Vec::from(key), Vec::from(value)))
     }
+
+    pub fn next(&mut self) -> Option<(Vec<u8>, Vec<u8>)> {
+        self.cursor
+            .next(&self.access)
+            .ok()
+            .map(|(key, value): (&[u8], &[u8])| (Vec::from(key), Vec::from(value)))
+    }
+
+    /// Positions the cursor at the first entry whose key is greater than or
+    /// equal to KEY.
+    pub fn seek_range(&mut self, key: &[u8]) -> Option<(Vec<u8>, Vec<u8>)> {
+        self.cursor
+            .seek_range_k(&self.access, key)
+            .ok()
+            .map(|(key, value): (&[u8], &[u8])| (Vec::from(key), Vec::from(value)))
+    }
 }
 
 pub struct LmdbDatabaseWriter<'a> {
@@ -185,6 +266,24 @@ pub struct LmdbDatabaseWriter<'a> {
 }
 
 impl<'a> LmdbDatabaseWriter<'a> {
+    /// Reads KEY as the writer sees it, including its uncommitted writes.
+    pub fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        let access = self.txn.access();
+        let val: Result<&[u8], _> = access.get(&self.db.main, key);
+        val.ok().map(Vec::from)
+    }
+
+    pub fn index_get(&self, index: &str, key: &[u8]) -> Result<Option<Vec<u8>>, DatabaseError> {
+        let index = self
+            .db
+            .indexes
+            .get(index)
+            .ok_or_else(|| DatabaseError::WriterError(format!("Not an index: {}", index)))?;
+        let access = self.txn.access();
+        let val: Result<&[u8], _> = access.get(index, key);
+        Ok(val.ok().map(Vec::from))
+    }
+
     pub fn put(&mut self, key: &[u8], value: &[u8]) -> Result<(), DatabaseError> {
         self.txn
             .access()
@@ -228,6 +327,19 @@ impl<'a> LmdbDatabaseWriter<'a> {
             .map_err(|err| DatabaseError::WriterError(format!("{}", err)))
     }
 
+    /// Removes every entry from INDEX.
+    pub fn index_clear(&mut self, index: &str) -> Result<(), DatabaseError> {
+        let index = self
+            .db
+            .indexes
+            .get(index)
+            .ok_or_else(|| DatabaseError::WriterError(format!("Not an index: {}", index)))?;
+        self.txn
+            .access()
+            .clear_db(index)
+            .map_err(|err| DatabaseError::WriterError(format!("{}", err)))
+    }
+
     pub fn commit(self) -> Result<(), DatabaseError> {
         self.txn
             .commit()
@@ -287,7 +399,7 @@ mod tests {
     /// database contents at each step.
     #[test]
     fn test_lmdb() {
-        let path_config = config::get_path_config();
+        let path_config = config::get_path_config().unwrap();
 
         let blockstore_path = &path_config.data_dir.join(String::from("unit-lmdb.lmdb"));
 
@@ -378,5 +490,34 @@ mod tests {
         assert_database_count(1, &database);
         assert_key_value(5, 6, &database);
         assert_not_in_database(3, &database);
+
+        // Iterate over the main database with a cursor
+        let mut writer = database.writer().unwrap();
+        writer.put(&[7], &[8]).unwrap();
+        writer.commit().unwrap();
+
+        {
+            let reader = database.reader().unwrap();
+            let mut cursor = reader.cursor().unwrap();
+            assert_eq!(cursor.first(), Some((vec![5], vec![6])));
+            assert_eq!(cursor.next(), Some((vec![7], vec![8])));
+            assert_eq!(cursor.next(), None);
+            assert_eq!(cursor.seek_range(&[6]), Some((vec![7], vec![8])));
+        }
+
+        // Clear "a"
+        let mut writer = database.writer().unwrap();
+        writer.index_put("a", &[55], &[5]).unwrap();
+        writer.index_put("a", &[56], &[6]).unwrap();
+        writer.commit().unwrap();
+
+        assert_index_count("a", 2, &database);
+
+        let mut writer = database.writer().unwrap();
+        writer.index_clear("a").unwrap();
+        writer.commit().unwrap();
+
+        assert_index_count("a", 0, &database);
+        assert_database_count(2, &database);
     }
 }
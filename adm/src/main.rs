@@ -15,23 +15,48 @@
  * ------------------------------------------------------------------------------
  */
 
+extern crate base64;
 #[macro_use]
 extern crate clap;
+extern crate crc32fast;
+extern crate crypto;
+extern crate flate2;
+extern crate hex;
 extern crate libc;
 extern crate lmdb_zero;
 extern crate protobuf;
+extern crate rand;
+#[cfg(feature = "simulate")]
+extern crate sawtooth_block_info;
+#[cfg(feature = "simulate")]
+extern crate sawtooth_identity;
 extern crate sawtooth_sdk;
+#[cfg(feature = "simulate")]
+extern crate sawtooth_settings;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
+extern crate serde_json;
 extern crate serde_yaml;
+extern crate toml;
+extern crate zstd;
 
+mod backup;
 mod blockstore;
 mod commands;
 mod config;
+mod config_schema;
 mod database;
 mod err;
+mod key_file;
+mod merkle;
+mod network_spec;
+mod output;
 mod proto;
+#[cfg(feature = "simulate")]
+mod simulator;
+mod snapshot;
+mod transactions;
 mod wrappers;
 
 use clap::ArgMatches;
@@ -43,8 +68,12 @@ fn main() {
 
     let result = match args.subcommand() {
         ("blockstore", Some(args)) => commands::blockstore::run(args),
+        ("config", Some(args)) => commands::config::run(args),
         ("keygen", Some(args)) => commands::keygen::run(args),
+        ("key", Some(args)) => commands::key::run(args),
         ("genesis", Some(args)) => commands::genesis::run(args),
+        ("state", Some(args)) => commands::state::run(args),
+        ("snapshot", Some(args)) => commands::snapshot::run(args),
         _ => {
             println!("Invalid subcommand; Pass --help for usage.");
             Ok(())
@@ -66,16 +95,33 @@ fn parse_args<'a>() -> ArgMatches<'a> {
         (about: "Manage a local validator keys and data files")
         (@subcommand blockstore =>
             (about: "manage the blockstore database directly")
+            (@arg data_dir: --("data-dir") +takes_value +global
+                "the directory containing the blockstore (default: the data_dir in path.toml)")
+            (@arg map_size: --("map-size") +takes_value +global
+                "the maximum size of the blockstore database, in bytes")
             (@subcommand backup =>
-                (about: "backup the entire blockstore database to a file")
-                (@arg output: +required "the file to backup the blockstore to"))
+                (about: "backup the blocks of the chain, or a range of its heights, to a file")
+                (@arg output: +required "the file to backup the blockstore to")
+                (@arg compression: --compression +takes_value possible_value[none gzip zstd]
+                    "the compression to use for the backup (default: none)")
+                (@arg from_height: --("from-height") +takes_value
+                    "the height of the first block to backup (default: 0)")
+                (@arg to_height: --("to-height") +takes_value
+                    "the height of the last block to backup (default: the chain head)"))
             (@subcommand restore =>
-                (about: "restore the entire blockstore database from a file")
-                (@arg input: +required "the file to restore the blockstore from"))
+                (about: "restore the blockstore database from a file; blocks that are already \
+                         present are skipped, so an interrupted restore can be resumed")
+                (@arg input: +required "the file to restore the blockstore from")
+                (@arg from_height: --("from-height") +takes_value
+                    "the height of the first block to restore (default: the first block in the backup)")
+                (@arg to_height: --("to-height") +takes_value
+                    "the height of the last block to restore (default: the last block in the backup)"))
             (@subcommand list =>
                 (about: "list blocks from the block store")
                 (@arg count: --count +takes_value "the number of blocks to list")
-                (@arg start: --start +takes_value "the first block to list"))
+                (@arg start: --start +takes_value "the first block to list")
+                (@arg format: --format +takes_value possible_value[text json yaml csv]
+                    "the output format (default: text)"))
             (@subcommand show =>
                 (about: "inspect a block in the blockstore")
                 (@arg block: -b --block +takes_value conflicts_with[batch transaction height]
@@ -86,33 +132,236 @@ fn parse_args<'a>() -> ArgMatches<'a> {
                     "show a block based on transaction id")
              (@arg blocknum: -n --("block-num")
               +takes_value conflicts_with[block batch transaction]
-                    "show a block based on height"))
+                    "show a block based on height")
+                (@arg format: --format +takes_value possible_value[yaml json csv]
+                    "the output format; csv lists the block's transactions (default: yaml)"))
             (@subcommand prune =>
                 (about: "remove a block and all children blocks from the blockstore")
-                (@arg block: +required "the block to remove"))
+                (@arg block: +required "the block to remove")
+                (@arg dry_run: --("dry-run")
+                    "list the blocks, batches and transactions that would be removed")
+                (@arg safe_depth: --("safe-depth") +takes_value
+                    "refuse to prune a block more than this many blocks below the chain head"))
             (@subcommand export =>
-                (about: "write a block's packed representation to file or stdout")
-                (@arg block: +required "the block to export")
+                (about: "write a block, or a range of blocks, to file or stdout")
+                (@arg block: required_unless[start] conflicts_with[start count]
+                    "the block to export")
+                (@arg start: --start +takes_value "the height of the first block in the range to export")
+                (@arg count: --count +takes_value requires[start]
+                    "the number of blocks to export (default: up to the chain head)")
+                (@arg format: --format +takes_value possible_value[protobuf delimited json]
+                    "the export format (default: protobuf for a block, delimited for a range)")
                 (@arg output: -o --output +takes_value "the file to export the block to"))
             (@subcommand import =>
-                (about: "add a block to the blockstore; new block's parent must be the current chain head")
-                (@arg blockfile: +required "a protobuf file containing the block to add"))
+                (about: "add blocks to the blockstore; the first block's parent must be the current chain head")
+                (@arg blockfile: +required "a file containing the blocks to add")
+                (@arg format: --format +takes_value possible_value[protobuf delimited json]
+                    "the format of the file, as written by export (default: protobuf)"))
             (@subcommand stats =>
                 (about: "print out database stats")
-                (@arg extended: -x --extended "show extended stats about the blockstore")))
+                (@arg extended: -x --extended "show extended stats about the blockstore")
+                (@arg format: --format +takes_value possible_value[text json yaml csv]
+                    "the output format (default: text)"))
+            (@subcommand query =>
+                (about: "find the blocks, batches or transactions on the chain that match all \
+                         of the given criteria")
+                (@arg block_signer: --("block-signer") +takes_value
+                    "find blocks signed by this public key")
+                (@arg batch_signer: --("batch-signer") +takes_value
+                    "find batches signed by this public key")
+                (@arg family: --family +takes_value
+                    "find transactions of this transaction family")
+                (@arg from_height: --("from-height") +takes_value
+                    "the height of the first block to search (default: 0)")
+                (@arg to_height: --("to-height") +takes_value
+                    "the height of the last block to search (default: the chain head)")
+                (@arg format: --format +takes_value possible_value[text json yaml csv]
+                    "the output format (default: text)"))
+            (@subcommand diff =>
+                (about: "compare the blockstore to another blockstore and report where their \
+                         chains diverge")
+                (@arg other: +required "the path of the other blockstore file")
+                (@arg format: --format +takes_value possible_value[text json yaml]
+                    "the output format (default: text)"))
+            (@subcommand reindex =>
+                (about: "rebuild the batch, transaction and block num indexes from the stored blocks")
+                (@arg chunk_size: --("chunk-size") +takes_value
+                    "the number of blocks to index in each write transaction (default: 1000)"))
+            (@subcommand verify =>
+                (about: "check the integrity of the chain from the chain head back to genesis")
+                (@arg format: --format +takes_value possible_value[text json yaml csv]
+                    "the format of the verification report; csv lists the problems found \
+                     (default: text)")))
+        (@subcommand config =>
+            (about: "check the validator's configuration files before starting it")
+            (@subcommand validate =>
+                (about: "check validator.toml, path.toml and the log config for unknown keys, \
+                         values of the wrong type and conflicting options; validator command \
+                         line flags can be given too, to check them against the files")
+                (@arg config_dir: --("config-dir") +takes_value
+                    "the configuration directory (default: the validator's)")
+                (@arg bind: -B --bind +takes_value +multiple number_of_values(1)
+                    "a network:<endpoint>, component:<endpoint> or consensus:<endpoint> binding")
+                (@arg peering: -P --peering +takes_value possible_value[static dynamic]
+                    "the peering type")
+                (@arg endpoint: -E --endpoint +takes_value "the advertised network endpoint URL")
+                (@arg seeds: -s --seeds +takes_value +multiple number_of_values(1)
+                    "the URIs for the initial connection to the validator network")
+                (@arg peers: -p --peers +takes_value +multiple number_of_values(1)
+                    "the static peers to connect to")
+                (@arg scheduler: --scheduler +takes_value possible_value[serial parallel]
+                    "the scheduler type")
+                (@arg network_auth: --("network-auth") +takes_value
+                    possible_value[trust challenge]
+                    "the type of authorization required to join the validator network")
+                (@arg opentsdb_url: --("opentsdb-url") +takes_value
+                    "the URL of the Open TSDB database used for metrics")
+                (@arg opentsdb_db: --("opentsdb-db") +takes_value
+                    "the name of the database used for storing metrics")
+                (@arg minimum_peer_connectivity: --("minimum-peer-connectivity") +takes_value
+                    "the minimum number of peers required before stopping peer search")
+                (@arg maximum_peer_connectivity: --("maximum-peer-connectivity") +takes_value
+                    "the maximum number of peers to accept")
+                (@arg state_pruning_block_depth: --("state-pruning-block-depth") +takes_value
+                    "the block depth below which state roots are pruned")
+                (@arg fork_cache_keep_time: --("fork-cache-keep-time") +takes_value
+                    "the time in seconds to keep uncommitted forks")))
         (@subcommand keygen =>
             (about: "generates keys for the validator to use when signing blocks")
             (@arg key_name: +takes_value "name of the key to create")
             (@arg force: --force "overwrite files if they exist")
-            (@arg quiet: -q --quiet "do not display output"))
+            (@arg quiet: -q --quiet "do not display output")
+            (@arg encrypt: --encrypt
+             "encrypt the private key with a passphrase; the validator cannot read an encrypted \
+              key, so it must be decrypted with 'sawadm key decrypt' before use")
+            (@arg passphrase_file: --("passphrase-file") +takes_value requires[encrypt]
+             "read the passphrase from the first line of this file instead of prompting"))
+        (@subcommand key =>
+            (about: "show, import and rotate the keys created by keygen, and manage the \
+                     passphrase of an encrypted key")
+            (@subcommand show =>
+                (about: "print the public key of a private key, checking that it matches the \
+                         public key file")
+                (@arg key_name: +takes_value "name of the key to show (default: validator)")
+                (@arg passphrase_file: --("passphrase-file") +takes_value
+                 "read the passphrase from the first line of this file instead of prompting"))
+            (@subcommand import =>
+                (about: "import a secp256k1 private key given as hex, PEM or DER")
+                (@arg key_file: +required "the file holding the key to import, or - for stdin")
+                (@arg key_name: +takes_value "name of the key to create (default: validator)")
+                (@arg force: --force "overwrite files if they exist")
+                (@arg encrypt: --encrypt "encrypt the private key with a passphrase")
+                (@arg passphrase_file: --("passphrase-file") +takes_value requires[encrypt]
+                 "read the passphrase from the first line of this file instead of prompting"))
+            (@subcommand rotate =>
+                (about: "replace a key with a new one, keeping the old key files with a .old \
+                         suffix, and write a batch of settings proposals, signed with the old \
+                         key, that swaps the old public key for the new one")
+                (@arg key_name: +takes_value "name of the key to rotate (default: validator)")
+                (@arg output: -o --output +takes_value +required
+                 "the file to write the proposal batch to, for 'sawtooth batch submit'")
+                (@arg authorized_keys: --("authorized-keys") +takes_value +required
+                 "the current value of sawtooth.settings.vote.authorized_keys")
+                (@arg allowed_keys: --("allowed-keys") +takes_value
+                 "the current value of sawtooth.identity.allowed_keys, if it should change too")
+                (@arg passphrase_file: --("passphrase-file") +takes_value
+                 "read the passphrase from the first line of this file instead of prompting"))
+            (@subcommand decrypt =>
+                (about: "replace an encrypted private key file with the plain key")
+                (@arg key_name: +takes_value "name of the key to decrypt (default: validator)")
+                (@arg passphrase_file: --("passphrase-file") +takes_value
+                 "read the passphrase from the first line of this file instead of prompting"))
+            (@subcommand rotate_passphrase =>
+                (name: "rotate-passphrase")
+                (about: "encrypt a private key with a new passphrase")
+                (@arg key_name: +takes_value "name of the key to change (default: validator)")
+                (@arg passphrase_file: --("passphrase-file") +takes_value
+                 "read the current passphrase from the first line of this file instead of \
+                  prompting")
+                (@arg new_passphrase_file: --("new-passphrase-file") +takes_value
+                 "read the new passphrase from the first line of this file instead of \
+                  prompting")))
         (@subcommand genesis =>
             (about: "creates the genesis.batch file for initializing the validator")
             (@arg input_file:
              +takes_value ... "file or files containing batches to add to the resulting")
             (@arg output: -o --output "choose the output file for GenesisData")
+            (@arg spec: --spec +takes_value
+             "a YAML or TOML network spec describing settings and identity policies to add \
+              to the genesis batches")
+            (@arg key: -k --key +takes_value requires[spec]
+             "the private key file used to sign the spec's transactions \
+              (default: validator.priv in the key directory)")
+            (@arg simulate: --simulate
+             "apply the genesis batches in-process with the settings, identity and block info \
+              handlers, and print the resulting state instead of writing the genesis file \
+              (requires sawadm to be built with the simulate feature)")
+            (@arg reorder: --reorder
+             "sort the batches so that every transaction's dependencies come before it, \
+              if the dependencies allow it")
             (@arg ignore_required_settings: --("ignore-required-settings")
              "skip the check for settings that are required at genesis (necessary if using a
-              settings transaction family other than sawtooth_settings)"))
+              settings transaction family other than sawtooth_settings)")
+            (@subcommand inspect =>
+                (about: "print the batches and transactions in a genesis.batch file and check \
+                         it as it would be checked when created")
+                (@arg genesis_file: +required "the genesis.batch file to inspect")))
+        (@subcommand state =>
+            (about: "read the global state from the state database, which is opened read-only")
+            (@arg data_dir: --("data-dir") +takes_value +global
+                "the directory containing the state database and blockstore (default: the data_dir in \
+                 path.toml)")
+            (@subcommand get =>
+                (about: "print the value at an address; settings are decoded, other values are \
+                         printed as hex")
+                (@arg address: +required "the address to read")
+                (@arg root: --root +takes_value conflicts_with[block]
+                    "the state root to read from (default: the chain head's)")
+                (@arg block: --block +takes_value
+                    "read from the state root of this block"))
+            (@subcommand list =>
+                (about: "print every address under a prefix, with its value, in address order")
+                (@arg prefix: +takes_value "the address prefix to list (default: all addresses)")
+                (@arg root: --root +takes_value conflicts_with[block]
+                    "the state root to read from (default: the chain head's)")
+                (@arg block: --block +takes_value
+                    "read from the state root of this block"))
+            (@subcommand roots =>
+                (about: "list the state roots of the most recent blocks, and whether each is \
+                         still in the state database")
+                (@arg count: --count +takes_value
+                    "the number of blocks to list, back from the chain head (default: 10)"))
+            (@subcommand diff =>
+                (about: "print the entries that were added (+), removed (-) or changed (- and +) \
+                         between two state roots")
+                (@arg root_a: +required "the earlier state root")
+                (@arg root_b: +required "the later state root"))
+            (@subcommand prune =>
+                (about: "delete the state of blocks deeper than the keep depth, as the validator \
+                         does with state_pruning_block_depth; the validator must be stopped")
+                (@arg keep_depth: --("keep-depth") +takes_value +required
+                    "the number of blocks, back from the chain head, whose state is kept"))
+            (@subcommand compact =>
+                (about: "copy the state database to a new file, leaving out the free space; \
+                         the copy can replace the database while the validator is stopped")
+                (@arg output: +required "the file to write the compacted database to")))
+        (@subcommand snapshot =>
+            (about: "write the state of a block to a file, or start a new validator from one")
+            (@arg data_dir: --("data-dir") +takes_value +global
+                "the directory containing the state database and blockstore (default: the data_dir in \
+                 path.toml)")
+            (@subcommand create =>
+                (about: "write a block and every entry of its state to a checksummed file")
+                (@arg output: +required "the file to write the snapshot to")
+                (@arg block: --block +takes_value
+                    "the block whose state to write (default: the chain head)")
+                (@arg compression: --compression +takes_value possible_value[none gzip zstd]
+                    "the compression to use for the snapshot (default: none)"))
+            (@subcommand restore =>
+                (about: "rebuild the state database from a snapshot and add its block to an empty \
+                         blockstore, so that a new validator starts from that block instead of \
+                         replaying the chain from genesis")
+                (@arg input: +required "the snapshot file to restore")))
         (@arg verbose: -v... "increase the logging level.")
     );
     app.get_matches()
@@ -0,0 +1,1003 @@
+/*
+ * Copyright 2018 Intel Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * ------------------------------------------------------------------------------
+ */
+
+//! Reads the validator's global state from its Merkle-Radix trie database.
+//!
+//! Each node of the trie is stored in the main database under the 32 bytes
+//! of its hash, the first half of the SHA-512 of its contents. The contents
+//! are a CBOR map with the node's value under "v", or null, and its children
+//! under "c", as a map from the next two hex characters of the address to the
+//! child's hash.
+//!
+//! The change log index holds a ChangeLogEntry for each state root, under
+//! the bytes of the root's hash, recording the nodes the root added and, for
+//! each successor root, the nodes of this root that the successor replaced.
+//! The duplicate log index counts, as a big-endian integer, the additional
+//! times a node was added while already stored; such a node is only deleted
+//! once the count is used up.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use crypto::digest::Digest;
+use crypto::sha2::Sha512;
+use hex;
+use protobuf;
+use protobuf::Message;
+
+use proto::merkle::ChangeLogEntry;
+
+use database::error::DatabaseError;
+use database::lmdb::{LmdbDatabaseReader, LmdbDatabaseWriter};
+
+pub const CHANGE_LOG_INDEX: &str = "change_log";
+pub const DUPLICATE_LOG_INDEX: &str = "duplicate_log";
+
+/// The number of hex characters of the address that each level of the trie
+/// consumes.
+const TOKEN_SIZE: usize = 2;
+
+/// A store of trie nodes, keyed by the bytes of their hash, and of the
+/// change log and duplicate log indexes.
+pub trait NodeSource {
+    fn get_node_bytes(&self, key: &[u8]) -> Option<Vec<u8>>;
+
+    fn get_index_entry(&self, index: &str, key: &[u8]) -> Result<Option<Vec<u8>>, DatabaseError>;
+}
+
+impl<'a> NodeSource for LmdbDatabaseReader<'a> {
+    fn get_node_bytes(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.get(key)
+    }
+
+    fn get_index_entry(&self, index: &str, key: &[u8]) -> Result<Option<Vec<u8>>, DatabaseError> {
+        self.index_get(index, key)
+    }
+}
+
+impl<'a> NodeSource for LmdbDatabaseWriter<'a> {
+    fn get_node_bytes(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.get(key)
+    }
+
+    fn get_index_entry(&self, index: &str, key: &[u8]) -> Result<Option<Vec<u8>>, DatabaseError> {
+        self.index_get(index, key)
+    }
+}
+
+/// A store that trie nodes and index entries can be written to.
+pub trait NodeSink: NodeSource {
+    fn put_node_bytes(&mut self, key: &[u8], bytes: &[u8]) -> Result<(), DatabaseError>;
+
+    fn put_index_entry(
+        &mut self,
+        index: &str,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<(), DatabaseError>;
+}
+
+impl<'a> NodeSink for LmdbDatabaseWriter<'a> {
+    fn put_node_bytes(&mut self, key: &[u8], bytes: &[u8]) -> Result<(), DatabaseError> {
+        self.put(key, bytes)
+    }
+
+    fn put_index_entry(
+        &mut self,
+        index: &str,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<(), DatabaseError> {
+        self.index_put(index, key, value)
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Node {
+    pub value: Option<Vec<u8>>,
+    pub children: BTreeMap<String, String>,
+}
+
+impl Node {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Node, DatabaseError> {
+        let mut node = Node::default();
+        let entries = match CborReader::new(bytes).read()? {
+            Cbor::Map(entries) => entries,
+            _ => return Err(corruption("node is not a map")),
+        };
+        for (key, value) in entries {
+            match (key, value) {
+                (Cbor::Text(ref key), Cbor::Bytes(value)) if key == "v" => node.value = Some(value),
+                (Cbor::Text(ref key), Cbor::Null) if key == "v" => node.value = None,
+                (Cbor::Text(ref key), Cbor::Map(children)) if key == "c" => {
+                    for child in children {
+                        match child {
+                            (Cbor::Text(token), Cbor::Text(hash)) => {
+                                node.children.insert(token, hash);
+                            }
+                            _ => return Err(corruption("invalid child entry")),
+                        }
+                    }
+                }
+                _ => return Err(corruption("unexpected node entry")),
+            }
+        }
+        Ok(node)
+    }
+
+    /// Encodes the node as the validator does, with the map keys sorted and
+    /// every length in its shortest form, so that it hashes the same.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        write_cbor_header(5, 2, &mut bytes);
+        write_cbor_text("c", &mut bytes);
+        write_cbor_header(5, self.children.len() as u64, &mut bytes);
+        for (token, child) in &self.children {
+            write_cbor_text(token, &mut bytes);
+            write_cbor_text(child, &mut bytes);
+        }
+        write_cbor_text("v", &mut bytes);
+        match self.value {
+            Some(ref value) => {
+                write_cbor_header(2, value.len() as u64, &mut bytes);
+                bytes.extend_from_slice(value);
+            }
+            None => bytes.push(CBOR_NULL),
+        }
+        bytes
+    }
+}
+
+/// Computes the hash under which a node with contents BYTES is stored.
+pub fn hash(bytes: &[u8]) -> String {
+    let mut sha = Sha512::new();
+    sha.input(bytes);
+    let mut result = [0; 64];
+    sha.result(&mut result);
+    hex::encode(&result[..32])
+}
+
+/// Reads the node with HASH, checking that its contents match the hash.
+pub fn get_node<S: NodeSource>(source: &S, hash_hex: &str) -> Result<Node, DatabaseError> {
+    let key = hex::decode(hash_hex)
+        .map_err(|_| DatabaseError::NotFoundError(format!("Invalid hash: {}", hash_hex)))?;
+    let bytes = source
+        .get_node_bytes(&key)
+        .ok_or_else(|| DatabaseError::NotFoundError(format!("Node not found: {}", hash_hex)))?;
+    if hash(&bytes) != hash_hex {
+        return Err(DatabaseError::CorruptionError(format!(
+            "Contents of node {} do not match its hash",
+            hash_hex
+        )));
+    }
+    Node::from_bytes(&bytes)
+}
+
+pub fn contains_root<S: NodeSource>(source: &S, root: &str) -> bool {
+    hex::decode(root)
+        .ok()
+        .and_then(|key| source.get_node_bytes(&key))
+        .is_some()
+}
+
+/// Returns the value at ADDRESS in the state with ROOT, if there is one.
+pub fn get_value<S: NodeSource>(
+    source: &S,
+    root: &str,
+    address: &str,
+) -> Result<Option<Vec<u8>>, DatabaseError> {
+    let mut node = get_node(source, root)?;
+    for token in tokens(address) {
+        node = match node.children.get(token) {
+            Some(child) => get_node(source, child)?,
+            None => return Ok(None),
+        };
+    }
+    Ok(node.value)
+}
+
+/// Iterates over the entries of the state with a root, in address order, like
+/// the validator's leaf iterator.
+pub struct Leaves<'s, S: NodeSource + 's> {
+    source: &'s S,
+    prefix: String,
+    stack: Vec<(String, String)>,
+}
+
+impl<'s, S: NodeSource + 's> Iterator for Leaves<'s, S> {
+    type Item = Result<(String, Vec<u8>), DatabaseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((path, hash)) = self.stack.pop() {
+            let node = match get_node(self.source, &hash) {
+                Ok(node) => node,
+                Err(err) => {
+                    self.stack.clear();
+                    return Some(Err(err));
+                }
+            };
+
+            // Children are pushed in reverse so that the smallest is visited
+            // first; subtrees that cannot hold the prefix are skipped.
+            for (token, child) in node.children.iter().rev() {
+                let child_path = format!("{}{}", path, token);
+                if child_path.starts_with(&self.prefix) || self.prefix.starts_with(&child_path) {
+                    self.stack.push((child_path, child.clone()));
+                }
+            }
+
+            if let Some(value) = node.value {
+                if path.starts_with(&self.prefix) {
+                    return Some(Ok((path, value)));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Returns an iterator over the entries under PREFIX in the state with ROOT.
+pub fn leaves<'s, S: NodeSource>(source: &'s S, root: &str, prefix: &str) -> Leaves<'s, S> {
+    Leaves {
+        source,
+        prefix: prefix.to_string(),
+        stack: vec![(String::new(), root.to_string())],
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum StateChange {
+    Added(String, Vec<u8>),
+    Removed(String, Vec<u8>),
+    Changed(String, Vec<u8>, Vec<u8>),
+}
+
+/// Lists the entries that differ between the states with ROOT_A and ROOT_B,
+/// in address order. Subtrees with the same hash in both states are skipped,
+/// so the cost depends on the size of the difference, not of the state.
+pub fn diff<S: NodeSource>(
+    source: &S,
+    root_a: &str,
+    root_b: &str,
+) -> Result<Vec<StateChange>, DatabaseError> {
+    let mut changes = Vec::new();
+    diff_nodes(source, "", Some(root_a), Some(root_b), &mut changes)?;
+    Ok(changes)
+}
+
+fn diff_nodes<S: NodeSource>(
+    source: &S,
+    path: &str,
+    hash_a: Option<&str>,
+    hash_b: Option<&str>,
+    changes: &mut Vec<StateChange>,
+) -> Result<(), DatabaseError> {
+    if hash_a == hash_b {
+        return Ok(());
+    }
+    let node_a = match hash_a {
+        Some(hash_a) => get_node(source, hash_a)?,
+        None => Node::default(),
+    };
+    let node_b = match hash_b {
+        Some(hash_b) => get_node(source, hash_b)?,
+        None => Node::default(),
+    };
+
+    match (node_a.value, node_b.value) {
+        (None, Some(value)) => changes.push(StateChange::Added(path.into(), value)),
+        (Some(value), None) => changes.push(StateChange::Removed(path.into(), value)),
+        (Some(old), Some(new)) => {
+            if old != new {
+                changes.push(StateChange::Changed(path.into(), old, new));
+            }
+        }
+        (None, None) => (),
+    }
+
+    let tokens = node_a
+        .children
+        .keys()
+        .chain(node_b.children.keys())
+        .collect::<BTreeSet<_>>();
+    for token in tokens {
+        diff_nodes(
+            source,
+            &format!("{}{}", path, token),
+            node_a.children.get(token).map(String::as_str),
+            node_b.children.get(token).map(String::as_str),
+            changes,
+        )?;
+    }
+    Ok(())
+}
+
+/// Returns the change log entry of the state with ROOT, if it has not been
+/// pruned.
+pub fn get_change_log<S: NodeSource>(
+    source: &S,
+    root: &str,
+) -> Result<Option<ChangeLogEntry>, DatabaseError> {
+    let key = hex::decode(root)
+        .map_err(|_| DatabaseError::NotFoundError(format!("Invalid hash: {}", root)))?;
+    source
+        .get_index_entry(CHANGE_LOG_INDEX, &key)?
+        .map(|bytes| {
+            protobuf::parse_from_bytes(&bytes).map_err(|err| {
+                DatabaseError::CorruptionError(format!(
+                    "Invalid change log entry for {}: {}",
+                    root, err
+                ))
+            })
+        })
+        .transpose()
+}
+
+/// The writes that prune one or more state roots.
+#[derive(Debug, Default)]
+pub struct PruneChanges {
+    /// The roots whose change log entries are removed
+    pub roots: Vec<Vec<u8>>,
+    /// The nodes that are deleted
+    pub nodes: BTreeSet<Vec<u8>>,
+    /// The new duplicate counts of nodes that were kept; zero removes the
+    /// count
+    pub duplicate_counts: BTreeMap<Vec<u8>, u64>,
+}
+
+impl PruneChanges {
+    /// Deletes the node with KEY, or uses up one of its duplicates.
+    fn delete_node<S: NodeSource>(&mut self, source: &S, key: &[u8]) -> Result<(), DatabaseError> {
+        let count = match self.duplicate_counts.get(key) {
+            Some(count) => *count,
+            None => source
+                .get_index_entry(DUPLICATE_LOG_INDEX, key)?
+                .map(|bytes| decode_count(&bytes))
+                .unwrap_or(0),
+        };
+        if count > 0 {
+            self.duplicate_counts.insert(key.to_vec(), count - 1);
+        } else if source.get_node_bytes(key).is_some() {
+            self.nodes.insert(key.to_vec());
+        }
+        Ok(())
+    }
+
+    /// Removes the state with ROOT, which is on an abandoned fork, with its
+    /// successors and every node they added. A state that was also reached
+    /// on the chain is left alone.
+    fn remove_abandoned<S: NodeSource>(
+        &mut self,
+        source: &S,
+        root: &str,
+        chain_roots: &HashSet<String>,
+    ) -> Result<(), DatabaseError> {
+        if chain_roots.contains(root) {
+            return Ok(());
+        }
+        let entry = match get_change_log(source, root)? {
+            Some(entry) => entry,
+            None => return Ok(()),
+        };
+        for successor in entry.get_successors() {
+            self.remove_abandoned(source, &hex::encode(successor.get_successor()), chain_roots)?;
+        }
+        for addition in entry.get_additions() {
+            self.delete_node(source, addition)?;
+        }
+        self.roots.push(hex::decode(root).unwrap_or_default());
+        Ok(())
+    }
+}
+
+/// Plans the pruning of the state with ROOT, as the validator prunes a state
+/// root once it is deep enough in the chain. The nodes that the root's
+/// successor on the chain replaced are deleted, and any fork from the root
+/// that is not on the chain is removed with everything it added. Returns None
+/// if the root is already pruned, or has no single successor in CHAIN_ROOTS.
+pub fn plan_prune<S: NodeSource>(
+    source: &S,
+    root: &str,
+    chain_roots: &HashSet<String>,
+) -> Result<Option<PruneChanges>, DatabaseError> {
+    let entry = match get_change_log(source, root)? {
+        Some(entry) => entry,
+        None => return Ok(None),
+    };
+
+    let (chain_successors, forks): (Vec<_>, Vec<_>) = entry
+        .get_successors()
+        .iter()
+        .partition(|successor| chain_roots.contains(&hex::encode(successor.get_successor())));
+    if chain_successors.len() != 1 {
+        return Ok(None);
+    }
+
+    let mut changes = PruneChanges::default();
+    for fork in forks {
+        changes.remove_abandoned(source, &hex::encode(fork.get_successor()), chain_roots)?;
+    }
+    for deletion in chain_successors[0].get_deletions() {
+        changes.delete_node(source, deletion)?;
+    }
+    changes.roots.push(hex::decode(root).unwrap_or_default());
+    Ok(Some(changes))
+}
+
+/// Writes the CHANGES that prune state roots.
+pub fn apply_prune(
+    writer: &mut LmdbDatabaseWriter,
+    changes: &PruneChanges,
+) -> Result<(), DatabaseError> {
+    for node in &changes.nodes {
+        writer.delete(node)?;
+    }
+    for (node, count) in &changes.duplicate_counts {
+        if *count == 0 {
+            writer.index_delete(DUPLICATE_LOG_INDEX, node)?;
+        } else {
+            writer.index_put(DUPLICATE_LOG_INDEX, node, &count.to_be_bytes())?;
+        }
+    }
+    for root in &changes.roots {
+        writer.index_delete(CHANGE_LOG_INDEX, root)?;
+    }
+    Ok(())
+}
+
+/// Builds a trie from entries added in address order. Each node is written as
+/// soon as no later entry can change it, so only the nodes on the path to the
+/// last entry are held in memory, and the store may be committed between
+/// entries.
+pub struct TrieBuilder {
+    /// The nodes from the root to the last entry, each with the token that
+    /// leads to it from its parent
+    path: Vec<(String, Node)>,
+    last_address: Option<String>,
+}
+
+impl TrieBuilder {
+    pub fn new() -> Self {
+        TrieBuilder {
+            path: vec![(String::new(), Node::default())],
+            last_address: None,
+        }
+    }
+
+    pub fn add<S: NodeSink>(
+        &mut self,
+        sink: &mut S,
+        address: &str,
+        value: Vec<u8>,
+    ) -> Result<(), DatabaseError> {
+        if let Some(ref last_address) = self.last_address {
+            if address <= last_address.as_str() {
+                return Err(DatabaseError::WriterError(format!(
+                    "Entry {} is not in address order after {}",
+                    address, last_address
+                )));
+            }
+        }
+
+        let tokens = tokens(address).collect::<Vec<_>>();
+        let shared = self.path[1..]
+            .iter()
+            .zip(&tokens)
+            .take_while(|((token, _), next)| token == *next)
+            .count();
+        self.close_to(sink, shared + 1)?;
+        for token in &tokens[shared..] {
+            self.path.push((token.to_string(), Node::default()));
+        }
+        if let Some((_, node)) = self.path.last_mut() {
+            node.value = Some(value);
+        }
+        self.last_address = Some(address.into());
+        Ok(())
+    }
+
+    /// Writes the remaining nodes and returns the root of the trie.
+    pub fn finish<S: NodeSink>(mut self, sink: &mut S) -> Result<String, DatabaseError> {
+        self.close_to(sink, 1)?;
+        let (_, root) = self.path.remove(0);
+        write_node(sink, &root)
+    }
+
+    /// Writes the nodes on the path below the first LEN, adding each to its
+    /// parent's children.
+    fn close_to<S: NodeSink>(&mut self, sink: &mut S, len: usize) -> Result<(), DatabaseError> {
+        while self.path.len() > len {
+            if let Some((token, node)) = self.path.pop() {
+                let child = write_node(sink, &node)?;
+                if let Some((_, parent)) = self.path.last_mut() {
+                    parent.children.insert(token, child);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes NODE, or counts a duplicate if it is already stored, and returns
+/// its hash.
+fn write_node<S: NodeSink>(sink: &mut S, node: &Node) -> Result<String, DatabaseError> {
+    let bytes = node.to_bytes();
+    let node_hash = hash(&bytes);
+    let key = hex::decode(&node_hash).unwrap_or_default();
+    if sink.get_node_bytes(&key).is_some() {
+        let count = sink
+            .get_index_entry(DUPLICATE_LOG_INDEX, &key)?
+            .map(|bytes| decode_count(&bytes))
+            .unwrap_or(0);
+        sink.put_index_entry(DUPLICATE_LOG_INDEX, &key, &(count + 1).to_be_bytes())?;
+    } else {
+        sink.put_node_bytes(&key, &bytes)?;
+    }
+    Ok(node_hash)
+}
+
+/// Records ROOT in the change log as a state with no parent, like the state
+/// at genesis, so that the validator can record its successors and later
+/// prune it.
+pub fn put_root_change_log<S: NodeSink>(sink: &mut S, root: &str) -> Result<(), DatabaseError> {
+    let key = hex::decode(root)
+        .map_err(|_| DatabaseError::WriterError(format!("Invalid hash: {}", root)))?;
+    let entry = ChangeLogEntry::new().write_to_bytes().map_err(|err| {
+        DatabaseError::WriterError(format!("Failed to serialize change log entry: {}", err))
+    })?;
+    sink.put_index_entry(CHANGE_LOG_INDEX, &key, &entry)
+}
+
+fn decode_count(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .fold(0, |count, byte| (count << 8) | u64::from(*byte))
+}
+
+fn tokens(address: &str) -> impl Iterator<Item = &str> {
+    (0..address.len())
+        .step_by(TOKEN_SIZE)
+        .map(move |i| &address[i..(i + TOKEN_SIZE).min(address.len())])
+}
+
+fn corruption(msg: &str) -> DatabaseError {
+    DatabaseError::CorruptionError(format!("Invalid trie node: {}", msg))
+}
+
+const CBOR_NULL: u8 = 0xf6;
+
+fn write_cbor_header(major: u8, arg: u64, bytes: &mut Vec<u8>) {
+    let size: usize = match arg {
+        0..=23 => {
+            bytes.push((major << 5) | arg as u8);
+            return;
+        }
+        24..=0xff => 1,
+        0x100..=0xffff => 2,
+        0x1_0000..=0xffff_ffff => 4,
+        _ => 8,
+    };
+    // The additional information is 24, 25, 26 or 27 for 1, 2, 4 or 8 bytes
+    bytes.push((major << 5) | (24 + size.trailing_zeros() as u8));
+    bytes.extend_from_slice(&arg.to_be_bytes()[8 - size..]);
+}
+
+fn write_cbor_text(text: &str, bytes: &mut Vec<u8>) {
+    write_cbor_header(3, text.len() as u64, bytes);
+    bytes.extend_from_slice(text.as_bytes());
+}
+
+/// The subset of CBOR that trie nodes use.
+#[derive(Debug)]
+enum Cbor {
+    Bytes(Vec<u8>),
+    Text(String),
+    Map(Vec<(Cbor, Cbor)>),
+    Null,
+    /// Any other item, which nodes do not hold
+    Other,
+}
+
+struct CborReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> CborReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        CborReader { bytes, pos: 0 }
+    }
+
+    fn read_bytes(&mut self, len: u64) -> Result<&'a [u8], DatabaseError> {
+        let end = self.pos as u64 + len;
+        if end > self.bytes.len() as u64 {
+            return Err(corruption("truncated"));
+        }
+        let bytes = &self.bytes[self.pos..end as usize];
+        self.pos = end as usize;
+        Ok(bytes)
+    }
+
+    /// Reads the argument of an item whose additional information is INFO.
+    fn read_argument(&mut self, info: u8) -> Result<u64, DatabaseError> {
+        let size = match info {
+            0..=23 => return Ok(u64::from(info)),
+            24 => 1,
+            25 => 2,
+            26 => 4,
+            27 => 8,
+            _ => return Err(corruption("indefinite lengths are not supported")),
+        };
+        Ok(self
+            .read_bytes(size)?
+            .iter()
+            .fold(0, |arg, byte| (arg << 8) | u64::from(*byte)))
+    }
+
+    fn read(&mut self) -> Result<Cbor, DatabaseError> {
+        let initial = self.read_bytes(1)?[0];
+        let (major, info) = (initial >> 5, initial & 0x1f);
+        if major == 7 {
+            // Simple values and floats
+            return match info {
+                22 => Ok(Cbor::Null),
+                24 => self.read_bytes(1).map(|_| Cbor::Other),
+                25 => self.read_bytes(2).map(|_| Cbor::Other),
+                26 => self.read_bytes(4).map(|_| Cbor::Other),
+                27 => self.read_bytes(8).map(|_| Cbor::Other),
+                _ => Ok(Cbor::Other),
+            };
+        }
+
+        let arg = self.read_argument(info)?;
+        match major {
+            0 | 1 => Ok(Cbor::Other),
+            2 => Ok(Cbor::Bytes(self.read_bytes(arg)?.to_vec())),
+            3 => String::from_utf8(self.read_bytes(arg)?.to_vec())
+                .map(Cbor::Text)
+                .map_err(|_| corruption("invalid text")),
+            4 => {
+                for _ in 0..arg {
+                    self.read()?;
+                }
+                Ok(Cbor::Other)
+            }
+            5 => (0..arg)
+                .map(|_| Ok((self.read()?, self.read()?)))
+                .collect::<Result<_, _>>()
+                .map(Cbor::Map),
+            // Tags are skipped
+            _ => self.read(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    use protobuf::RepeatedField;
+
+    use proto::merkle::ChangeLogEntry_Successor;
+
+    /// A state database, in memory.
+    #[derive(Default)]
+    struct MemoryDatabase {
+        nodes: HashMap<Vec<u8>, Vec<u8>>,
+        change_log: HashMap<Vec<u8>, Vec<u8>>,
+        duplicate_log: HashMap<Vec<u8>, Vec<u8>>,
+    }
+
+    impl NodeSource for MemoryDatabase {
+        fn get_node_bytes(&self, key: &[u8]) -> Option<Vec<u8>> {
+            self.nodes.get(key).cloned()
+        }
+
+        fn get_index_entry(
+            &self,
+            index: &str,
+            key: &[u8],
+        ) -> Result<Option<Vec<u8>>, DatabaseError> {
+            match index {
+                CHANGE_LOG_INDEX => Ok(self.change_log.get(key).cloned()),
+                DUPLICATE_LOG_INDEX => Ok(self.duplicate_log.get(key).cloned()),
+                _ => Err(DatabaseError::ReaderError(format!(
+                    "Not an index: {}",
+                    index
+                ))),
+            }
+        }
+    }
+
+    impl NodeSink for MemoryDatabase {
+        fn put_node_bytes(&mut self, key: &[u8], bytes: &[u8]) -> Result<(), DatabaseError> {
+            self.nodes.insert(key.to_vec(), bytes.to_vec());
+            Ok(())
+        }
+
+        fn put_index_entry(
+            &mut self,
+            index: &str,
+            key: &[u8],
+            value: &[u8],
+        ) -> Result<(), DatabaseError> {
+            match index {
+                CHANGE_LOG_INDEX => self.change_log.insert(key.to_vec(), value.to_vec()),
+                DUPLICATE_LOG_INDEX => self.duplicate_log.insert(key.to_vec(), value.to_vec()),
+                _ => {
+                    return Err(DatabaseError::WriterError(format!(
+                        "Not an index: {}",
+                        index
+                    )))
+                }
+            };
+            Ok(())
+        }
+    }
+
+    /// Stores the trie holding ENTRIES in NODES, and returns its root.
+    fn put_state(nodes: &mut HashMap<Vec<u8>, Vec<u8>>, entries: &[(&str, &[u8])]) -> String {
+        fn put_subtree(
+            nodes: &mut HashMap<Vec<u8>, Vec<u8>>,
+            path: &str,
+            entries: &[(&str, &[u8])],
+        ) -> String {
+            let mut node = Node::default();
+            let mut tokens = entries
+                .iter()
+                .filter(|(address, _)| address.len() > path.len())
+                .map(|(address, _)| &address[path.len()..path.len() + TOKEN_SIZE])
+                .collect::<Vec<_>>();
+            tokens.dedup();
+            for token in tokens {
+                let child_path = format!("{}{}", path, token);
+                let child_entries = entries
+                    .iter()
+                    .filter(|(address, _)| address.starts_with(&child_path))
+                    .cloned()
+                    .collect::<Vec<_>>();
+                let child = put_subtree(nodes, &child_path, &child_entries);
+                node.children.insert(token.to_string(), child);
+            }
+            node.value = entries
+                .iter()
+                .find(|(address, _)| *address == path)
+                .map(|(_, value)| value.to_vec());
+
+            let bytes = node.to_bytes();
+            let node_hash = hash(&bytes);
+            nodes.insert(hex::decode(&node_hash).unwrap(), bytes);
+            node_hash
+        }
+        put_subtree(nodes, "", entries)
+    }
+
+    /// Stores the state holding ENTRIES as a successor of PARENT, recording
+    /// its changes in the change log and duplicate log as the validator does,
+    /// and returns its root.
+    fn commit_state(db: &mut MemoryDatabase, parent: &str, entries: &[(&str, &[u8])]) -> String {
+        let mut nodes = HashMap::new();
+        let root = put_state(&mut nodes, entries);
+        let parent_nodes = get_node_keys(db, parent);
+
+        let mut additions = vec![];
+        for (key, bytes) in nodes.iter() {
+            if parent_nodes.contains(key) {
+                continue;
+            }
+            if db.nodes.contains_key(key) {
+                let count = db.duplicate_log.get(key).map(|count| count[7]).unwrap_or(0);
+                db.duplicate_log
+                    .insert(key.clone(), u64::from(count + 1).to_be_bytes().to_vec());
+            }
+            db.nodes.insert(key.clone(), bytes.clone());
+            additions.push(key.clone());
+        }
+
+        let mut entry = ChangeLogEntry::new();
+        entry.set_additions(RepeatedField::from_vec(additions));
+        if !parent.is_empty() {
+            entry.set_parent(hex::decode(parent).unwrap());
+
+            let parent_key = hex::decode(parent).unwrap();
+            let mut parent_entry: ChangeLogEntry =
+                protobuf::parse_from_bytes(&db.change_log[&parent_key]).unwrap();
+            let mut successor = ChangeLogEntry_Successor::new();
+            successor.set_successor(hex::decode(&root).unwrap());
+            successor.set_deletions(
+                parent_nodes
+                    .into_iter()
+                    .filter(|key| !nodes.contains_key(key))
+                    .collect(),
+            );
+            parent_entry.mut_successors().push(successor);
+            db.change_log
+                .insert(parent_key, parent_entry.write_to_bytes().unwrap());
+        }
+        db.change_log
+            .insert(hex::decode(&root).unwrap(), entry.write_to_bytes().unwrap());
+        root
+    }
+
+    /// Returns the keys of every node in the state with ROOT.
+    fn get_node_keys(db: &MemoryDatabase, root: &str) -> HashSet<Vec<u8>> {
+        let mut keys = HashSet::new();
+        let mut stack = vec![root.to_string()];
+        while let Some(hash) = stack.pop() {
+            if hash.is_empty() {
+                continue;
+            }
+            stack.extend(get_node(db, &hash).unwrap().children.values().cloned());
+            keys.insert(hex::decode(&hash).unwrap());
+        }
+        keys
+    }
+
+    /// Writes CHANGES to DB, as apply_prune does to a state database.
+    fn apply_to_memory(db: &mut MemoryDatabase, changes: &PruneChanges) {
+        for node in &changes.nodes {
+            db.nodes.remove(node);
+        }
+        for (node, count) in &changes.duplicate_counts {
+            if *count == 0 {
+                db.duplicate_log.remove(node);
+            } else {
+                db.duplicate_log
+                    .insert(node.clone(), count.to_be_bytes().to_vec());
+            }
+        }
+        for root in &changes.roots {
+            db.change_log.remove(root);
+        }
+    }
+
+    #[test]
+    fn node_from_bytes() {
+        let mut node = Node {
+            value: Some(vec![1, 2, 3]),
+            children: BTreeMap::new(),
+        };
+        node.children.insert("0a".into(), "ab".repeat(32));
+        assert_eq!(node, Node::from_bytes(&node.to_bytes()).unwrap());
+
+        // The empty node, as the validator encodes it
+        let empty = [0xa2, 0x61, 0x63, 0xa0, 0x61, 0x76, 0xf6];
+        assert_eq!(Node::default(), Node::from_bytes(&empty).unwrap());
+        assert!(Node::from_bytes(&empty[..5]).is_err());
+        assert!(Node::from_bytes(&[0x40]).is_err());
+    }
+
+    #[test]
+    fn get_and_list() {
+        let mut source = MemoryDatabase::default();
+        let root = put_state(
+            &mut source.nodes,
+            &[("0000aa", b"one"), ("0000ab", b"two"), ("00a1aa", b"three")],
+        );
+
+        assert_eq!(
+            Some(b"two".to_vec()),
+            get_value(&source, &root, "0000ab").unwrap()
+        );
+        assert_eq!(None, get_value(&source, &root, "0000ac").unwrap());
+        assert!(get_value(&source, &"00".repeat(32), "0000ab").is_err());
+
+        let addresses = |prefix| {
+            leaves(&source, &root, prefix)
+                .map(|leaf| leaf.unwrap().0)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec!["0000aa", "0000ab", "00a1aa"], addresses(""));
+        assert_eq!(vec!["0000aa", "0000ab"], addresses("0000"));
+        assert_eq!(vec!["00a1aa"], addresses("00a"));
+        assert!(addresses("01").is_empty());
+    }
+
+    #[test]
+    fn diff_states() {
+        let mut source = MemoryDatabase::default();
+        let root_a = put_state(
+            &mut source.nodes,
+            &[("0000aa", b"one"), ("0000ab", b"two"), ("00a1aa", b"three")],
+        );
+        let root_b = put_state(
+            &mut source.nodes,
+            &[("0000aa", b"one"), ("0000ab", b"2"), ("00b1aa", b"four")],
+        );
+
+        assert_eq!(
+            vec![
+                StateChange::Changed("0000ab".into(), b"two".to_vec(), b"2".to_vec()),
+                StateChange::Removed("00a1aa".into(), b"three".to_vec()),
+                StateChange::Added("00b1aa".into(), b"four".to_vec()),
+            ],
+            diff(&source, &root_a, &root_b).unwrap()
+        );
+        assert!(diff(&source, &root_a, &root_a).unwrap().is_empty());
+    }
+
+    #[test]
+    fn prune_states() {
+        let mut db = MemoryDatabase::default();
+        let root_0 = commit_state(&mut db, "", &[("0000aa", b"one")]);
+        let root_1 = commit_state(&mut db, &root_0, &[("0000aa", b"1")]);
+        let fork = commit_state(&mut db, &root_1, &[("0000aa", b"1"), ("00ff00", b"x")]);
+        // The leaf for "one" is added again while the first state still has
+        // it, so it is a duplicate
+        let root_2 = commit_state(&mut db, &root_1, &[("0000aa", b"one"), ("0000ab", b"two")]);
+        assert_eq!(1, db.duplicate_log.len());
+
+        let chain_roots = vec![root_0.clone(), root_1.clone(), root_2.clone()]
+            .into_iter()
+            .collect::<HashSet<_>>();
+        // The chain head's state has no successor to prune it for
+        assert!(plan_prune(&db, &root_2, &chain_roots).unwrap().is_none());
+
+        for root in &[&root_0, &root_1] {
+            let changes = plan_prune(&db, root, &chain_roots).unwrap().unwrap();
+            apply_to_memory(&mut db, &changes);
+        }
+        assert!(plan_prune(&db, &root_0, &chain_roots).unwrap().is_none());
+
+        // Only the chain head's state is left, with no duplicates
+        assert_eq!(
+            get_node_keys(&db, &root_2),
+            db.nodes.keys().cloned().collect()
+        );
+        assert_eq!(
+            vec![hex::decode(&root_2).unwrap()],
+            db.change_log.keys().cloned().collect::<Vec<_>>()
+        );
+        assert!(db.duplicate_log.is_empty());
+        assert!(!contains_root(&db, &fork));
+        assert_eq!(
+            Some(b"one".to_vec()),
+            get_value(&db, &root_2, "0000aa").unwrap()
+        );
+    }
+
+    #[test]
+    fn build_trie() {
+        let entries: &[(&str, &[u8])] = &[
+            ("0000aa", b"one"),
+            ("0000ab", b"one"),
+            ("00a1aa", &[7; 300]),
+        ];
+        let mut db = MemoryDatabase::default();
+        let mut builder = TrieBuilder::new();
+        for (address, value) in entries {
+            builder.add(&mut db, address, value.to_vec()).unwrap();
+        }
+        assert!(builder.add(&mut db, "0000ac", vec![]).is_err());
+        let root = builder.finish(&mut db).unwrap();
+
+        // The trie matches one built node by node, and the two identical
+        // leaves are stored once, with a duplicate
+        let mut nodes = HashMap::new();
+        assert_eq!(put_state(&mut nodes, entries), root);
+        assert_eq!(nodes.len(), db.nodes.len());
+        assert_eq!(1, db.duplicate_log.len());
+
+        assert_eq!(
+            Node::default().to_bytes(),
+            vec![0xa2, 0x61, 0x63, 0xa0, 0x61, 0x76, 0xf6]
+        );
+        let empty_root = TrieBuilder::new().finish(&mut db).unwrap();
+        assert_eq!(hash(&Node::default().to_bytes()), empty_root);
+    }
+}
@@ -0,0 +1,384 @@
+/*
+ * Copyright 2018 Intel Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * ------------------------------------------------------------------------------
+ */
+
+//! A declarative description of a network's genesis state, written in YAML or
+//! TOML. For example:
+//!
+//! ```yaml
+//! consensus:
+//!   name: Devmode
+//!   version: "0.1"
+//! authorized_keys: [02a1b2...]
+//! approval_threshold: 1
+//! validator_rules: "NofX:1,block_info;XatY:block_info,0;local:0"
+//! block_info: true
+//! settings:
+//!   sawtooth.publisher.max_batches_per_block: 100
+//! identity:
+//!   allowed_keys: [02a1b2...]
+//!   policies:
+//!     - name: policy_1
+//!       rules: ["PERMIT_KEY *"]
+//!   roles:
+//!     - name: transactor
+//!       policy: policy_1
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use sawtooth_sdk::signing;
+use serde_yaml;
+use toml;
+
+use proto::batch::Batch;
+use proto::identity::Policy_EntryType;
+
+use err::CliError;
+use transactions::{
+    create_batch, create_policy_txn, create_role_txn, create_setting_proposal_txn,
+    get_public_key_hex, ALLOWED_IDENTITY_KEYS_SETTING, AUTHORIZED_KEYS_SETTING,
+};
+
+const APPROVAL_THRESHOLD_SETTING: &str = "sawtooth.settings.vote.approval_threshold";
+const CONSENSUS_NAME_SETTING: &str = "sawtooth.consensus.algorithm.name";
+const CONSENSUS_VERSION_SETTING: &str = "sawtooth.consensus.algorithm.version";
+const VALIDATOR_RULES_SETTING: &str = "sawtooth.validator.block_validation_rules";
+const BATCH_INJECTORS_SETTING: &str = "sawtooth.validator.batch_injectors";
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NetworkSpec {
+    consensus: ConsensusSpec,
+    /// The keys allowed to vote on settings; defaults to the signing key
+    #[serde(default)]
+    authorized_keys: Vec<String>,
+    approval_threshold: Option<u32>,
+    validator_rules: Option<String>,
+    /// Whether the block_info batch injector is enabled
+    #[serde(default)]
+    block_info: bool,
+    #[serde(default)]
+    settings: BTreeMap<String, SettingValue>,
+    identity: Option<IdentitySpec>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConsensusSpec {
+    name: String,
+    version: SettingValue,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct IdentitySpec {
+    /// The keys allowed to change identity state; defaults to the signing key
+    #[serde(default)]
+    allowed_keys: Vec<String>,
+    #[serde(default)]
+    policies: Vec<PolicySpec>,
+    #[serde(default)]
+    roles: Vec<RoleSpec>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PolicySpec {
+    name: String,
+    /// Rules in the form "PERMIT_KEY <key>" or "DENY_KEY <key>"
+    rules: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RoleSpec {
+    name: String,
+    policy: String,
+}
+
+/// Setting values are strings on chain, but the spec may write numbers and
+/// booleans without quotes.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum SettingValue {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Bool(bool),
+}
+
+impl fmt::Display for SettingValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SettingValue::String(ref value) => write!(f, "{}", value),
+            SettingValue::Integer(value) => write!(f, "{}", value),
+            // Debug keeps the decimal point, so that 1.0 is not written as 1
+            SettingValue::Float(value) => write!(f, "{:?}", value),
+            SettingValue::Bool(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+impl NetworkSpec {
+    /// Loads the spec at PATH; files ending in .toml are read as TOML, and
+    /// all others as YAML.
+    pub fn load(path: &Path) -> Result<Self, CliError> {
+        let raw_spec = fs::read_to_string(path).map_err(|err| {
+            CliError::EnvironmentError(format!("Unable to read spec {:?}: {}", path, err))
+        })?;
+        if path.extension().map(|ext| ext == "toml").unwrap_or(false) {
+            toml::from_str(&raw_spec)
+                .map_err(|err| CliError::ParseError(format!("Invalid spec {:?}: {}", path, err)))
+        } else {
+            serde_yaml::from_str(&raw_spec)
+                .map_err(|err| CliError::ParseError(format!("Invalid spec {:?}: {}", path, err)))
+        }
+    }
+
+    /// Lists the settings to propose at genesis, in order. The authorized
+    /// keys come first, since later proposals must be signed by one of them,
+    /// and the approval threshold comes last, since with a threshold above 1
+    /// later proposals would only be recorded as candidates for a vote.
+    fn settings(&self, public_key: &str) -> Result<Vec<(String, String)>, CliError> {
+        let mut authorized_keys = self.authorized_keys.clone();
+        if !authorized_keys.iter().any(|key| key == public_key) {
+            authorized_keys.push(public_key.into());
+        }
+
+        let mut settings = vec![(AUTHORIZED_KEYS_SETTING.into(), authorized_keys.join(","))];
+
+        settings.push((CONSENSUS_NAME_SETTING.into(), self.consensus.name.clone()));
+        settings.push((
+            CONSENSUS_VERSION_SETTING.into(),
+            self.consensus.version.to_string(),
+        ));
+
+        if let Some(ref validator_rules) = self.validator_rules {
+            settings.push((VALIDATOR_RULES_SETTING.into(), validator_rules.clone()));
+        }
+
+        if self.block_info {
+            settings.push((BATCH_INJECTORS_SETTING.into(), "block_info".into()));
+        }
+
+        if let Some(ref identity) = self.identity {
+            let allowed_keys = if identity.allowed_keys.is_empty() {
+                vec![public_key.to_string()]
+            } else {
+                identity.allowed_keys.clone()
+            };
+            settings.push((ALLOWED_IDENTITY_KEYS_SETTING.into(), allowed_keys.join(",")));
+        }
+
+        for (setting, value) in self.settings.iter() {
+            if settings.iter().any(|(key, _)| key == setting) {
+                return Err(CliError::ArgumentError(format!(
+                    "Setting {} is set by another field of the spec",
+                    setting
+                )));
+            }
+            settings.push((setting.clone(), value.to_string()));
+        }
+
+        if let Some(approval_threshold) = self.approval_threshold {
+            if approval_threshold < 1 || approval_threshold as usize > authorized_keys.len() {
+                return Err(CliError::ArgumentError(format!(
+                    "Approval threshold must be between 1 and the number of authorized keys ({})",
+                    authorized_keys.len()
+                )));
+            }
+            settings.push((
+                APPROVAL_THRESHOLD_SETTING.into(),
+                approval_threshold.to_string(),
+            ));
+        }
+
+        Ok(settings)
+    }
+
+    /// Builds the genesis batches described by the spec, signed by SIGNER: a
+    /// batch of settings proposals, followed by a batch of identity policies
+    /// and roles, if there are any.
+    pub fn build_batches(&self, signer: &signing::Signer) -> Result<Vec<Batch>, CliError> {
+        let public_key = get_public_key_hex(signer)?;
+
+        let settings_txns = self
+            .settings(&public_key)?
+            .iter()
+            .map(|(setting, value)| create_setting_proposal_txn(signer, setting, value))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut batches = vec![create_batch(signer, settings_txns)?];
+
+        if let Some(ref identity) = self.identity {
+            // Roles refer to policies, so the policies are set first
+            let mut identity_txns = vec![];
+            for policy in identity.policies.iter() {
+                identity_txns.push(create_policy_txn(
+                    signer,
+                    &policy.name,
+                    parse_policy_rules(&policy.rules)?,
+                )?);
+            }
+            for role in identity.roles.iter() {
+                if !identity
+                    .policies
+                    .iter()
+                    .any(|policy| policy.name == role.policy)
+                {
+                    return Err(CliError::ArgumentError(format!(
+                        "Role {} refers to undefined policy {}",
+                        role.name, role.policy
+                    )));
+                }
+                identity_txns.push(create_role_txn(signer, &role.name, &role.policy)?);
+            }
+            if !identity_txns.is_empty() {
+                batches.push(create_batch(signer, identity_txns)?);
+            }
+        }
+
+        Ok(batches)
+    }
+}
+
+/// Parses policy rules in the form used by the identity CLI.
+fn parse_policy_rules(rules: &[String]) -> Result<Vec<(Policy_EntryType, String)>, CliError> {
+    rules
+        .iter()
+        .map(|rule| {
+            let mut parts = rule.split_whitespace();
+            let entry_type = match parts.next() {
+                Some("PERMIT_KEY") => Policy_EntryType::PERMIT_KEY,
+                Some("DENY_KEY") => Policy_EntryType::DENY_KEY,
+                _ => {
+                    return Err(CliError::ArgumentError(format!(
+                        "Invalid policy rule {:?}; expected PERMIT_KEY or DENY_KEY",
+                        rule
+                    )));
+                }
+            };
+            match (parts.next(), parts.next()) {
+                (Some(key), None) => Ok((entry_type, key.to_string())),
+                _ => Err(CliError::ArgumentError(format!(
+                    "Invalid policy rule {:?}; expected a single key",
+                    rule
+                ))),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPEC: &str = "
+consensus:
+  name: Devmode
+  version: 0.1
+authorized_keys: [aaaa]
+approval_threshold: 2
+block_info: true
+settings:
+  sawtooth.publisher.max_batches_per_block: 100
+identity:
+  policies:
+    - name: policy_1
+      rules: [\"PERMIT_KEY *\"]
+  roles:
+    - name: transactor
+      policy: policy_1
+";
+
+    #[test]
+    fn spec_settings() {
+        let spec: NetworkSpec = serde_yaml::from_str(SPEC).unwrap();
+        assert_eq!(
+            spec.settings("bbbb").unwrap(),
+            vec![
+                (AUTHORIZED_KEYS_SETTING.into(), "aaaa,bbbb".into()),
+                (CONSENSUS_NAME_SETTING.into(), "Devmode".into()),
+                (CONSENSUS_VERSION_SETTING.into(), "0.1".into()),
+                (BATCH_INJECTORS_SETTING.into(), "block_info".into()),
+                (ALLOWED_IDENTITY_KEYS_SETTING.into(), "bbbb".into()),
+                (
+                    "sawtooth.publisher.max_batches_per_block".into(),
+                    "100".into()
+                ),
+                (APPROVAL_THRESHOLD_SETTING.into(), "2".into()),
+            ]
+        );
+    }
+
+    /// Asserts that applying the genesis batches of the spec sets every
+    /// setting, rather than leaving them as candidates for a vote.
+    #[test]
+    #[cfg(feature = "simulate")]
+    fn spec_simulation() {
+        use protobuf;
+
+        use proto::setting::Setting;
+        use simulator;
+        use transactions::setting_key_to_address;
+
+        let spec: NetworkSpec = serde_yaml::from_str(SPEC).unwrap();
+        let context = signing::create_context("secp256k1").unwrap();
+        let private_key = context.new_random_private_key().unwrap();
+        let factory = signing::CryptoFactory::new(&*context);
+        let signer = factory.new_signer(&*private_key);
+
+        let state = simulator::simulate(&spec.build_batches(&signer).unwrap()).unwrap();
+
+        for (key, value) in &[
+            (CONSENSUS_NAME_SETTING, "Devmode"),
+            (BATCH_INJECTORS_SETTING, "block_info"),
+            (APPROVAL_THRESHOLD_SETTING, "2"),
+        ] {
+            let setting: Setting =
+                protobuf::parse_from_bytes(&state[&setting_key_to_address(key)]).unwrap();
+            assert_eq!(
+                vec![(key.to_string(), value.to_string())],
+                setting
+                    .get_entries()
+                    .iter()
+                    .map(|entry| (entry.get_key().to_string(), entry.get_value().to_string()))
+                    .collect::<Vec<_>>()
+            );
+        }
+    }
+
+    #[test]
+    fn spec_errors() {
+        let spec: NetworkSpec =
+            serde_yaml::from_str("consensus: {name: Devmode, version: 0.1}\napproval_threshold: 2")
+                .unwrap();
+        assert!(spec.settings("bbbb").is_err());
+
+        let spec: NetworkSpec = serde_yaml::from_str(
+            "consensus: {name: Devmode, version: 0.1}\nsettings: {sawtooth.consensus.algorithm.name: PoET}",
+        )
+        .unwrap();
+        assert!(spec.settings("bbbb").is_err());
+
+        assert!(serde_yaml::from_str::<NetworkSpec>("consensus: {name: Devmode}").is_err());
+        assert!(parse_policy_rules(&["ALLOW_KEY *".into()]).is_err());
+        assert!(parse_policy_rules(&["PERMIT_KEY a b".into()]).is_err());
+    }
+}
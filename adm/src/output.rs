@@ -0,0 +1,96 @@
+/*
+ * Copyright 2018 Intel Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * ------------------------------------------------------------------------------
+ */
+
+//! Machine-readable output for commands that print reports.
+
+use serde::Serialize;
+use serde_json;
+use serde_yaml;
+
+use err::CliError;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+    Yaml,
+    Csv,
+}
+
+impl OutputFormat {
+    pub fn from_name(name: &str) -> Result<Self, CliError> {
+        match name {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "yaml" => Ok(OutputFormat::Yaml),
+            "csv" => Ok(OutputFormat::Csv),
+            _ => Err(CliError::ArgumentError(format!(
+                "Unknown output format: {}",
+                name
+            ))),
+        }
+    }
+}
+
+/// Prints VALUE as JSON or YAML. Text and CSV output are specific to each
+/// report, so they must be printed by the caller.
+pub fn print_serialized<T: Serialize>(value: &T, format: OutputFormat) -> Result<(), CliError> {
+    let serialized = match format {
+        OutputFormat::Json => serde_json::to_string_pretty(value).map_err(|err| err.to_string()),
+        OutputFormat::Yaml => serde_yaml::to_string(value).map_err(|err| err.to_string()),
+        _ => Err(format!("{:?} output is not serialized", format)),
+    }
+    .map_err(|err| CliError::EnvironmentError(format!("failed to serialize output: {}", err)))?;
+
+    println!("{}", serialized);
+    Ok(())
+}
+
+/// Prints FIELDS as one CSV record, quoting fields as needed.
+pub fn print_csv_row<S: AsRef<str>>(fields: &[S]) {
+    println!("{}", csv_row(fields));
+}
+
+fn csv_row<S: AsRef<str>>(fields: &[S]) -> String {
+    fields
+        .iter()
+        .map(|field| {
+            let field = field.as_ref();
+            if field.contains(&[',', '"', '\n', '\r'][..]) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_quoting() {
+        assert_eq!("a,b,1", csv_row(&["a", "b", "1"]));
+        assert_eq!(
+            "\"a,b\",\"say \"\"hi\"\"\",c",
+            csv_row(&["a,b", "say \"hi\"", "c"])
+        );
+        assert_eq!("", csv_row::<&str>(&[]));
+    }
+}
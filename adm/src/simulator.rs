@@ -0,0 +1,246 @@
+/*
+ * Copyright 2018 Intel Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * ------------------------------------------------------------------------------
+ */
+
+//! Applies genesis batches in-process, with the transaction handlers for the
+//! families this repo ships, so that an invalid batch is found before the
+//! validator fails on it at startup.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+use protobuf;
+use sawtooth_block_info::handler::BlockInfoTransactionHandler;
+use sawtooth_identity::handler::IdentityTransactionHandler;
+use sawtooth_sdk::messages::processor::TpProcessRequest;
+use sawtooth_sdk::messages::transaction::TransactionHeader;
+use sawtooth_sdk::processor::handler::{ContextError, TransactionContext, TransactionHandler};
+use sawtooth_settings::handler::SettingsTransactionHandler;
+
+use proto::batch::Batch;
+
+use err::CliError;
+
+/// The global state, from address to value.
+pub type State = BTreeMap<String, Vec<u8>>;
+
+/// The context of a single transaction. As in the validator, the transaction
+/// may only read the addresses under its inputs, and only change the
+/// addresses under its outputs.
+struct SimulatedContext<'a> {
+    state: &'a RefCell<State>,
+    inputs: Vec<String>,
+    outputs: Vec<String>,
+}
+
+impl<'a> SimulatedContext<'a> {
+    fn check_authorized(
+        &self,
+        addresses: &[String],
+        authorized: &[String],
+        access: &str,
+    ) -> Result<(), ContextError> {
+        for address in addresses {
+            if !authorized
+                .iter()
+                .any(|prefix| address.starts_with(prefix.as_str()))
+            {
+                return Err(ContextError::AuthorizationError(format!(
+                    "Tried to {} unauthorized address: {}",
+                    access, address
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a> TransactionContext for SimulatedContext<'a> {
+    fn get_state_entries(
+        &self,
+        addresses: &[String],
+    ) -> Result<Vec<(String, Vec<u8>)>, ContextError> {
+        self.check_authorized(addresses, &self.inputs, "get")?;
+        let state = self.state.borrow();
+        Ok(addresses
+            .iter()
+            .filter_map(|address| {
+                state
+                    .get(address)
+                    .map(|value| (address.clone(), value.clone()))
+            })
+            .collect())
+    }
+
+    fn set_state_entries(&self, entries: Vec<(String, Vec<u8>)>) -> Result<(), ContextError> {
+        let addresses = entries
+            .iter()
+            .map(|(address, _)| address.clone())
+            .collect::<Vec<_>>();
+        self.check_authorized(&addresses, &self.outputs, "set")?;
+        self.state.borrow_mut().extend(entries);
+        Ok(())
+    }
+
+    fn delete_state_entries(&self, addresses: &[String]) -> Result<Vec<String>, ContextError> {
+        self.check_authorized(addresses, &self.outputs, "delete")?;
+        let mut state = self.state.borrow_mut();
+        Ok(addresses
+            .iter()
+            .filter(|address| state.remove(address.as_str()).is_some())
+            .cloned()
+            .collect())
+    }
+
+    // Receipts and events are not part of the state, so they are dropped
+    fn add_receipt_data(&self, _data: &[u8]) -> Result<(), ContextError> {
+        Ok(())
+    }
+
+    fn add_event(
+        &self,
+        _event_type: String,
+        _attributes: Vec<(String, String)>,
+        _data: &[u8],
+    ) -> Result<(), ContextError> {
+        Ok(())
+    }
+}
+
+/// Applies the transactions in BATCHES, in order, to an empty state, and
+/// returns the resulting state. Stops at the first transaction that fails,
+/// or that no handler supports.
+pub fn simulate(batches: &[Batch]) -> Result<State, CliError> {
+    let handlers: Vec<Box<dyn TransactionHandler>> = vec![
+        Box::new(SettingsTransactionHandler::new()),
+        Box::new(IdentityTransactionHandler::new()),
+        Box::new(BlockInfoTransactionHandler::new()),
+    ];
+    let state = RefCell::new(State::new());
+
+    for batch in batches {
+        for txn in batch.get_transactions() {
+            let header: TransactionHeader =
+                protobuf::parse_from_bytes(&txn.header).map_err(|err| {
+                    CliError::ArgumentError(format!(
+                        "Invalid transaction header for txn {}: {}",
+                        &txn.header_signature, err
+                    ))
+                })?;
+
+            let handler = handlers
+                .iter()
+                .find(|handler| {
+                    handler.family_name() == header.family_name
+                        && handler.family_versions().contains(&header.family_version)
+                })
+                .ok_or_else(|| {
+                    CliError::ArgumentError(format!(
+                        "Transaction {} cannot be simulated; no handler for family {} {}",
+                        &txn.header_signature, header.family_name, header.family_version
+                    ))
+                })?;
+
+            let mut context = SimulatedContext {
+                state: &state,
+                inputs: header.inputs.to_vec(),
+                outputs: header.outputs.to_vec(),
+            };
+
+            let mut request = TpProcessRequest::new();
+            request.set_header(header);
+            request.set_payload(txn.payload.clone());
+            request.set_signature(txn.header_signature.clone());
+
+            handler.apply(&request, &mut context).map_err(|err| {
+                CliError::ArgumentError(format!(
+                    "Transaction {} in batch {} failed: {:?}",
+                    &txn.header_signature, &batch.header_signature, err
+                ))
+            })?;
+        }
+    }
+
+    Ok(state.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use protobuf::{Message, RepeatedField};
+
+    use proto::setting::Setting;
+    use proto::settings::{SettingProposal, SettingsPayload, SettingsPayload_Action};
+    use proto::transaction::Transaction;
+    use transactions::setting_key_to_address;
+
+    // The authorized keys must be set before any other setting
+    const SETTING: &str = "sawtooth.settings.vote.authorized_keys";
+
+    /// Creates a batch with a proposal for SETTING, which may write only to
+    /// OUTPUTS.
+    fn create_proposal_batch(outputs: Vec<String>) -> Batch {
+        let mut proposal = SettingProposal::new();
+        proposal.set_setting(SETTING.into());
+        proposal.set_value("aaaa".into());
+        let mut payload = SettingsPayload::new();
+        payload.set_action(SettingsPayload_Action::PROPOSE);
+        payload.set_data(proposal.write_to_bytes().unwrap());
+
+        let mut header = TransactionHeader::new();
+        header.set_family_name("sawtooth_settings".into());
+        header.set_family_version("1.0".into());
+        header.set_signer_public_key("aaaa".into());
+        header.set_inputs(RepeatedField::from_vec(vec!["000000".into()]));
+        header.set_outputs(RepeatedField::from_vec(outputs));
+
+        let mut txn = Transaction::new();
+        txn.set_header(header.write_to_bytes().unwrap());
+        txn.set_header_signature("txn".into());
+        txn.set_payload(payload.write_to_bytes().unwrap());
+
+        let mut batch = Batch::new();
+        batch.set_header_signature("batch".into());
+        batch.set_transactions(RepeatedField::from_vec(vec![txn]));
+        batch
+    }
+
+    #[test]
+    fn simulate_settings() {
+        let address = setting_key_to_address(SETTING);
+        let state = simulate(&[create_proposal_batch(vec![address.clone()])]).unwrap();
+
+        let setting: Setting = protobuf::parse_from_bytes(&state[&address]).unwrap();
+        assert_eq!(SETTING, setting.get_entries()[0].get_key());
+        assert_eq!("aaaa", setting.get_entries()[0].get_value());
+    }
+
+    #[test]
+    fn simulate_failures() {
+        // The proposal writes to an address outside its outputs
+        assert!(simulate(&[create_proposal_batch(vec![])]).is_err());
+
+        let mut header = TransactionHeader::new();
+        header.set_family_name("intkey".into());
+        header.set_family_version("1.0".into());
+        let mut txn = Transaction::new();
+        txn.set_header(header.write_to_bytes().unwrap());
+        let mut batch = Batch::new();
+        batch.set_transactions(RepeatedField::from_vec(vec![txn]));
+        assert!(simulate(&[batch]).is_err());
+    }
+}
@@ -0,0 +1,331 @@
+/*
+ * Copyright 2018 Intel Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * ------------------------------------------------------------------------------
+ */
+
+//! The state snapshot container format.
+//!
+//! A snapshot file is laid out like a blockstore backup: the magic bytes
+//! `STSN`, the big-endian `u32` format version and a length-prefixed JSON
+//! `SnapshotHeader`, followed by the body, compressed as described in the
+//! header. The body starts with a block record holding the packed `Block`
+//! whose state the snapshot holds, followed by one entry record per state
+//! entry, in address order. Each record ends with the CRC-32 of its contents.
+//! The body ends with a trailer record holding the number of entries and a
+//! CRC-32 over the contents of every record.
+
+use std::io::{Read, Write};
+
+use crc32fast::Hasher;
+use protobuf;
+use protobuf::Message;
+use serde_json;
+
+use proto::block::Block;
+
+use backup::{
+    body_reader, crc32, read_bytes, read_u32, read_u64, BackupError, BodyWriter, Compression,
+};
+
+pub const SNAPSHOT_MAGIC: &[u8; 4] = b"STSN";
+pub const SNAPSHOT_FORMAT_VERSION: u32 = 1;
+
+const BLOCK_RECORD: u8 = 1;
+const ENTRY_RECORD: u8 = 2;
+const TRAILER_RECORD: u8 = 3;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotHeader {
+    pub format_version: u32,
+    /// The id of the block whose state the snapshot holds
+    pub block_id: String,
+    pub block_num: u64,
+    pub state_root_hash: String,
+    pub compression: Compression,
+}
+
+/// Writes a snapshot: the block, then its state entries in address order.
+pub struct SnapshotWriter<W: Write> {
+    body: BodyWriter<W>,
+    entry_count: u64,
+    hasher: Hasher,
+}
+
+impl<W: Write> SnapshotWriter<W> {
+    pub fn new(mut writer: W, header: &SnapshotHeader, block: &Block) -> Result<Self, BackupError> {
+        let header_bytes = serde_json::to_vec(header)
+            .map_err(|err| BackupError::FormatError(format!("Invalid header: {}", err)))?;
+
+        writer.write_all(SNAPSHOT_MAGIC)?;
+        writer.write_all(&header.format_version.to_be_bytes())?;
+        writer.write_all(&(header_bytes.len() as u32).to_be_bytes())?;
+        writer.write_all(&header_bytes)?;
+
+        let packed = block.write_to_bytes().map_err(|err| {
+            BackupError::FormatError(format!(
+                "Failed to serialize block {}: {}",
+                block.header_signature, err
+            ))
+        })?;
+
+        let mut snapshot_writer = SnapshotWriter {
+            body: BodyWriter::new(writer, header.compression)?,
+            entry_count: 0,
+            hasher: Hasher::new(),
+        };
+        snapshot_writer.write_record(BLOCK_RECORD, &[&packed])?;
+        Ok(snapshot_writer)
+    }
+
+    pub fn write_entry(&mut self, address: &str, value: &[u8]) -> Result<(), BackupError> {
+        self.write_record(ENTRY_RECORD, &[address.as_bytes(), value])?;
+        self.entry_count += 1;
+        Ok(())
+    }
+
+    /// Writes the trailer and flushes any compressed data, returning the
+    /// underlying writer.
+    pub fn finish(mut self) -> Result<W, BackupError> {
+        self.body.write_all(&[TRAILER_RECORD])?;
+        self.body.write_all(&self.entry_count.to_be_bytes())?;
+        self.body.write_all(&self.hasher.finalize().to_be_bytes())?;
+
+        let mut writer = self.body.finish()?;
+        writer.flush()?;
+        Ok(writer)
+    }
+
+    /// Writes a record of the length-prefixed FIELDS and their checksum.
+    fn write_record(&mut self, tag: u8, fields: &[&[u8]]) -> Result<(), BackupError> {
+        let mut record_hasher = Hasher::new();
+        self.body.write_all(&[tag])?;
+        for field in fields {
+            self.body.write_all(&(field.len() as u32).to_be_bytes())?;
+            self.body.write_all(field)?;
+            record_hasher.update(field);
+            self.hasher.update(field);
+        }
+        self.body
+            .write_all(&record_hasher.finalize().to_be_bytes())?;
+        Ok(())
+    }
+}
+
+/// Reads a snapshot, checking each record's checksum and, once the last entry
+/// has been read, the trailer.
+pub struct SnapshotReader<'a> {
+    header: SnapshotHeader,
+    block: Block,
+    body: Box<dyn Read + 'a>,
+    entry_count: u64,
+    hasher: Hasher,
+}
+
+impl<'a> SnapshotReader<'a> {
+    pub fn new<R: Read + 'a>(mut reader: R) -> Result<Self, BackupError> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != SNAPSHOT_MAGIC {
+            return Err(BackupError::FormatError("Not a state snapshot".into()));
+        }
+
+        let format_version = read_u32(&mut reader)?;
+        if format_version != SNAPSHOT_FORMAT_VERSION {
+            return Err(BackupError::FormatError(format!(
+                "Unsupported snapshot format version: {}",
+                format_version
+            )));
+        }
+
+        let header_len = read_u32(&mut reader)?;
+        let header_bytes = read_bytes(&mut reader, header_len)?;
+        let header: SnapshotHeader = serde_json::from_slice(&header_bytes)
+            .map_err(|err| BackupError::FormatError(format!("Invalid header: {}", err)))?;
+
+        let mut snapshot_reader = SnapshotReader {
+            body: body_reader(reader, header.compression)?,
+            header,
+            block: Block::new(),
+            entry_count: 0,
+            hasher: Hasher::new(),
+        };
+
+        if snapshot_reader.read_tag()? != BLOCK_RECORD {
+            return Err(BackupError::FormatError(
+                "Snapshot does not start with a block".into(),
+            ));
+        }
+        let packed = snapshot_reader.read_record(1)?.remove(0);
+        snapshot_reader.block = protobuf::parse_from_bytes(&packed)
+            .map_err(|err| BackupError::FormatError(format!("Failed to parse block: {}", err)))?;
+        Ok(snapshot_reader)
+    }
+
+    pub fn header(&self) -> &SnapshotHeader {
+        &self.header
+    }
+
+    pub fn block(&self) -> &Block {
+        &self.block
+    }
+
+    /// Reads the next state entry, or returns None once the trailer has been
+    /// read and checked.
+    pub fn read_entry(&mut self) -> Result<Option<(String, Vec<u8>)>, BackupError> {
+        match self.read_tag()? {
+            ENTRY_RECORD => {
+                let mut fields = self.read_record(2)?;
+                let value = fields.pop().unwrap_or_default();
+                let address =
+                    String::from_utf8(fields.pop().unwrap_or_default()).map_err(|_| {
+                        BackupError::FormatError(format!(
+                            "Invalid address for entry {} in snapshot",
+                            self.entry_count
+                        ))
+                    })?;
+                self.entry_count += 1;
+                Ok(Some((address, value)))
+            }
+            TRAILER_RECORD => {
+                let entry_count = read_u64(&mut self.body)?;
+                let expected_crc = read_u32(&mut self.body)?;
+                if entry_count != self.entry_count {
+                    return Err(BackupError::FormatError(format!(
+                        "Snapshot trailer lists {} entries, but {} were read",
+                        entry_count, self.entry_count
+                    )));
+                }
+                if self.hasher.clone().finalize() != expected_crc {
+                    return Err(BackupError::ChecksumError(
+                        "Checksum mismatch for snapshot trailer".into(),
+                    ));
+                }
+                Ok(None)
+            }
+            tag => Err(BackupError::FormatError(format!(
+                "Unknown record type in snapshot: {}",
+                tag
+            ))),
+        }
+    }
+
+    fn read_tag(&mut self) -> Result<u8, BackupError> {
+        let mut tag = [0; 1];
+        self.body.read_exact(&mut tag).map_err(|err| {
+            BackupError::FormatError(format!("Snapshot is truncated, missing trailer: {}", err))
+        })?;
+        Ok(tag[0])
+    }
+
+    /// Reads the FIELD_COUNT length-prefixed fields of a record and checks
+    /// its checksum.
+    fn read_record(&mut self, field_count: usize) -> Result<Vec<Vec<u8>>, BackupError> {
+        let mut fields = Vec::with_capacity(field_count);
+        let mut contents = vec![];
+        for _ in 0..field_count {
+            let len = read_u32(&mut self.body)?;
+            let field = read_bytes(&mut self.body, len)?;
+            contents.extend_from_slice(&field);
+            fields.push(field);
+        }
+        if crc32(&contents) != read_u32(&mut self.body)? {
+            return Err(BackupError::ChecksumError(format!(
+                "Checksum mismatch for record {} in snapshot",
+                self.entry_count + 1
+            )));
+        }
+        self.hasher.update(&contents);
+        Ok(fields)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_snapshot(entries: &[(&str, &[u8])], compression: Compression) -> Vec<u8> {
+        let header = SnapshotHeader {
+            format_version: SNAPSHOT_FORMAT_VERSION,
+            block_id: "block-2".into(),
+            block_num: 2,
+            state_root_hash: "ab".repeat(32),
+            compression,
+        };
+        let mut block = Block::new();
+        block.set_header_signature("block-2".into());
+
+        let mut writer = SnapshotWriter::new(vec![], &header, &block).unwrap();
+        for (address, value) in entries {
+            writer.write_entry(address, value).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    fn read_entries(buffer: &[u8]) -> Result<Vec<(String, Vec<u8>)>, BackupError> {
+        let mut reader = SnapshotReader::new(buffer)?;
+        let mut entries = vec![];
+        while let Some(entry) = reader.read_entry()? {
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+
+    #[test]
+    fn snapshot_round_trip() {
+        let entries: &[(&str, &[u8])] = &[("0000aa", b"one"), ("0000ab", b"")];
+        for compression in &[Compression::None, Compression::Gzip, Compression::Zstd] {
+            let buffer = write_snapshot(entries, *compression);
+
+            let reader = SnapshotReader::new(&buffer[..]).unwrap();
+            assert_eq!(2, reader.header().block_num);
+            assert_eq!("block-2", reader.block().header_signature);
+            assert_eq!(
+                vec![
+                    ("0000aa".to_string(), b"one".to_vec()),
+                    ("0000ab".to_string(), vec![]),
+                ],
+                read_entries(&buffer).unwrap()
+            );
+        }
+    }
+
+    /// Asserts that a corrupted entry, a missing trailer and a field length
+    /// past the end of the snapshot are rejected.
+    #[test]
+    fn snapshot_corruption() {
+        let mut buffer = write_snapshot(&[("0000aa", b"one")], Compression::None);
+        let len = buffer.len();
+
+        assert!(read_entries(&buffer[..len - 13]).is_err());
+
+        // Claim an address far larger than the snapshot
+        let mut oversized = buffer[..len - 13].to_vec();
+        oversized.push(ENTRY_RECORD);
+        oversized.extend_from_slice(&u32::MAX.to_be_bytes());
+        oversized.extend_from_slice(b"0000ab");
+        match read_entries(&oversized) {
+            Err(BackupError::FormatError(_)) => (),
+            result => panic!("Expected a format error, got {:?}", result),
+        }
+
+        // The last byte of the entry's value, before its checksum and the
+        // 13 byte trailer
+        buffer[len - 18] ^= 0xff;
+        match read_entries(&buffer) {
+            Err(BackupError::ChecksumError(_)) => (),
+            result => panic!("Expected a checksum error, got {:?}", result),
+        }
+    }
+}
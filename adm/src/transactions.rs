@@ -0,0 +1,304 @@
+/*
+ * Copyright 2018 Intel Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * ------------------------------------------------------------------------------
+ */
+
+//! Builds the signed sawtooth_settings and sawtooth_identity transactions, and
+//! the batches holding them, that sawadm creates.
+
+use std::iter::repeat;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crypto::digest::Digest;
+use crypto::sha2::{Sha256, Sha512};
+use protobuf;
+use protobuf::Message;
+use sawtooth_sdk::signing;
+use sawtooth_sdk::signing::secp256k1::Secp256k1PrivateKey;
+
+use proto::batch::{Batch, BatchHeader};
+use proto::identities::{IdentityPayload, IdentityPayload_IdentityType};
+use proto::identity::{Policy, Policy_Entry, Policy_EntryType, Role};
+use proto::settings::{SettingProposal, SettingsPayload, SettingsPayload_Action};
+use proto::transaction::{Transaction, TransactionHeader};
+
+use err::CliError;
+use key_file::read_private_key_hex;
+
+pub const SETTING_NAMESPACE: &str = "000000";
+const SETTING_MAX_KEY_PARTS: usize = 4;
+const SETTING_ADDRESS_PART_SIZE: usize = 16;
+
+const POLICY_NAMESPACE: &str = "00001d00";
+const ROLE_NAMESPACE: &str = "00001d01";
+const IDENTITY_MAX_KEY_PARTS: usize = 4;
+
+pub const AUTHORIZED_KEYS_SETTING: &str = "sawtooth.settings.vote.authorized_keys";
+pub const ALLOWED_IDENTITY_KEYS_SETTING: &str = "sawtooth.identity.allowed_keys";
+
+static NONCE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Reads a secp256k1 private key from KEY_PATH, prompting for its passphrase
+/// if it is encrypted.
+pub fn read_private_key(key_path: &Path) -> Result<Secp256k1PrivateKey, CliError> {
+    let key_hex = read_private_key_hex(key_path, None)?;
+    Secp256k1PrivateKey::from_hex(&key_hex)
+        .map_err(|err| CliError::ArgumentError(format!("Invalid key in {:?}: {}", key_path, err)))
+}
+
+/// Computes the address of a setting. The key is split on dots into at most
+/// four parts, padded with empty parts, and the first 16 hex characters of
+/// each part's SHA-256 are appended to the settings namespace.
+pub fn setting_key_to_address(key: &str) -> String {
+    let mut address = String::from(SETTING_NAMESPACE);
+    address.push_str(
+        &key.splitn(SETTING_MAX_KEY_PARTS, '.')
+            .chain(repeat(""))
+            .take(SETTING_MAX_KEY_PARTS)
+            .map(|part| short_hash(part, SETTING_ADDRESS_PART_SIZE))
+            .collect::<Vec<_>>()
+            .join(""),
+    );
+    address
+}
+
+pub fn policy_to_address(name: &str) -> String {
+    format!("{}{}", POLICY_NAMESPACE, short_hash(name, 62))
+}
+
+pub fn role_to_address(name: &str) -> String {
+    let mut address = String::from(ROLE_NAMESPACE);
+    address.push_str(
+        &name
+            .splitn(IDENTITY_MAX_KEY_PARTS, '.')
+            .chain(repeat(""))
+            .take(IDENTITY_MAX_KEY_PARTS)
+            .enumerate()
+            .map(|(i, part)| short_hash(part, if i == 0 { 14 } else { 16 }))
+            .collect::<Vec<_>>()
+            .join(""),
+    );
+    address
+}
+
+fn short_hash(s: &str, length: usize) -> String {
+    let mut sha = Sha256::new();
+    sha.input(s.as_bytes());
+    sha.result_str()[..length].to_string()
+}
+
+/// Creates a sawtooth_settings transaction proposing that SETTING be set to
+/// VALUE.
+pub fn create_setting_proposal_txn(
+    signer: &signing::Signer,
+    setting: &str,
+    value: &str,
+) -> Result<Transaction, CliError> {
+    let mut proposal = SettingProposal::new();
+    proposal.set_setting(setting.into());
+    proposal.set_value(value.into());
+    proposal.set_nonce(create_nonce());
+
+    let mut payload = SettingsPayload::new();
+    payload.set_action(SettingsPayload_Action::PROPOSE);
+    payload.set_data(write_message(&proposal)?);
+
+    let proposals_address = setting_key_to_address("sawtooth.settings.vote.proposals");
+    let setting_address = setting_key_to_address(setting);
+    create_txn(
+        signer,
+        "sawtooth_settings",
+        vec![
+            proposals_address.clone(),
+            setting_key_to_address(AUTHORIZED_KEYS_SETTING),
+            setting_key_to_address("sawtooth.settings.vote.approval_threshold"),
+            setting_address.clone(),
+        ],
+        vec![proposals_address, setting_address],
+        write_message(&payload)?,
+    )
+}
+
+/// Creates a sawtooth_identity transaction setting the policy NAME to ENTRIES.
+pub fn create_policy_txn(
+    signer: &signing::Signer,
+    name: &str,
+    entries: Vec<(Policy_EntryType, String)>,
+) -> Result<Transaction, CliError> {
+    let mut policy = Policy::new();
+    policy.set_name(name.into());
+    for (entry_type, key) in entries {
+        let mut entry = Policy_Entry::new();
+        entry.set_field_type(entry_type);
+        entry.set_key(key);
+        policy.mut_entries().push(entry);
+    }
+
+    let mut payload = IdentityPayload::new();
+    payload.set_field_type(IdentityPayload_IdentityType::POLICY);
+    payload.set_data(write_message(&policy)?);
+
+    let policy_address = policy_to_address(name);
+    create_txn(
+        signer,
+        "sawtooth_identity",
+        vec![
+            setting_key_to_address(ALLOWED_IDENTITY_KEYS_SETTING),
+            policy_address.clone(),
+        ],
+        vec![policy_address],
+        write_message(&payload)?,
+    )
+}
+
+/// Creates a sawtooth_identity transaction setting the role NAME to
+/// POLICY_NAME.
+pub fn create_role_txn(
+    signer: &signing::Signer,
+    name: &str,
+    policy_name: &str,
+) -> Result<Transaction, CliError> {
+    let mut role = Role::new();
+    role.set_name(name.into());
+    role.set_policy_name(policy_name.into());
+
+    let mut payload = IdentityPayload::new();
+    payload.set_field_type(IdentityPayload_IdentityType::ROLE);
+    payload.set_data(write_message(&role)?);
+
+    let role_address = role_to_address(name);
+    create_txn(
+        signer,
+        "sawtooth_identity",
+        vec![
+            setting_key_to_address(ALLOWED_IDENTITY_KEYS_SETTING),
+            policy_to_address(policy_name),
+            role_address.clone(),
+        ],
+        vec![role_address],
+        write_message(&payload)?,
+    )
+}
+
+/// Creates a batch of TRANSACTIONS, signed by SIGNER.
+pub fn create_batch(
+    signer: &signing::Signer,
+    transactions: Vec<Transaction>,
+) -> Result<Batch, CliError> {
+    let mut batch_header = BatchHeader::new();
+    batch_header.set_signer_public_key(get_public_key_hex(signer)?);
+    batch_header.set_transaction_ids(protobuf::RepeatedField::from_vec(
+        transactions
+            .iter()
+            .map(|txn| txn.header_signature.clone())
+            .collect(),
+    ));
+    let batch_header_bytes = write_message(&batch_header)?;
+
+    let mut batch = Batch::new();
+    batch.set_header_signature(sign(signer, &batch_header_bytes)?);
+    batch.set_header(batch_header_bytes);
+    batch.set_transactions(protobuf::RepeatedField::from_vec(transactions));
+    Ok(batch)
+}
+
+pub fn get_public_key_hex(signer: &signing::Signer) -> Result<String, CliError> {
+    signer
+        .get_public_key()
+        .map(|public_key| public_key.as_hex())
+        .map_err(|err| CliError::EnvironmentError(format!("Unable to get public key: {}", err)))
+}
+
+fn create_txn(
+    signer: &signing::Signer,
+    family_name: &str,
+    inputs: Vec<String>,
+    outputs: Vec<String>,
+    payload: Vec<u8>,
+) -> Result<Transaction, CliError> {
+    let public_key = get_public_key_hex(signer)?;
+
+    let mut sha = Sha512::new();
+    sha.input(&payload);
+
+    let mut txn_header = TransactionHeader::new();
+    txn_header.set_batcher_public_key(public_key.clone());
+    txn_header.set_family_name(family_name.into());
+    txn_header.set_family_version("1.0".into());
+    txn_header.set_inputs(protobuf::RepeatedField::from_vec(inputs));
+    txn_header.set_outputs(protobuf::RepeatedField::from_vec(outputs));
+    txn_header.set_nonce(create_nonce());
+    txn_header.set_payload_sha512(sha.result_str());
+    txn_header.set_signer_public_key(public_key);
+    let txn_header_bytes = write_message(&txn_header)?;
+
+    let mut txn = Transaction::new();
+    txn.set_header_signature(sign(signer, &txn_header_bytes)?);
+    txn.set_header(txn_header_bytes);
+    txn.set_payload(payload);
+    Ok(txn)
+}
+
+/// Creates a nonce that is unique within this process and unlikely to repeat
+/// across processes.
+fn create_nonce() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() * 1_000_000_000 + u64::from(duration.subsec_nanos()))
+        .unwrap_or(0);
+    format!(
+        "0x{:x}{:04x}",
+        nanos,
+        NONCE_COUNTER.fetch_add(1, Ordering::SeqCst) & 0xffff
+    )
+}
+
+fn sign(signer: &signing::Signer, message: &[u8]) -> Result<String, CliError> {
+    signer
+        .sign(message)
+        .map_err(|err| CliError::EnvironmentError(format!("Unable to sign: {}", err)))
+}
+
+fn write_message<M: Message>(message: &M) -> Result<Vec<u8>, CliError> {
+    message
+        .write_to_bytes()
+        .map_err(|err| CliError::EnvironmentError(format!("Unable to serialize: {}", err)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The addresses match the ones computed by the settings and identity
+    /// transaction processors.
+    #[test]
+    fn addresses() {
+        assert_eq!(
+            "000000a87cb5eafdcca6a8cde0fb0dec1400c5ab274474a6aa82c12840f169a04216b7",
+            setting_key_to_address("sawtooth.settings.vote.authorized_keys")
+        );
+        let address = role_to_address("transactor.batch_signer");
+        assert_eq!(70, address.len());
+        assert!(address.starts_with(ROLE_NAMESPACE));
+        assert_eq!(70, policy_to_address("policy_1").len());
+    }
+
+    #[test]
+    fn nonces_are_unique() {
+        assert_ne!(create_nonce(), create_nonce());
+    }
+}
@@ -17,6 +17,7 @@
 use std;
 
 use protobuf;
+use protobuf::{Message, RepeatedField};
 
 use proto;
 
@@ -55,6 +56,8 @@ pub struct Block {
     pub consensus: Vec<u8>,
     pub header_signature: String,
     pub previous_block_id: String,
+    #[serde(skip)]
+    pub signer_public_key: String,
     pub state_root_hash: String,
 }
 
@@ -79,10 +82,38 @@ impl Block {
                         consensus: Vec::from(block_header.get_consensus()),
                         header_signature: String::from(block.get_header_signature()),
                         previous_block_id: String::from(block_header.get_previous_block_id()),
+                        signer_public_key: String::from(block_header.get_signer_public_key()),
                         state_root_hash: String::from(block_header.get_state_root_hash()),
                     })
             })
     }
+
+    /// Re-encodes the block, including its header, as a protobuf message.
+    pub fn into_proto(self) -> Result<proto::block::Block, Error> {
+        let mut block_header = proto::block::BlockHeader::new();
+        block_header.set_batch_ids(RepeatedField::from_vec(
+            self.batches
+                .iter()
+                .map(|batch| batch.header_signature.clone())
+                .collect(),
+        ));
+        block_header.set_block_num(self.block_num);
+        block_header.set_consensus(self.consensus);
+        block_header.set_previous_block_id(self.previous_block_id);
+        block_header.set_signer_public_key(self.signer_public_key);
+        block_header.set_state_root_hash(self.state_root_hash);
+
+        let mut block = proto::block::Block::new();
+        block.set_header(write_header(&block_header, &self.header_signature)?);
+        block.set_header_signature(self.header_signature);
+        block.set_batches(RepeatedField::from_vec(
+            self.batches
+                .into_iter()
+                .map(Batch::into_proto)
+                .collect::<Result<_, _>>()?,
+        ));
+        Ok(block)
+    }
 }
 
 #[derive(Serialize)]
@@ -90,6 +121,8 @@ pub struct Batch {
     pub header_signature: String,
     pub signer_public_key: String,
     pub transactions: Vec<Transaction>,
+    #[serde(skip)]
+    pub trace: bool,
 }
 
 impl Batch {
@@ -111,9 +144,34 @@ impl Batch {
                         header_signature: String::from(batch.get_header_signature()),
                         signer_public_key: String::from(batch_header.get_signer_public_key()),
                         transactions,
+                        trace: batch.get_trace(),
                     })
             })
     }
+
+    /// Re-encodes the batch, including its header, as a protobuf message.
+    pub fn into_proto(self) -> Result<proto::batch::Batch, Error> {
+        let mut batch_header = proto::batch::BatchHeader::new();
+        batch_header.set_signer_public_key(self.signer_public_key);
+        batch_header.set_transaction_ids(RepeatedField::from_vec(
+            self.transactions
+                .iter()
+                .map(|transaction| transaction.header_signature.clone())
+                .collect(),
+        ));
+
+        let mut batch = proto::batch::Batch::new();
+        batch.set_header(write_header(&batch_header, &self.header_signature)?);
+        batch.set_header_signature(self.header_signature);
+        batch.set_trace(self.trace);
+        batch.set_transactions(RepeatedField::from_vec(
+            self.transactions
+                .into_iter()
+                .map(Transaction::into_proto)
+                .collect::<Result<_, _>>()?,
+        ));
+        Ok(batch)
+    }
 }
 
 #[derive(Serialize)]
@@ -157,4 +215,240 @@ impl Transaction {
                 },
             )
     }
+
+    /// Re-encodes the transaction, including its header, as a protobuf message.
+    pub fn into_proto(self) -> Result<proto::transaction::Transaction, Error> {
+        let mut transaction_header = proto::transaction::TransactionHeader::new();
+        transaction_header.set_batcher_public_key(self.batcher_public_key);
+        transaction_header.set_dependencies(RepeatedField::from_vec(self.dependencies));
+        transaction_header.set_family_name(self.family_name);
+        transaction_header.set_family_version(self.family_version);
+        transaction_header.set_inputs(RepeatedField::from_vec(self.inputs));
+        transaction_header.set_nonce(self.nonce);
+        transaction_header.set_outputs(RepeatedField::from_vec(self.outputs));
+        transaction_header.set_payload_sha512(self.payload_sha512);
+        transaction_header.set_signer_public_key(self.signer_public_key);
+
+        let mut transaction = proto::transaction::Transaction::new();
+        transaction.set_header(write_header(&transaction_header, &self.header_signature)?);
+        transaction.set_header_signature(self.header_signature);
+        transaction.set_payload(self.payload);
+        Ok(transaction)
+    }
+}
+
+/// The full contents of a block, as exported and imported as JSON. Unlike
+/// `Block`, which is what `show` prints, every header field is written, and
+/// binary fields are written as hex, so that the block can be re-encoded.
+#[derive(Serialize, Deserialize)]
+pub struct BlockRecord {
+    pub batches: Vec<BatchRecord>,
+    pub block_num: u64,
+    #[serde(with = "hex_bytes", default)]
+    pub consensus: Vec<u8>,
+    pub header_signature: String,
+    pub previous_block_id: String,
+    pub signer_public_key: String,
+    pub state_root_hash: String,
+}
+
+impl From<Block> for BlockRecord {
+    fn from(block: Block) -> Self {
+        BlockRecord {
+            batches: block.batches.into_iter().map(BatchRecord::from).collect(),
+            block_num: block.block_num,
+            consensus: block.consensus,
+            header_signature: block.header_signature,
+            previous_block_id: block.previous_block_id,
+            signer_public_key: block.signer_public_key,
+            state_root_hash: block.state_root_hash,
+        }
+    }
+}
+
+impl From<BlockRecord> for Block {
+    fn from(record: BlockRecord) -> Self {
+        Block {
+            batches: record.batches.into_iter().map(Batch::from).collect(),
+            block_num: record.block_num,
+            consensus: record.consensus,
+            header_signature: record.header_signature,
+            previous_block_id: record.previous_block_id,
+            signer_public_key: record.signer_public_key,
+            state_root_hash: record.state_root_hash,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BatchRecord {
+    pub header_signature: String,
+    pub signer_public_key: String,
+    pub transactions: Vec<TransactionRecord>,
+    #[serde(default)]
+    pub trace: bool,
+}
+
+impl From<Batch> for BatchRecord {
+    fn from(batch: Batch) -> Self {
+        BatchRecord {
+            header_signature: batch.header_signature,
+            signer_public_key: batch.signer_public_key,
+            transactions: batch
+                .transactions
+                .into_iter()
+                .map(TransactionRecord::from)
+                .collect(),
+            trace: batch.trace,
+        }
+    }
+}
+
+impl From<BatchRecord> for Batch {
+    fn from(record: BatchRecord) -> Self {
+        Batch {
+            header_signature: record.header_signature,
+            signer_public_key: record.signer_public_key,
+            transactions: record
+                .transactions
+                .into_iter()
+                .map(Transaction::from)
+                .collect(),
+            trace: record.trace,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TransactionRecord {
+    pub batcher_public_key: String,
+    pub dependencies: Vec<String>,
+    pub family_name: String,
+    pub family_version: String,
+    pub header_signature: String,
+    pub inputs: Vec<String>,
+    pub nonce: String,
+    pub outputs: Vec<String>,
+    #[serde(with = "hex_bytes", default)]
+    pub payload: Vec<u8>,
+    pub payload_sha512: String,
+    pub signer_public_key: String,
+}
+
+impl From<Transaction> for TransactionRecord {
+    fn from(transaction: Transaction) -> Self {
+        TransactionRecord {
+            batcher_public_key: transaction.batcher_public_key,
+            dependencies: transaction.dependencies,
+            family_name: transaction.family_name,
+            family_version: transaction.family_version,
+            header_signature: transaction.header_signature,
+            inputs: transaction.inputs,
+            nonce: transaction.nonce,
+            outputs: transaction.outputs,
+            payload: transaction.payload,
+            payload_sha512: transaction.payload_sha512,
+            signer_public_key: transaction.signer_public_key,
+        }
+    }
+}
+
+impl From<TransactionRecord> for Transaction {
+    fn from(record: TransactionRecord) -> Self {
+        Transaction {
+            batcher_public_key: record.batcher_public_key,
+            dependencies: record.dependencies,
+            family_name: record.family_name,
+            family_version: record.family_version,
+            header_signature: record.header_signature,
+            inputs: record.inputs,
+            nonce: record.nonce,
+            outputs: record.outputs,
+            payload: record.payload,
+            payload_sha512: record.payload_sha512,
+            signer_public_key: record.signer_public_key,
+        }
+    }
+}
+
+fn write_header<M: Message>(header: &M, header_signature: &str) -> Result<Vec<u8>, Error> {
+    header.write_to_bytes().map_err(|err| {
+        Error::ParseError(format!(
+            "Unable to encode header {}: {}",
+            header_signature, err
+        ))
+    })
+}
+
+/// Serializes binary fields as hex strings.
+mod hex_bytes {
+    use hex;
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex::encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        hex::decode(&encoded).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json;
+
+    /// Blocks exported as JSON should be re-encoded to the same protobuf
+    /// bytes.
+    #[test]
+    fn json_round_trip() {
+        let mut transaction_header = proto::transaction::TransactionHeader::new();
+        transaction_header.set_family_name("intkey".into());
+        transaction_header.set_family_version("1.0".into());
+        transaction_header.set_inputs(RepeatedField::from_vec(vec!["1cf126".into()]));
+        transaction_header.set_outputs(RepeatedField::from_vec(vec!["1cf126".into()]));
+        transaction_header.set_signer_public_key("02abcd".into());
+        let mut transaction = proto::transaction::Transaction::new();
+        transaction.set_header(transaction_header.write_to_bytes().unwrap());
+        transaction.set_header_signature("txn".into());
+        transaction.set_payload(vec![0, 1, 2, 255]);
+
+        let mut batch_header = proto::batch::BatchHeader::new();
+        batch_header.set_signer_public_key("02abcd".into());
+        batch_header.set_transaction_ids(RepeatedField::from_vec(vec!["txn".into()]));
+        let mut batch = proto::batch::Batch::new();
+        batch.set_header(batch_header.write_to_bytes().unwrap());
+        batch.set_header_signature("batch".into());
+        batch.set_transactions(RepeatedField::from_vec(vec![transaction]));
+
+        let mut block_header = proto::block::BlockHeader::new();
+        block_header.set_block_num(1);
+        block_header.set_previous_block_id("genesis".into());
+        block_header.set_signer_public_key("03ef01".into());
+        block_header.set_batch_ids(RepeatedField::from_vec(vec!["batch".into()]));
+        block_header.set_consensus(b"Devmode".to_vec());
+        let mut block = proto::block::Block::new();
+        block.set_header(block_header.write_to_bytes().unwrap());
+        block.set_header_signature("block".into());
+        block.set_batches(RepeatedField::from_vec(vec![batch]));
+
+        let wrapper = Block::try_from(block.clone()).unwrap();
+
+        // Binary fields and the block signer are left out of what show prints
+        let shown = serde_json::to_value(&wrapper).unwrap();
+        assert!(shown.get("consensus").is_none());
+        assert!(shown.get("signer_public_key").is_none());
+        assert!(shown["batches"][0]["transactions"][0]
+            .get("payload")
+            .is_none());
+
+        let json = serde_json::to_string(&BlockRecord::from(wrapper)).unwrap();
+        let record: BlockRecord = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(block, Block::from(record).into_proto().unwrap());
+    }
 }
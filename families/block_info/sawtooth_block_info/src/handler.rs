@@ -27,7 +27,6 @@ cfg_if! {
         use sabre_sdk::TransactionContext;
         use sabre_sdk::TransactionHandler;
         use sabre_sdk::TpProcessRequest;
-        use sabre_sdk::{WasmPtr, execute_entrypoint};
     } else {
         use sawtooth_sdk::messages::processor::TpProcessRequest;
         use sawtooth_sdk::processor::handler::ApplyError;
@@ -194,16 +193,13 @@ impl TransactionHandler for BlockInfoTransactionHandler {
 
 #[cfg(target_arch = "wasm32")]
 // Sabre apply must return a bool
-fn apply(request: &TpProcessRequest, context: &mut TransactionContext) -> Result<bool, ApplyError> {
+pub fn apply(
+    request: &TpProcessRequest,
+    context: &mut TransactionContext,
+) -> Result<bool, ApplyError> {
     let handler = BlockInfoTransactionHandler::new();
     match handler.apply(request, context) {
         Ok(_) => Ok(true),
         Err(err) => Err(err),
     }
 }
-
-#[cfg(target_arch = "wasm32")]
-#[no_mangle]
-pub unsafe fn entrypoint(payload: WasmPtr, signer: WasmPtr, signature: WasmPtr) -> i32 {
-    execute_entrypoint(payload, signer, signature, apply)
-}
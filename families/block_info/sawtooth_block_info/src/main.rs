@@ -17,21 +17,19 @@
 
 #[macro_use]
 extern crate cfg_if;
-extern crate crypto;
-extern crate hex;
-extern crate protobuf;
+extern crate sawtooth_block_info;
 
 cfg_if! {
     if #[cfg(target_arch = "wasm32")] {
-        #[macro_use]
         extern crate sabre_sdk;
+        use sabre_sdk::{execute_entrypoint, WasmPtr};
+        use sawtooth_block_info::handler::apply;
     } else {
         #[macro_use]
         extern crate clap;
         #[macro_use]
         extern crate log;
         extern crate log4rs;
-        extern crate rustc_serialize;
         extern crate sawtooth_sdk;
         use std::process;
         use log::LevelFilter;
@@ -39,16 +37,10 @@ cfg_if! {
         use log4rs::config::{Appender, Config, Root};
         use log4rs::encode::pattern::PatternEncoder;
         use sawtooth_sdk::processor::TransactionProcessor;
-        use handler::BlockInfoTransactionHandler;
+        use sawtooth_block_info::handler::BlockInfoTransactionHandler;
     }
 }
 
-pub mod addressing;
-pub mod handler;
-pub mod payload;
-pub mod protos;
-pub mod state;
-
 #[cfg(not(target_arch = "wasm32"))]
 fn main() {
     let matches = clap_app!(block_info =>
@@ -102,3 +94,9 @@ fn main() {
 
 #[cfg(target_arch = "wasm32")]
 fn main() {}
+
+#[cfg(target_arch = "wasm32")]
+#[no_mangle]
+pub unsafe fn entrypoint(payload: WasmPtr, signer: WasmPtr, signature: WasmPtr) -> i32 {
+    execute_entrypoint(payload, signer, signature, apply)
+}
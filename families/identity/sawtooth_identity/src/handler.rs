@@ -19,10 +19,7 @@ cfg_if! {
     if #[cfg(target_arch = "wasm32")] {
         use protos::identity::{Policy, Role};
         use protos::setting::Setting;
-        use sabre_sdk::{
-            execute_entrypoint, ApplyError, TpProcessRequest, TransactionContext,
-            TransactionHandler, WasmPtr,
-        };
+        use sabre_sdk::{ApplyError, TpProcessRequest, TransactionContext, TransactionHandler};
     } else {
         use sawtooth_sdk::messages::identity::{Policy, Role};
         use sawtooth_sdk::messages::processor::TpProcessRequest;
@@ -40,7 +37,10 @@ use std::iter::repeat;
 
 #[cfg(target_arch = "wasm32")]
 // Sabre apply must return a bool
-fn apply(request: &TpProcessRequest, context: &mut TransactionContext) -> Result<bool, ApplyError> {
+pub fn apply(
+    request: &TpProcessRequest,
+    context: &mut TransactionContext,
+) -> Result<bool, ApplyError> {
     let handler = IdentityTransactionHandler::new();
     match handler.apply(request, context) {
         Ok(_) => Ok(true),
@@ -48,12 +48,6 @@ fn apply(request: &TpProcessRequest, context: &mut TransactionContext) -> Result
     }
 }
 
-#[cfg(target_arch = "wasm32")]
-#[no_mangle]
-pub unsafe fn entrypoint(payload: WasmPtr, signer: WasmPtr, signature: WasmPtr) -> i32 {
-    execute_entrypoint(payload, signer, signature, apply)
-}
-
 // The identity namespace is special: it is not derived from a hash.
 const IDENTITY_NAMESPACE: &str = "00001d";
 const ALLOWED_SIGNER_SETTING: &str = "sawtooth.identity.allowed_keys";
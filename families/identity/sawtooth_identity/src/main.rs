@@ -17,38 +17,39 @@
 
 #[macro_use]
 extern crate cfg_if;
+extern crate sawtooth_identity;
 
 cfg_if! {
-     if #[cfg(target_arch = "wasm32")] {
-         #[macro_use]
-         extern crate sabre_sdk;
-     } else {
+    if #[cfg(target_arch = "wasm32")] {
+        extern crate sabre_sdk;
+        use sabre_sdk::{execute_entrypoint, WasmPtr};
+        use sawtooth_identity::handler::apply;
+    } else {
         #[macro_use]
         extern crate clap;
         #[macro_use]
         extern crate log;
         extern crate log4rs;
-        extern crate rustc_serialize;
         extern crate sawtooth_sdk;
+        use std::process;
         use log::LevelFilter;
         use log4rs::append::console::ConsoleAppender;
         use log4rs::config::{Appender, Config, Root};
         use log4rs::encode::pattern::PatternEncoder;
-        use std::process;
         use sawtooth_sdk::processor::TransactionProcessor;
-        use handler::IdentityTransactionHandler;
+        use sawtooth_identity::handler::IdentityTransactionHandler;
     }
 }
 
-pub mod handler;
-mod protos;
-mod state;
-extern crate crypto;
-extern crate protobuf;
-
 #[cfg(target_arch = "wasm32")]
 fn main() {}
 
+#[cfg(target_arch = "wasm32")]
+#[no_mangle]
+pub unsafe fn entrypoint(payload: WasmPtr, signer: WasmPtr, signature: WasmPtr) -> i32 {
+    execute_entrypoint(payload, signer, signature, apply)
+}
+
 #[cfg(not(target_arch = "wasm32"))]
 fn main() {
     let matches = clap_app!(identity =>
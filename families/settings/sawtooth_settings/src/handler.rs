@@ -21,7 +21,6 @@ cfg_if! {
          use sabre_sdk::TransactionContext;
          use sabre_sdk::TransactionHandler;
          use sabre_sdk::TpProcessRequest;
-         use sabre_sdk::{WasmPtr, execute_entrypoint};
          use protos::setting::{ Setting,
                         Setting_Entry};
      } else {
@@ -46,7 +45,10 @@ use std::iter::repeat;
 
 #[cfg(target_arch = "wasm32")]
 // Sabre apply must return a bool
-fn apply(request: &TpProcessRequest, context: &mut TransactionContext) -> Result<bool, ApplyError> {
+pub fn apply(
+    request: &TpProcessRequest,
+    context: &mut TransactionContext,
+) -> Result<bool, ApplyError> {
     let handler = SettingsTransactionHandler::new();
     match handler.apply(request, context) {
         Ok(_) => Ok(true),
@@ -54,12 +56,6 @@ fn apply(request: &TpProcessRequest, context: &mut TransactionContext) -> Result
     }
 }
 
-#[cfg(target_arch = "wasm32")]
-#[no_mangle]
-pub unsafe fn entrypoint(payload: WasmPtr, signer: WasmPtr, signature: WasmPtr) -> i32 {
-    execute_entrypoint(payload, signer, signature, apply)
-}
-
 // The config namespace is special: it is not derived from a hash.
 const SETTINGS_NAMESPACE: &str = "000000";
 
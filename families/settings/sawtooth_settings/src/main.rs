@@ -17,36 +17,30 @@
 
 #[macro_use]
 extern crate cfg_if;
+extern crate sawtooth_settings;
 
 cfg_if! {
-     if #[cfg(target_arch = "wasm32")] {
-        #[macro_use]
+    if #[cfg(target_arch = "wasm32")] {
         extern crate sabre_sdk;
-     } else {
+        use sabre_sdk::{execute_entrypoint, WasmPtr};
+        use sawtooth_settings::handler::apply;
+    } else {
         #[macro_use]
         extern crate clap;
-        extern crate log4rs;
-        extern crate rustc_serialize;
-        extern crate sawtooth_sdk;
         #[macro_use]
         extern crate log;
+        extern crate log4rs;
+        extern crate sawtooth_sdk;
+        use std::process;
         use log::LevelFilter;
         use log4rs::append::console::ConsoleAppender;
         use log4rs::config::{Appender, Config, Root};
         use log4rs::encode::pattern::PatternEncoder;
-        use std::process;
-
         use sawtooth_sdk::processor::TransactionProcessor;
-
-        use handler::SettingsTransactionHandler;
+        use sawtooth_settings::handler::SettingsTransactionHandler;
     }
 }
 
-pub mod handler;
-mod protos;
-extern crate crypto;
-extern crate protobuf;
-
 #[cfg(not(target_arch = "wasm32"))]
 fn main() {
     let matches = clap_app!(settings =>
@@ -100,3 +94,9 @@ fn main() {
 
 #[cfg(target_arch = "wasm32")]
 fn main() {}
+
+#[cfg(target_arch = "wasm32")]
+#[no_mangle]
+pub unsafe fn entrypoint(payload: WasmPtr, signer: WasmPtr, signature: WasmPtr) -> i32 {
+    execute_entrypoint(payload, signer, signature, apply)
+}
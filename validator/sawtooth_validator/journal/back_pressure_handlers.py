@@ -33,11 +33,17 @@ COLLECTOR = metrics.get_collector(__name__)
 class ClientBatchSubmitBackpressureHandler(Handler):
     """This handler receives a batch list, and accepts it if the system is
     able.  Otherwise it returns a QUEUE_FULL response.
+
+    If batch_quota_fn is given, a batch list is also rejected with QUEUE_FULL
+    if its batches, counted together, would not fit in their signers' quotas,
+    which only affects the clients submitting those signers' batches.
     """
 
-    def __init__(self, whitelist_public_key, queue_info_fn):
+    def __init__(self, whitelist_public_key, queue_info_fn,
+                 batch_quota_fn=None):
         self._whitelist_public_key = whitelist_public_key
         self._queue_info = queue_info_fn
+        self._batch_quota = batch_quota_fn
         self._applying_backpressure = False
 
         self._batches_rejected_count = COLLECTOR.counter(
@@ -45,6 +51,8 @@ class ClientBatchSubmitBackpressureHandler(Handler):
         self._batches_rejected_gauge = COLLECTOR.gauge(
             'backpressure_batches_rejected_gauge', instance=self)
         self._batches_rejected_gauge.set_value(0)
+        self._batches_over_quota_count = COLLECTOR.counter(
+            'backpressure_batches_over_quota_count', instance=self)
 
     def handle(self, connection_id, message_content):
         batch_header = BatchHeader()
@@ -85,4 +93,19 @@ class ClientBatchSubmitBackpressureHandler(Handler):
                 'current depth: %s, limit: %s',
                 pending, limit)
 
+        if self._batch_quota is not None and \
+                not self._batch_quota(message_content.batches):
+            LOGGER.debug(
+                'Rejecting batch list of %s batches: it is over its '
+                'signers\' quotas', len(message_content.batches))
+            self._batches_over_quota_count.inc()
+
+            response = ClientBatchSubmitResponse(
+                status=ClientBatchSubmitResponse.QUEUE_FULL)
+            return HandlerResult(
+                status=HandlerStatus.RETURN,
+                message_out=response,
+                message_type=Message.CLIENT_BATCH_SUBMIT_RESPONSE
+            )
+
         return HandlerResult(status=HandlerStatus.PASS)
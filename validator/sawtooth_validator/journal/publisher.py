@@ -26,6 +26,8 @@ from sawtooth_validator.ffi import PY_LIBRARY, LIBRARY
 from sawtooth_validator.ffi import OwnedPointer
 
 from sawtooth_validator.journal.block_wrapper import BlockWrapper
+from sawtooth_validator.journal.consensus.batch_publisher import \
+    BatchPublisher
 
 LOGGER = logging.getLogger(__name__)
 
@@ -46,6 +48,26 @@ class PendingBatchObserver(metaclass=abc.ABCMeta):
         raise NotImplementedError('PendingBatchObservers must have a '
                                   '"notify_batch_pending" method')
 
+    def notify_batch_evicted(self, batch):
+        """This method will be called when a pending Batch is evicted by the
+        Publisher, because it expired before it could be committed. It does
+        nothing unless overridden.
+
+        Args:
+            batch (Batch): The Batch that has been removed from the Publisher
+        """
+
+    def notify_batch_invalid(self, batch, message):
+        """This method will be called when a pending Batch is removed by the
+        Publisher because it can never be added to a block, such as when it
+        is larger than the block limits allow. It does nothing unless
+        overridden.
+
+        Args:
+            batch (Batch): The Batch that has been removed from the Publisher
+            message (str): Message explaining why the batch is invalid
+        """
+
 
 class IncomingBatchSenderErrorCode(IntEnum):
     Success = 0
@@ -114,6 +136,7 @@ class BlockPublisherErrorCode(IntEnum):
     BlockNotInitialized = 0x04
     BlockEmpty = 0x05
     MissingPredecessor = 0x07
+    QuotaExceeded = 0x08
 
 
 class BlockEmpty(Exception):
@@ -128,6 +151,11 @@ class BlockNotInitialized(Exception):
     """There is no block in progress to finalize."""
 
 
+class QuotaExceeded(Exception):
+    """The batch does not fit in its signer's quota or the pending batch
+    pool."""
+
+
 class MissingPredecessor(Exception):
     """A predecessor was missing"""
 
@@ -146,6 +174,7 @@ class BlockPublisher(OwnedPointer):
                  block_sender,
                  batch_sender,
                  identity_signer,
+                 identity_key_path,
                  data_dir,
                  config_dir,
                  permission_verifier,
@@ -165,7 +194,9 @@ class BlockPublisher(OwnedPointer):
             batch_sender (:obj:`BatchSender`): The BatchSender instance.
             chain_head_lock (:obj:`RLock`): The chain head lock.
             identity_signer (:obj:`Signer`): Cryptographic signer for signing
-                blocks
+                batches sent by consensus
+            identity_key_path (str): path to the private key file used to
+                sign blocks
             data_dir (str): path to location where persistent data for the
                 consensus module can be stored.
             config_dir (str): path to location where configuration can be
@@ -181,6 +212,8 @@ class BlockPublisher(OwnedPointer):
         else:
             chain_head_block = None
 
+        batch_publisher = BatchPublisher(identity_signer, batch_sender)
+
         self._to_exception(PY_LIBRARY.call(
             'block_publisher_new',
             block_store.pointer,
@@ -188,9 +221,9 @@ class BlockPublisher(OwnedPointer):
             ctypes.py_object(transaction_executor),
             state_view_factory.pointer,
             ctypes.py_object(block_sender),
-            ctypes.py_object(batch_sender),
+            ctypes.py_object(batch_publisher),
             ctypes.py_object(chain_head_block),
-            ctypes.py_object(identity_signer),
+            ctypes.c_char_p(identity_key_path.encode()),
             ctypes.py_object(data_dir),
             ctypes.py_object(config_dir),
             ctypes.py_object(permission_verifier),
@@ -223,6 +256,8 @@ class BlockPublisher(OwnedPointer):
             raise BlockEmpty("The block is empty")
         elif res == BlockPublisherErrorCode.MissingPredecessor:
             raise MissingPredecessor("A predecessor was missing")
+        elif res == BlockPublisherErrorCode.QuotaExceeded:
+            raise QuotaExceeded("The batch quota was exceeded")
 
     def start(self):
         sender_ptr = ctypes.c_void_p()
@@ -294,6 +329,19 @@ class BlockPublisher(OwnedPointer):
 
         return has
 
+    def check_batch_quota(self, batches):
+        """Returns whether the batches of a batch list would all fit in their
+        signers' quotas of pending batches and in the pending batch pool.
+        """
+        try:
+            self._py_call(
+                'check_batch_quota',
+                ctypes.py_object(list(batches)))
+        except QuotaExceeded:
+            return False
+
+        return True
+
     def initialize_block(self, block):
         self._call('initialize_block', ctypes.py_object(block))
 
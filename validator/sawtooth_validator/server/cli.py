@@ -30,6 +30,7 @@ from sawtooth_validator.config.validator import merge_validator_config
 from sawtooth_validator.config.validator import ValidatorConfig
 from sawtooth_validator.config.logs import get_log_config
 from sawtooth_validator.server.core import Validator
+from sawtooth_validator.server.keys import get_identity_key_path
 from sawtooth_validator.server.keys import load_identity_signer
 from sawtooth_validator.server.log import init_console_logging
 from sawtooth_validator.server.log import log_configuration
@@ -270,6 +271,7 @@ def main(args):
         path_config.data_dir,
         path_config.config_dir,
         identity_signer,
+        get_identity_key_path(path_config.key_dir, 'validator'),
         validator_config.scheduler,
         validator_config.permissions,
         validator_config.minimum_peer_connectivity,
@@ -130,7 +130,8 @@ def add(
         validator_pb2.Message.CLIENT_BATCH_SUBMIT_REQUEST,
         ClientBatchSubmitBackpressureHandler(
             public_key,
-            block_publisher.pending_batch_info),
+            block_publisher.pending_batch_info,
+            block_publisher.check_batch_quota),
         client_thread_pool)
 
     dispatcher.add_handler(
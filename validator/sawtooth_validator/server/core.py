@@ -83,6 +83,7 @@ class Validator:
                  data_dir,
                  config_dir,
                  identity_signer,
+                 identity_key_path,
                  scheduler_type,
                  permissions,
                  minimum_peer_connectivity,
@@ -119,6 +120,8 @@ class Validator:
             config_dir (str): path to the config directory
             identity_signer (str): cryptographic signer the validator uses for
                 signing
+            identity_key_path (str): path to the private key file the
+                validator signs blocks with
             component_thread_pool_workers (int): number of workers in the
                 component thread pool; defaults to 10.
             network_thread_pool_workers (int): number of workers in the network
@@ -326,6 +329,7 @@ class Validator:
             block_sender=block_sender,
             batch_sender=batch_sender,
             identity_signer=identity_signer,
+            identity_key_path=identity_key_path,
             data_dir=data_dir,
             config_dir=config_dir,
             permission_verifier=permission_verifier,
@@ -24,6 +24,19 @@ from sawtooth_validator.exceptions import LocalConfigurationError
 LOGGER = logging.getLogger(__name__)
 
 
+def get_identity_key_path(key_dir, key_name):
+    """Returns the path of a validator's identity key file.
+
+    Args:
+        key_dir (str): The path to the key directory.
+        key_name (str): The name of the key.
+
+    Returns:
+        str: the path to the private key file
+    """
+    return os.path.join(key_dir, '{}.priv'.format(key_name))
+
+
 def load_identity_signer(key_dir, key_name):
     """Loads a private key from the key directory, based on a validator's
     identity.
@@ -35,7 +48,7 @@ def load_identity_signer(key_dir, key_name):
     Returns:
         Signer: the cryptographic signer for the key
     """
-    key_path = os.path.join(key_dir, '{}.priv'.format(key_name))
+    key_path = get_identity_key_path(key_dir, key_name)
 
     if not os.path.exists(key_path):
         raise LocalConfigurationError(
@@ -52,6 +65,12 @@ def load_identity_signer(key_dir, key_name):
         raise LocalConfigurationError(
             "Could not load key file: {}".format(str(e)))
 
+    # sawadm stores a key encrypted with a passphrase as JSON
+    if private_key_str.startswith('{'):
+        raise LocalConfigurationError(
+            "Key file {} holds an encrypted key, which the validator cannot "
+            "read; decrypt it with 'sawadm key decrypt'".format(key_path))
+
     try:
         private_key = Secp256k1PrivateKey.from_hex(private_key_str)
     except signing.ParseError as e:
@@ -109,6 +109,38 @@ class BatchTracker(ChainObserver,
             self._update_observers(batch.header_signature,
                                    ClientBatchStatus.PENDING)
 
+    def notify_batch_evicted(self, batch):
+        """Removes an evicted Batch id from the pending cache. The batch is
+        no longer known to the validator, so its status becomes UNKNOWN.
+
+        Args:
+            batch (Batch): The evicted batch
+        """
+        with self._lock:
+            self._pending.discard(batch.header_signature)
+            self._batch_info.pop(batch.header_signature, None)
+            self._update_observers(batch.header_signature,
+                                   ClientBatchStatus.UNKNOWN)
+
+    def notify_batch_invalid(self, batch, message):
+        """Adds a Batch id that the publisher found to be invalid to the
+        invalid cache, along with the id of its first transaction and the
+        error message, and removes it from the pending set.
+
+        Args:
+            batch (Batch): The invalid batch
+            message (str): Message explaining why the batch is invalid
+        """
+        invalid_txn_info = {'message': message}
+        if batch.transactions:
+            invalid_txn_info['id'] = batch.transactions[0].header_signature
+
+        with self._lock:
+            self._invalid[batch.header_signature] = [invalid_txn_info]
+            self._pending.discard(batch.header_signature)
+            self._update_observers(batch.header_signature,
+                                   ClientBatchStatus.INVALID)
+
     def get_status(self, batch_id):
         """Returns the status enum for a batch.
 
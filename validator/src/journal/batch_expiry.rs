@@ -0,0 +1,208 @@
+/*
+ * Copyright 2018 Intel Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * ------------------------------------------------------------------------------
+ */
+
+//! The expiry of batches that stay in the pending batch pool too long, such
+//! as those whose dependencies never arrive.
+//!
+//! A pending batch expires once it has been pending for
+//! `sawtooth.publisher.pending_batch_ttl_seconds` seconds, or once
+//! `sawtooth.publisher.pending_batch_ttl_blocks` blocks have been committed
+//! since it arrived. A value of 0, the default, means no limit.
+
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use sawtooth::batch::Batch;
+use sawtooth::state::settings_view::SettingsView;
+
+pub const TTL_SECONDS_SETTING: &str = "sawtooth.publisher.pending_batch_ttl_seconds";
+pub const TTL_BLOCKS_SETTING: &str = "sawtooth.publisher.pending_batch_ttl_blocks";
+
+/// How long a batch may stay pending, where 0 means no limit.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BatchTtl {
+    pub seconds: u64,
+    pub blocks: u64,
+}
+
+impl BatchTtl {
+    pub fn from_settings(settings_view: &SettingsView) -> Self {
+        let get_ttl = |key| {
+            u64::from(
+                settings_view
+                    .get_setting_u32(key, Some(0u32))
+                    .expect("Unable to get value from settings view")
+                    .expect("Failed to return expected default"),
+            )
+        };
+
+        BatchTtl {
+            seconds: get_ttl(TTL_SECONDS_SETTING),
+            blocks: get_ttl(TTL_BLOCKS_SETTING),
+        }
+    }
+}
+
+/// When each pending batch arrived, in wall time and as the number of the
+/// chain head at the time.
+#[derive(Default)]
+pub struct PendingBatchAges {
+    ttl: BatchTtl,
+    block_num: u64,
+    arrivals: HashMap<String, (Instant, u64)>,
+}
+
+impl PendingBatchAges {
+    pub fn set_ttl(&mut self, ttl: BatchTtl) {
+        self.ttl = ttl;
+    }
+
+    pub fn set_block_num(&mut self, block_num: u64) {
+        self.block_num = block_num;
+    }
+
+    /// Records that the batch arrived at NOW, unless it is already known.
+    pub fn add(&mut self, batch_id: &str, now: Instant) {
+        let block_num = self.block_num;
+        self.arrivals
+            .entry(batch_id.to_string())
+            .or_insert((now, block_num));
+    }
+
+    /// Returns the BATCHES, the contents of the pool, that have expired at
+    /// NOW. Batches that are not yet known, such as those put back in the
+    /// pool from an abandoned fork, are recorded as arriving at NOW.
+    pub fn expired<'a, I: IntoIterator<Item = &'a Batch>>(
+        &mut self,
+        batches: I,
+        now: Instant,
+    ) -> Vec<Batch> {
+        let mut expired = vec![];
+        for batch in batches {
+            self.add(&batch.header_signature, now);
+            let (arrived_at, arrived_block_num) = self.arrivals[&batch.header_signature];
+
+            let expired_by_time = self.ttl.seconds > 0
+                && now.duration_since(arrived_at) >= Duration::from_secs(self.ttl.seconds);
+            let expired_by_blocks =
+                self.ttl.blocks > 0 && self.block_num >= arrived_block_num + self.ttl.blocks;
+            if expired_by_time || expired_by_blocks {
+                expired.push(batch.clone());
+            }
+        }
+        expired
+    }
+
+    /// Forgets the batches that are no longer in BATCHES, the contents of the
+    /// pool.
+    pub fn retain<'a, I: IntoIterator<Item = &'a Batch>>(&mut self, batches: I) {
+        let pending: HashSet<&str> = batches
+            .into_iter()
+            .map(|batch| batch.header_signature.as_str())
+            .collect();
+        self.arrivals
+            .retain(|batch_id, _| pending.contains(batch_id.as_str()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_batch(id: &str) -> Batch {
+        Batch {
+            header_signature: id.into(),
+            transactions: vec![],
+            signer_public_key: "signer".into(),
+            transaction_ids: vec![],
+            trace: false,
+            header_bytes: vec![],
+        }
+    }
+
+    fn ids(batches: &[Batch]) -> Vec<&str> {
+        batches
+            .iter()
+            .map(|batch| batch.header_signature.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn expiry_by_time() {
+        let mut ages = PendingBatchAges::default();
+        ages.set_ttl(BatchTtl {
+            seconds: 60,
+            blocks: 0,
+        });
+
+        let start = Instant::now();
+        let pool = vec![create_batch("old"), create_batch("new")];
+        ages.add("old", start);
+        ages.add("new", start + Duration::from_secs(30));
+
+        assert!(ages
+            .expired(&pool, start + Duration::from_secs(59))
+            .is_empty());
+        assert_eq!(
+            ids(&ages.expired(&pool, start + Duration::from_secs(60))),
+            vec!["old"]
+        );
+    }
+
+    #[test]
+    fn expiry_by_blocks() {
+        let mut ages = PendingBatchAges::default();
+        ages.set_ttl(BatchTtl {
+            seconds: 0,
+            blocks: 2,
+        });
+
+        let now = Instant::now();
+        ages.set_block_num(10);
+        ages.add("old", now);
+        ages.set_block_num(11);
+        ages.add("new", now);
+        // Re-adding a batch keeps its first arrival
+        ages.add("old", now);
+
+        let pool = vec![create_batch("old"), create_batch("new")];
+        assert!(ages.expired(&pool, now).is_empty());
+
+        ages.set_block_num(12);
+        assert_eq!(ids(&ages.expired(&pool, now)), vec!["old"]);
+    }
+
+    /// Asserts that batches which left the pool are forgotten, so that they
+    /// count as new if they are put back.
+    #[test]
+    fn retain() {
+        let mut ages = PendingBatchAges::default();
+        ages.set_ttl(BatchTtl {
+            seconds: 0,
+            blocks: 1,
+        });
+
+        let now = Instant::now();
+        ages.add("committed", now);
+        ages.add("pending", now);
+        ages.retain(&[create_batch("pending")]);
+
+        ages.set_block_num(1);
+        let pool = vec![create_batch("committed"), create_batch("pending")];
+        assert_eq!(ids(&ages.expired(&pool, now)), vec!["pending"]);
+    }
+}
@@ -0,0 +1,378 @@
+/*
+ * Copyright 2018 Intel Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * ------------------------------------------------------------------------------
+ */
+
+//! The policies for the order in which pending batches are added to a
+//! candidate block.
+//!
+//! The policy is chosen by the `sawtooth.publisher.batch_ordering` setting:
+//!
+//! * `fifo` (the default) adds batches in the order they arrived, leaving the
+//!   pending batch pool as it is.
+//! * `signer_round_robin` takes one batch from each batch signer in turn, so
+//!   that a signer with many pending batches can not starve the others.
+//! * `family_weight` adds the batches with the highest weight first, where a
+//!   batch's weight is the highest weight of its transactions' families, as
+//!   set in `sawtooth.publisher.batch_family_weights` in the format
+//!   `family:weight,family:weight`. Families that are not listed have a
+//!   weight of 0.
+//!
+//! Under the other policies, a batch is never placed ahead of a pending batch
+//! that holds one of its transactions' dependencies. The policies order the
+//! indexes of the pending batches, so that the batches themselves are only
+//! copied when they have to be moved.
+
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+
+use sawtooth::batch::Batch;
+use sawtooth::state::settings_view::SettingsView;
+
+pub const BATCH_ORDERING_SETTING: &str = "sawtooth.publisher.batch_ordering";
+pub const FAMILY_WEIGHTS_SETTING: &str = "sawtooth.publisher.batch_family_weights";
+
+pub trait BatchOrdering {
+    /// Returns the indexes of BATCHES, given in arrival order, in the order
+    /// the batches should be added to a block; dependencies are handled by
+    /// `reorder_batches`.
+    fn order(&self, batches: &[&Batch]) -> Vec<usize>;
+}
+
+/// Takes the oldest batch of each signer in turn, with the signers in the
+/// order their oldest batches arrived.
+pub struct SignerRoundRobinOrdering;
+
+impl BatchOrdering for SignerRoundRobinOrdering {
+    fn order(&self, batches: &[&Batch]) -> Vec<usize> {
+        let mut signer_queues: HashMap<&str, usize> = HashMap::new();
+        let mut queues: Vec<VecDeque<usize>> = vec![];
+        for (i, batch) in batches.iter().enumerate() {
+            let queue = *signer_queues
+                .entry(batch.signer_public_key.as_str())
+                .or_insert_with(|| {
+                    queues.push(VecDeque::new());
+                    queues.len() - 1
+                });
+            queues[queue].push_back(i);
+        }
+
+        let mut order = Vec::with_capacity(batches.len());
+        while order.len() < batches.len() {
+            for queue in &mut queues {
+                if let Some(i) = queue.pop_front() {
+                    order.push(i);
+                }
+            }
+        }
+        order
+    }
+}
+
+/// Adds the batches with the highest family weight first, and batches of
+/// equal weight in the order they arrived.
+pub struct FamilyWeightOrdering {
+    weights: HashMap<String, u64>,
+}
+
+impl FamilyWeightOrdering {
+    pub fn new(weights: HashMap<String, u64>) -> Self {
+        FamilyWeightOrdering { weights }
+    }
+
+    fn weight(&self, batch: &Batch) -> u64 {
+        batch
+            .transactions
+            .iter()
+            .filter_map(|txn| self.weights.get(&txn.family_name))
+            .cloned()
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+impl BatchOrdering for FamilyWeightOrdering {
+    fn order(&self, batches: &[&Batch]) -> Vec<usize> {
+        // The sort is stable, which keeps batches of equal weight in order
+        let mut order: Vec<usize> = (0..batches.len()).collect();
+        order.sort_by_key(|i| Reverse(self.weight(batches[*i])));
+        order
+    }
+}
+
+/// Returns the ordering chosen by the settings, or None if batches are added
+/// in the order they arrived, because the setting is `fifo`, not set, or not
+/// valid.
+pub fn from_settings(settings_view: &SettingsView) -> Option<Box<dyn BatchOrdering>> {
+    let name = settings_view
+        .get_setting_str(BATCH_ORDERING_SETTING, Some("fifo".into()))
+        .expect("Unable to get value from settings view")
+        .expect("Failed to return expected default");
+
+    match name.as_str() {
+        "fifo" => None,
+        "signer_round_robin" => Some(Box::new(SignerRoundRobinOrdering)),
+        "family_weight" => {
+            let weights = settings_view
+                .get_setting_str(FAMILY_WEIGHTS_SETTING, Some("".into()))
+                .expect("Unable to get value from settings view")
+                .expect("Failed to return expected default");
+            Some(Box::new(FamilyWeightOrdering::new(parse_family_weights(
+                &weights,
+            ))))
+        }
+        _ => {
+            warn!(
+                "Unknown value for {}: {}, using fifo",
+                BATCH_ORDERING_SETTING, name
+            );
+            None
+        }
+    }
+}
+
+/// Orders BATCHES, given in arrival order, with ORDERING, then moves any
+/// batch that would be ahead of a batch it depends on to just after it.
+///
+/// Returns the indexes of the batches to move to the front of the pool, in
+/// order; the batches that are not returned keep their arrival order after
+/// them, so nothing is returned if the order is unchanged.
+pub fn reorder_batches(ordering: &dyn BatchOrdering, batches: &[&Batch]) -> Vec<usize> {
+    let ordered = ordering.order(batches);
+
+    // The position in the ordering of the batch holding each transaction
+    let position_by_txn: HashMap<&str, usize> = ordered
+        .iter()
+        .enumerate()
+        .flat_map(|(position, i)| {
+            batches[*i]
+                .transactions
+                .iter()
+                .map(move |txn| (txn.header_signature.as_str(), position))
+        })
+        .collect();
+
+    // The number of pending batches each batch depends on, and the batches
+    // that depend on each batch, by position
+    let mut dependency_counts = vec![0; ordered.len()];
+    let mut dependents: Vec<Vec<usize>> = vec![vec![]; ordered.len()];
+    for (position, i) in ordered.iter().enumerate() {
+        let dependencies: BTreeSet<usize> = batches[*i]
+            .transactions
+            .iter()
+            .flat_map(|txn| txn.dependencies.iter())
+            .filter_map(|dependency| position_by_txn.get(dependency.as_str()))
+            .cloned()
+            .filter(|other| *other != position)
+            .collect();
+        dependency_counts[position] = dependencies.len();
+        for other in dependencies {
+            dependents[other].push(position);
+        }
+    }
+
+    // Always take the earliest batch, in the ordering, whose dependencies
+    // have all been taken
+    let mut ready: BTreeSet<usize> = (0..ordered.len())
+        .filter(|position| dependency_counts[*position] == 0)
+        .collect();
+    let mut positions = Vec::with_capacity(ordered.len());
+    while let Some(position) = ready.iter().next().cloned() {
+        ready.remove(&position);
+        positions.push(position);
+        for other in &dependents[position] {
+            dependency_counts[*other] -= 1;
+            if dependency_counts[*other] == 0 {
+                ready.insert(*other);
+            }
+        }
+    }
+
+    // Batches with circular dependencies can never be valid, so they are left
+    // at the end for the candidate block to drop
+    if positions.len() < ordered.len() {
+        let taken: BTreeSet<usize> = positions.iter().cloned().collect();
+        positions.extend((0..ordered.len()).filter(|position| !taken.contains(position)));
+    }
+
+    // The batches after the last one that is out of arrival order need not
+    // be moved
+    let mut order: Vec<usize> = positions.into_iter().map(|p| ordered[p]).collect();
+    let mut moved = order.len();
+    while moved > 0 && (moved == order.len() || order[moved - 1] < order[moved]) {
+        moved -= 1;
+    }
+    order.truncate(moved);
+    order
+}
+
+/// Parses family weights in the format `family:weight,family:weight`,
+/// skipping invalid entries.
+fn parse_family_weights(value: &str) -> HashMap<String, u64> {
+    let mut weights = HashMap::new();
+    for entry in value.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let mut parts = entry.splitn(2, ':');
+        match (
+            parts.next(),
+            parts.next().and_then(|weight| weight.trim().parse().ok()),
+        ) {
+            (Some(family), Some(weight)) => {
+                weights.insert(family.trim().to_string(), weight);
+            }
+            _ => warn!(
+                "Invalid entry in {}: {}, expected family:weight",
+                FAMILY_WEIGHTS_SETTING, entry
+            ),
+        }
+    }
+    weights
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use sawtooth::transaction::Transaction;
+
+    fn create_batch(id: &str, signer: &str, family: &str, dependencies: &[&str]) -> Batch {
+        Batch {
+            header_signature: id.into(),
+            transactions: vec![Transaction {
+                header_signature: format!("{}-txn", id),
+                payload: vec![],
+                batcher_public_key: signer.into(),
+                dependencies: dependencies
+                    .iter()
+                    .map(|dependency| format!("{}-txn", dependency))
+                    .collect(),
+                family_name: family.into(),
+                family_version: "1.0".into(),
+                inputs: vec![],
+                outputs: vec![],
+                nonce: "".into(),
+                payload_sha512: "".into(),
+                signer_public_key: signer.into(),
+                header_bytes: vec![],
+            }],
+            signer_public_key: signer.into(),
+            transaction_ids: vec![format!("{}-txn", id)],
+            trace: false,
+            header_bytes: vec![],
+        }
+    }
+
+    /// Returns the ids of BATCHES in the order of the pool after it is
+    /// reordered with ORDERING.
+    fn reorder(ordering: &dyn BatchOrdering, batches: &[Batch]) -> Vec<String> {
+        let batches: Vec<&Batch> = batches.iter().collect();
+        let moved = reorder_batches(ordering, &batches);
+        moved
+            .iter()
+            .cloned()
+            .chain((0..batches.len()).filter(|i| !moved.contains(i)))
+            .map(|i| batches[i].header_signature.clone())
+            .collect()
+    }
+
+    /// Asserts that round robin alternates between signers, in the order
+    /// they first appear, and keeps each signer's batches in order.
+    #[test]
+    fn signer_round_robin() {
+        let batches = vec![
+            create_batch("a1", "alice", "intkey", &[]),
+            create_batch("a2", "alice", "intkey", &[]),
+            create_batch("a3", "alice", "intkey", &[]),
+            create_batch("b1", "bob", "intkey", &[]),
+            create_batch("c1", "carol", "intkey", &[]),
+            create_batch("b2", "bob", "intkey", &[]),
+        ];
+        assert_eq!(
+            reorder(&SignerRoundRobinOrdering, &batches),
+            vec!["a1", "b1", "c1", "a2", "b2", "a3"]
+        );
+
+        // Only the batches up to the last one out of place are moved
+        let batches = [
+            create_batch("a1", "alice", "intkey", &[]),
+            create_batch("a2", "alice", "intkey", &[]),
+            create_batch("b1", "bob", "intkey", &[]),
+            create_batch("c1", "carol", "intkey", &[]),
+        ];
+        assert_eq!(
+            reorder_batches(
+                &SignerRoundRobinOrdering,
+                &batches.iter().collect::<Vec<_>>()
+            ),
+            vec![0, 2, 3]
+        );
+    }
+
+    #[test]
+    fn family_weight() {
+        let weights = parse_family_weights("xo:10, intkey:5,invalid,bad:weight");
+        assert_eq!(weights.len(), 2);
+
+        let batches = vec![
+            create_batch("s1", "alice", "sawtooth_settings", &[]),
+            create_batch("i1", "alice", "intkey", &[]),
+            create_batch("x1", "bob", "xo", &[]),
+            create_batch("i2", "bob", "intkey", &[]),
+        ];
+        assert_eq!(
+            reorder(&FamilyWeightOrdering::new(weights), &batches),
+            vec!["x1", "i1", "i2", "s1"]
+        );
+    }
+
+    /// Asserts that a batch is never placed ahead of a batch it depends on,
+    /// and otherwise keeps its place.
+    #[test]
+    fn dependencies() {
+        let mut weights = HashMap::new();
+        weights.insert("xo".to_string(), 10);
+
+        let batches = vec![
+            create_batch("i1", "alice", "intkey", &[]),
+            create_batch("i2", "alice", "intkey", &[]),
+            create_batch("x1", "bob", "xo", &["i2", "committed"]),
+            create_batch("x2", "bob", "xo", &[]),
+        ];
+        assert_eq!(
+            reorder(&FamilyWeightOrdering::new(weights), &batches),
+            vec!["x2", "i1", "i2", "x1"]
+        );
+
+        // A batch that arrived ahead of its dependency is moved after it
+        let batches = vec![
+            create_batch("i2", "alice", "intkey", &["i1"]),
+            create_batch("i1", "alice", "intkey", &[]),
+        ];
+        assert_eq!(
+            reorder(&SignerRoundRobinOrdering, &batches),
+            vec!["i1", "i2"]
+        );
+
+        // Batches that are already in order are not moved
+        let batches = [
+            create_batch("i1", "alice", "intkey", &[]),
+            create_batch("i2", "bob", "intkey", &["i1"]),
+        ];
+        assert!(reorder_batches(
+            &SignerRoundRobinOrdering,
+            &batches.iter().collect::<Vec<_>>()
+        )
+        .is_empty());
+    }
+}
@@ -0,0 +1,295 @@
+/*
+ * Copyright 2018 Intel Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * ------------------------------------------------------------------------------
+ */
+
+//! Admission control for the pending batch pool.
+//!
+//! Each batch signer may have at most
+//! `sawtooth.publisher.max_pending_batches_per_signer` batches, of at most
+//! `sawtooth.publisher.max_pending_bytes_per_signer` bytes in total, pending
+//! at once, and the pool as a whole may hold at most
+//! `sawtooth.publisher.max_pending_bytes` bytes. A value of 0, the default,
+//! means no limit. Batches are measured with `candidate_block::batch_size`.
+
+use std::collections::HashMap;
+
+use sawtooth::batch::Batch;
+use sawtooth::state::settings_view::SettingsView;
+
+use journal::candidate_block::batch_size;
+
+pub const MAX_BATCHES_PER_SIGNER_SETTING: &str =
+    "sawtooth.publisher.max_pending_batches_per_signer";
+pub const MAX_BYTES_PER_SIGNER_SETTING: &str = "sawtooth.publisher.max_pending_bytes_per_signer";
+pub const MAX_BYTES_SETTING: &str = "sawtooth.publisher.max_pending_bytes";
+
+/// The limits on the pending batch pool, where 0 means no limit.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct QuotaLimits {
+    pub max_batches_per_signer: usize,
+    pub max_bytes_per_signer: usize,
+    pub max_bytes: usize,
+}
+
+impl QuotaLimits {
+    pub fn from_settings(settings_view: &SettingsView) -> Self {
+        let get_limit = |key| {
+            settings_view
+                .get_setting_u32(key, Some(0u32))
+                .expect("Unable to get value from settings view")
+                .expect("Failed to return expected default") as usize
+        };
+
+        QuotaLimits {
+            max_batches_per_signer: get_limit(MAX_BATCHES_PER_SIGNER_SETTING),
+            max_bytes_per_signer: get_limit(MAX_BYTES_PER_SIGNER_SETTING),
+            max_bytes: get_limit(MAX_BYTES_SETTING),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum QuotaError {
+    SignerBatchesExceeded,
+    SignerBytesExceeded,
+    PoolBytesExceeded,
+}
+
+#[derive(Default)]
+struct SignerUsage {
+    batches: usize,
+    bytes: usize,
+}
+
+/// The number and size of the pending batches of each signer, and the size
+/// of the pool, checked against the limits.
+#[derive(Default)]
+pub struct PendingBatchQuotas {
+    limits: QuotaLimits,
+    signers: HashMap<String, SignerUsage>,
+    total_bytes: usize,
+}
+
+impl PendingBatchQuotas {
+    pub fn new(limits: QuotaLimits) -> Self {
+        PendingBatchQuotas {
+            limits,
+            ..Default::default()
+        }
+    }
+
+    pub fn set_limits(&mut self, limits: QuotaLimits) {
+        self.limits = limits;
+    }
+
+    /// Checks whether a batch of BATCH_SIZE bytes from the signer would fit in
+    /// the pool.
+    pub fn check(&self, signer_public_key: &str, batch_size: usize) -> Result<(), QuotaError> {
+        self.check_signer(signer_public_key, 1, batch_size)?;
+        self.check_pool(batch_size)
+    }
+
+    /// Checks whether BATCHES, such as those of a submitted batch list, would
+    /// all fit in the pool together.
+    pub fn check_batches(&self, batches: &[Batch]) -> Result<(), QuotaError> {
+        let mut signers: HashMap<&str, SignerUsage> = HashMap::new();
+        let mut total_bytes = 0;
+        for batch in batches {
+            let batch_size = batch_size(batch);
+            let usage = signers.entry(&batch.signer_public_key).or_default();
+            usage.batches += 1;
+            usage.bytes += batch_size;
+            total_bytes += batch_size;
+        }
+
+        for (signer_public_key, usage) in &signers {
+            self.check_signer(signer_public_key, usage.batches, usage.bytes)?;
+        }
+        self.check_pool(total_bytes)
+    }
+
+    fn check_signer(
+        &self,
+        signer_public_key: &str,
+        added_batches: usize,
+        added_bytes: usize,
+    ) -> Result<(), QuotaError> {
+        let (batches, bytes) = self
+            .signers
+            .get(signer_public_key)
+            .map(|usage| (usage.batches, usage.bytes))
+            .unwrap_or((0, 0));
+
+        if self.limits.max_batches_per_signer > 0
+            && batches + added_batches > self.limits.max_batches_per_signer
+        {
+            return Err(QuotaError::SignerBatchesExceeded);
+        }
+        if self.limits.max_bytes_per_signer > 0
+            && bytes + added_bytes > self.limits.max_bytes_per_signer
+        {
+            return Err(QuotaError::SignerBytesExceeded);
+        }
+        Ok(())
+    }
+
+    fn check_pool(&self, added_bytes: usize) -> Result<(), QuotaError> {
+        if self.limits.max_bytes > 0 && self.total_bytes + added_bytes > self.limits.max_bytes {
+            return Err(QuotaError::PoolBytesExceeded);
+        }
+        Ok(())
+    }
+
+    /// Counts a batch that was added to the pool.
+    pub fn add(&mut self, signer_public_key: &str, batch_size: usize) {
+        let usage = self
+            .signers
+            .entry(signer_public_key.to_string())
+            .or_default();
+        usage.batches += 1;
+        usage.bytes += batch_size;
+        self.total_bytes += batch_size;
+    }
+
+    /// Recounts the usage from BATCHES, the contents of the pool, after
+    /// batches were removed from it.
+    pub fn recount<'a, I: IntoIterator<Item = &'a Batch>>(&mut self, batches: I) {
+        self.signers.clear();
+        self.total_bytes = 0;
+        for batch in batches {
+            self.add(&batch.signer_public_key, batch_size(batch));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates a batch of SIZE bytes, for sizes under 128 bytes.
+    fn create_batch(id: &str, signer: &str, size: usize) -> Batch {
+        let mut batch = Batch {
+            header_signature: id.into(),
+            transactions: vec![],
+            signer_public_key: signer.into(),
+            transaction_ids: vec![],
+            trace: false,
+            header_bytes: vec![],
+        };
+        // The header's field number and length take another 2 bytes
+        batch.header_bytes = vec![0; size - batch_size(&batch) - 2];
+        assert_eq!(batch_size(&batch), size);
+        batch
+    }
+
+    #[test]
+    fn signer_limits() {
+        let mut quotas = PendingBatchQuotas::new(QuotaLimits {
+            max_batches_per_signer: 2,
+            max_bytes_per_signer: 100,
+            max_bytes: 0,
+        });
+
+        quotas.add("alice", 40);
+        assert_eq!(quotas.check("alice", 60), Ok(()));
+        assert_eq!(
+            quotas.check("alice", 61),
+            Err(QuotaError::SignerBytesExceeded)
+        );
+
+        quotas.add("alice", 10);
+        assert_eq!(
+            quotas.check("alice", 1),
+            Err(QuotaError::SignerBatchesExceeded)
+        );
+
+        // One signer's usage does not limit another's
+        assert_eq!(quotas.check("bob", 100), Ok(()));
+    }
+
+    #[test]
+    fn pool_limit() {
+        let mut quotas = PendingBatchQuotas::new(QuotaLimits {
+            max_bytes: 100,
+            ..Default::default()
+        });
+
+        quotas.add("alice", 70);
+        assert_eq!(quotas.check("bob", 30), Ok(()));
+        assert_eq!(quotas.check("bob", 31), Err(QuotaError::PoolBytesExceeded));
+    }
+
+    /// Asserts that the batches of a batch list are counted together, so
+    /// that a list does not fit just because each of its batches would.
+    #[test]
+    fn batch_list_limits() {
+        let mut quotas = PendingBatchQuotas::new(QuotaLimits {
+            max_batches_per_signer: 3,
+            max_bytes_per_signer: 0,
+            max_bytes: 100,
+        });
+        quotas.add("alice", 20);
+
+        let batch_list = vec![
+            create_batch("a1", "alice", 20),
+            create_batch("a2", "alice", 20),
+        ];
+        assert_eq!(quotas.check_batches(&batch_list), Ok(()));
+
+        let batch_list = vec![
+            create_batch("a1", "alice", 10),
+            create_batch("a2", "alice", 10),
+            create_batch("a3", "alice", 10),
+        ];
+        assert_eq!(quotas.check("alice", 10), Ok(()));
+        assert_eq!(
+            quotas.check_batches(&batch_list),
+            Err(QuotaError::SignerBatchesExceeded)
+        );
+
+        let batch_list = vec![
+            create_batch("b1", "bob", 40),
+            create_batch("c1", "carol", 41),
+        ];
+        assert_eq!(
+            quotas.check_batches(&batch_list),
+            Err(QuotaError::PoolBytesExceeded)
+        );
+    }
+
+    /// Asserts that recounting replaces the usage with that of the batches
+    /// still in the pool.
+    #[test]
+    fn recount() {
+        let mut quotas = PendingBatchQuotas::new(QuotaLimits {
+            max_batches_per_signer: 1,
+            max_bytes_per_signer: 0,
+            max_bytes: 100,
+        });
+
+        quotas.add("alice", 50);
+        quotas.add("bob", 50);
+        assert_eq!(quotas.check("carol", 1), Err(QuotaError::PoolBytesExceeded));
+
+        let pool = vec![create_batch("b1", "bob", 50)];
+        quotas.recount(&pool);
+        assert_eq!(quotas.check("alice", 50), Ok(()));
+        assert_eq!(
+            quotas.check("bob", 1),
+            Err(QuotaError::SignerBatchesExceeded)
+        );
+    }
+}
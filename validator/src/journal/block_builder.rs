@@ -0,0 +1,193 @@
+/*
+ * Copyright 2018 Intel Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * ------------------------------------------------------------------------------
+ */
+
+//! Assembles and signs the blocks built by the publisher, without calling
+//! into Python.
+
+use protobuf::{Message, RepeatedField};
+use sawtooth::{batch::Batch, block::Block};
+use sawtooth_sdk::signing;
+use sawtooth_sdk::signing::secp256k1::{Secp256k1Context, Secp256k1PrivateKey};
+use sawtooth_sdk::signing::Context;
+
+use proto::block::BlockHeader;
+
+#[derive(Debug)]
+pub enum BlockBuilderError {
+    SerializationError(protobuf::ProtobufError),
+    SigningError(signing::Error),
+}
+
+impl From<protobuf::ProtobufError> for BlockBuilderError {
+    fn from(err: protobuf::ProtobufError) -> Self {
+        BlockBuilderError::SerializationError(err)
+    }
+}
+
+impl From<signing::Error> for BlockBuilderError {
+    fn from(err: signing::Error) -> Self {
+        BlockBuilderError::SigningError(err)
+    }
+}
+
+/// Signs blocks with the validator's key.
+pub struct BlockSigner {
+    context: Secp256k1Context,
+    private_key: Secp256k1PrivateKey,
+    public_key: String,
+}
+
+impl BlockSigner {
+    pub fn from_hex(private_key_hex: &str) -> Result<Self, signing::Error> {
+        let context = Secp256k1Context::new();
+        let private_key = Secp256k1PrivateKey::from_hex(private_key_hex)?;
+        let public_key = context.get_public_key(&private_key)?.as_hex();
+
+        Ok(BlockSigner {
+            context,
+            private_key,
+            public_key,
+        })
+    }
+
+    /// Returns the hex encoded public key of the signer.
+    pub fn public_key(&self) -> &str {
+        &self.public_key
+    }
+
+    pub fn sign(&self, message: &[u8]) -> Result<String, signing::Error> {
+        self.context.sign(message, &self.private_key)
+    }
+}
+
+/// Collects the contents of a block as it is built.
+pub struct BlockBuilder {
+    block_num: u64,
+    previous_block_id: String,
+    signer_public_key: String,
+    batches: Vec<Batch>,
+    state_root_hash: String,
+    consensus: Vec<u8>,
+}
+
+impl BlockBuilder {
+    pub fn new(block_num: u64, previous_block_id: String, signer_public_key: String) -> Self {
+        BlockBuilder {
+            block_num,
+            previous_block_id,
+            signer_public_key,
+            batches: vec![],
+            state_root_hash: String::new(),
+            consensus: vec![],
+        }
+    }
+
+    pub fn add_batch(&mut self, batch: Batch) {
+        self.batches.push(batch);
+    }
+
+    pub fn batches(&self) -> &[Batch] {
+        &self.batches
+    }
+
+    pub fn set_state_hash(&mut self, state_root_hash: String) {
+        self.state_root_hash = state_root_hash;
+    }
+
+    pub fn set_consensus(&mut self, consensus: Vec<u8>) {
+        self.consensus = consensus;
+    }
+
+    /// Serializes the block header and signs it with SIGNER.
+    pub fn build_block(&self, signer: &BlockSigner) -> Result<Block, BlockBuilderError> {
+        let batch_ids: Vec<String> = self
+            .batches
+            .iter()
+            .map(|batch| batch.header_signature.clone())
+            .collect();
+
+        let mut block_header = BlockHeader::new();
+        block_header.set_block_num(self.block_num);
+        block_header.set_previous_block_id(self.previous_block_id.clone());
+        block_header.set_signer_public_key(self.signer_public_key.clone());
+        block_header.set_batch_ids(RepeatedField::from_vec(batch_ids.clone()));
+        block_header.set_consensus(self.consensus.clone());
+        block_header.set_state_root_hash(self.state_root_hash.clone());
+        let header_bytes = block_header.write_to_bytes()?;
+
+        Ok(Block {
+            header_signature: signer.sign(&header_bytes)?,
+            batches: self.batches.clone(),
+            state_root_hash: self.state_root_hash.clone(),
+            consensus: self.consensus.clone(),
+            batch_ids,
+            signer_public_key: self.signer_public_key.clone(),
+            previous_block_id: self.previous_block_id.clone(),
+            block_num: self.block_num,
+            header_bytes,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use protobuf;
+    use sawtooth_sdk::signing::secp256k1::Secp256k1PublicKey;
+
+    fn create_batch(id: &str) -> Batch {
+        Batch {
+            header_signature: id.into(),
+            transactions: vec![],
+            signer_public_key: "signer".into(),
+            transaction_ids: vec![],
+            trace: false,
+            header_bytes: vec![],
+        }
+    }
+
+    /// Asserts that the built block holds the batches in the order they were
+    /// added, and that its header is signed by the signer.
+    #[test]
+    fn build_block() {
+        let context = Secp256k1Context::new();
+        let private_key = context.new_random_private_key().unwrap();
+        let signer = BlockSigner::from_hex(&private_key.as_hex()).unwrap();
+
+        let mut builder = BlockBuilder::new(3, "block-2".into(), signer.public_key().into());
+        builder.add_batch(create_batch("b1"));
+        builder.add_batch(create_batch("b2"));
+        builder.set_state_hash("state-3".into());
+        builder.set_consensus(b"consensus".to_vec());
+        let block = builder.build_block(&signer).unwrap();
+
+        let block_header: BlockHeader = protobuf::parse_from_bytes(&block.header_bytes).unwrap();
+        assert_eq!(3, block_header.get_block_num());
+        assert_eq!("block-2", block_header.get_previous_block_id());
+        assert_eq!(signer.public_key(), block_header.get_signer_public_key());
+        assert_eq!(&["b1", "b2"], block_header.get_batch_ids());
+        assert_eq!("state-3", block_header.get_state_root_hash());
+        assert_eq!(b"consensus", block_header.get_consensus());
+        assert_eq!(vec!["b1", "b2"], block.batch_ids);
+
+        let public_key = Secp256k1PublicKey::from_hex(signer.public_key()).unwrap();
+        assert!(context
+            .verify(&block.header_signature, &block.header_bytes, &public_key)
+            .unwrap());
+    }
+}
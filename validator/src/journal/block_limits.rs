@@ -0,0 +1,300 @@
+/*
+ * Copyright 2018 Intel Corporation
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ * ------------------------------------------------------------------------------
+ */
+
+//! The limits on the contents of a block.
+//!
+//! A block may hold at most `sawtooth.publisher.max_batches_per_block`
+//! batches, `sawtooth.publisher.max_txns_per_block` transactions and
+//! `sawtooth.publisher.max_block_bytes` bytes of batches, as measured by
+//! `candidate_block::batch_size`. A value of 0, the default, means no limit.
+
+use sawtooth::batch::Batch;
+use sawtooth::state::settings_view::SettingsView;
+
+use journal::candidate_block::batch_size;
+
+pub const MAX_BATCHES_SETTING: &str = "sawtooth.publisher.max_batches_per_block";
+pub const MAX_TXNS_SETTING: &str = "sawtooth.publisher.max_txns_per_block";
+pub const MAX_BYTES_SETTING: &str = "sawtooth.publisher.max_block_bytes";
+
+/// The limits on a block, where 0 means no limit.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BlockLimits {
+    pub max_batches: usize,
+    pub max_txns: usize,
+    pub max_bytes: usize,
+}
+
+impl BlockLimits {
+    pub fn from_settings(settings_view: &SettingsView) -> Self {
+        let get_limit = |key| {
+            settings_view
+                .get_setting_u32(key, Some(0u32))
+                .expect("Unable to get value from settings view")
+                .expect("Failed to return expected default") as usize
+        };
+
+        BlockLimits {
+            max_batches: get_limit(MAX_BATCHES_SETTING),
+            max_txns: get_limit(MAX_TXNS_SETTING),
+            max_bytes: get_limit(MAX_BYTES_SETTING),
+        }
+    }
+
+    /// Whether a block of TXN_COUNT transactions and BYTE_COUNT bytes is over
+    /// the limits.
+    pub fn exceeds_limits(&self, txn_count: usize, byte_count: usize) -> bool {
+        (self.max_txns != 0 && txn_count > self.max_txns)
+            || (self.max_bytes != 0 && byte_count > self.max_bytes)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum BlockLimitError {
+    /// The batch is larger than the limits allow, so it can never be added to
+    /// a block
+    Oversized,
+    /// The block has no room left for the batch
+    Full,
+}
+
+/// The batches, transactions and bytes added to a block, counted against the
+/// limits.
+#[derive(Default)]
+pub struct BlockUsage {
+    limits: BlockLimits,
+    batch_count: usize,
+    txn_count: usize,
+    byte_count: usize,
+    /// Set once a batch does not fit in the block, so that no later batch is
+    /// added ahead of it
+    full: bool,
+}
+
+impl BlockUsage {
+    pub fn new(limits: BlockLimits) -> Self {
+        BlockUsage {
+            limits,
+            ..Default::default()
+        }
+    }
+
+    /// Whether there may be room for another batch.
+    pub fn has_room(&self) -> bool {
+        !self.full
+            && (self.limits.max_batches == 0 || self.batch_count < self.limits.max_batches)
+            && (self.limits.max_txns == 0 || self.txn_count < self.limits.max_txns)
+            && (self.limits.max_bytes == 0 || self.byte_count < self.limits.max_bytes)
+    }
+
+    /// Checks whether BATCH fits in the block after the batches INJECTED
+    /// ahead of it, returning whether the injected batches fit as well. An
+    /// empty block that only has room for the batch is filled without them,
+    /// as it would otherwise never take a batch. Any other block that has no
+    /// room is marked full, unless the batch would not fit in any block.
+    pub fn check(&mut self, injected: &[Batch], batch: &Batch) -> Result<bool, BlockLimitError> {
+        let (txn_count, byte_count) = count(injected.iter().chain(Some(batch)));
+        if !self
+            .limits
+            .exceeds_limits(self.txn_count + txn_count, self.byte_count + byte_count)
+        {
+            return Ok(true);
+        }
+
+        let (txn_count, byte_count) = count(Some(batch));
+        if self.limits.exceeds_limits(txn_count, byte_count) {
+            Err(BlockLimitError::Oversized)
+        } else if self.batch_count == 0 {
+            Ok(false)
+        } else {
+            self.full = true;
+            Err(BlockLimitError::Full)
+        }
+    }
+
+    /// Counts BATCHES, which were checked, as added to the block.
+    pub fn add(&mut self, batches: &[Batch]) {
+        let (txn_count, byte_count) = count(batches);
+        self.batch_count += batches.len();
+        self.txn_count += txn_count;
+        self.byte_count += byte_count;
+    }
+}
+
+fn count<'a, I: IntoIterator<Item = &'a Batch>>(batches: I) -> (usize, usize) {
+    batches
+        .into_iter()
+        .fold((0, 0), |(txn_count, byte_count), batch| {
+            (
+                txn_count + batch.transactions.len(),
+                byte_count + batch_size(batch),
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use sawtooth::transaction::Transaction;
+
+    /// Creates a batch of TXN_COUNT transactions and SIZE bytes, for sizes
+    /// under 128 bytes.
+    fn create_batch(id: &str, txn_count: usize, size: usize) -> Batch {
+        let transactions = (0..txn_count)
+            .map(|i| Transaction {
+                header_signature: format!("{}-{}", id, i),
+                payload: vec![],
+                batcher_public_key: "signer".into(),
+                dependencies: vec![],
+                family_name: "intkey".into(),
+                family_version: "1.0".into(),
+                inputs: vec![],
+                outputs: vec![],
+                nonce: "".into(),
+                payload_sha512: "".into(),
+                signer_public_key: "signer".into(),
+                header_bytes: vec![],
+            })
+            .collect::<Vec<_>>();
+        let mut batch = Batch {
+            header_signature: id.into(),
+            transactions,
+            signer_public_key: "signer".into(),
+            transaction_ids: vec![],
+            trace: false,
+            header_bytes: vec![],
+        };
+        // The header's field number and length take another 2 bytes
+        batch.header_bytes = vec![0; size - batch_size(&batch) - 2];
+        assert_eq!(batch_size(&batch), size);
+        batch
+    }
+
+    #[test]
+    fn exceeds_limits() {
+        let limits = BlockLimits {
+            max_batches: 0,
+            max_txns: 3,
+            max_bytes: 100,
+        };
+        assert!(!limits.exceeds_limits(3, 100));
+        assert!(limits.exceeds_limits(4, 100));
+        assert!(limits.exceeds_limits(3, 101));
+
+        assert!(!BlockLimits::default().exceeds_limits(1 << 30, 1 << 30));
+    }
+
+    /// Asserts that the block is marked full by the first batch that does not
+    /// fit, so that no later batch is added ahead of it.
+    #[test]
+    fn full() {
+        let mut usage = BlockUsage::new(BlockLimits {
+            max_batches: 0,
+            max_txns: 3,
+            max_bytes: 100,
+        });
+
+        let batch = create_batch("b1", 2, 60);
+        assert_eq!(usage.check(&[], &batch), Ok(true));
+        usage.add(&[batch]);
+        assert!(usage.has_room());
+
+        assert_eq!(
+            usage.check(&[], &create_batch("b2", 1, 41)),
+            Err(BlockLimitError::Full)
+        );
+        assert!(!usage.has_room());
+    }
+
+    /// Asserts that a batch too large for any block is reported as such,
+    /// without marking the block full.
+    #[test]
+    fn oversized() {
+        let mut usage = BlockUsage::new(BlockLimits {
+            max_batches: 0,
+            max_txns: 3,
+            max_bytes: 100,
+        });
+
+        assert_eq!(
+            usage.check(&[], &create_batch("b1", 4, 60)),
+            Err(BlockLimitError::Oversized)
+        );
+        assert_eq!(
+            usage.check(&[], &create_batch("b1", 1, 101)),
+            Err(BlockLimitError::Oversized)
+        );
+        assert!(usage.has_room());
+    }
+
+    /// Asserts that batches injected at the start of a block are left out if
+    /// only the first batch fits, so that the block is not full while empty.
+    #[test]
+    fn injected() {
+        let mut usage = BlockUsage::new(BlockLimits {
+            max_batches: 0,
+            max_txns: 1,
+            max_bytes: 0,
+        });
+
+        let injected = [create_batch("i1", 1, 50)];
+        let batch = create_batch("b1", 1, 50);
+        assert_eq!(usage.check(&injected, &batch), Ok(false));
+        assert!(usage.has_room());
+        usage.add(&[batch]);
+
+        assert_eq!(
+            usage.check(&[], &create_batch("b2", 1, 50)),
+            Err(BlockLimitError::Full)
+        );
+
+        let mut usage = BlockUsage::new(BlockLimits {
+            max_batches: 0,
+            max_txns: 2,
+            max_bytes: 0,
+        });
+        assert_eq!(usage.check(&injected, &create_batch("b1", 1, 50)), Ok(true));
+    }
+
+    /// Asserts that counting the batches of a block again, as is done when it
+    /// is summarized, finds the batch that takes it over the limits.
+    #[test]
+    fn recheck() {
+        let limits = BlockLimits {
+            max_batches: 2,
+            max_txns: 0,
+            max_bytes: 100,
+        };
+        let mut usage = BlockUsage::new(limits);
+        let batches = [create_batch("b1", 1, 50), create_batch("b2", 1, 50)];
+        for batch in batches.iter() {
+            assert_eq!(usage.check(&[], batch), Ok(true));
+            usage.add(::std::slice::from_ref(batch));
+        }
+        assert!(!usage.has_room());
+
+        // The same batches, against smaller limits
+        let mut usage = BlockUsage::new(BlockLimits {
+            max_bytes: 99,
+            ..limits
+        });
+        assert_eq!(usage.check(&[], &batches[0]), Ok(true));
+        usage.add(::std::slice::from_ref(&batches[0]));
+        assert_eq!(usage.check(&[], &batches[1]), Err(BlockLimitError::Full));
+    }
+}
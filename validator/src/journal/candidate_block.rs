@@ -18,12 +18,12 @@
 #![allow(unknown_lints)]
 
 use std::collections::HashSet;
+use std::slice;
+use std::sync::Arc;
 
 use cpython;
 use cpython::ObjectProtocol;
-use cpython::PyClone;
-use cpython::PyList;
-use cpython::Python;
+use protobuf::{Message, RepeatedField};
 
 use sawtooth::hashlib::sha256_digest_strs;
 use sawtooth::journal::candidate_block::{
@@ -36,6 +36,9 @@ use sawtooth::state::settings_view::SettingsView;
 use sawtooth::{batch::Batch, block::Block, scheduler::Scheduler, transaction::Transaction};
 
 use crate::py_object_wrapper::PyObjectWrapper;
+use journal::block_builder::{BlockBuilder, BlockSigner};
+use journal::block_limits::{BlockLimitError, BlockLimits, BlockUsage};
+use proto;
 
 use pylogger;
 
@@ -43,10 +46,10 @@ pub struct FFICandidateBlock {
     previous_block: Block,
     commit_store: CommitStore,
     scheduler: Box<dyn Scheduler>,
-    max_batches: usize,
-    block_builder: cpython::PyObject,
+    limits: BlockLimits,
+    block_builder: BlockBuilder,
     batch_injectors: Vec<cpython::PyObject>,
-    identity_signer: cpython::PyObject,
+    block_signer: Arc<BlockSigner>,
     settings_view: SettingsView,
 
     summary: Option<Vec<u8>>,
@@ -56,6 +59,10 @@ pub struct FFICandidateBlock {
     pending_batches: Vec<Batch>,
     pending_batch_ids: HashSet<String>,
     injected_batch_ids: HashSet<String>,
+    usage: BlockUsage,
+    /// Batches that are larger than the block limits allow, which can never
+    /// be added to a block
+    oversized_batches: Vec<Batch>,
 
     committed_txn_cache: TransactionCommitCache,
 }
@@ -70,8 +77,7 @@ impl CandidateBlock for FFICandidateBlock {
     }
 
     fn can_add_batch(&self) -> bool {
-        self.summary.is_none()
-            && (self.max_batches == 0 || self.pending_batches.len() < self.max_batches)
+        self.summary.is_none() && self.usage.has_room()
     }
 
     fn add_batch(&mut self, batch: Batch) {
@@ -123,6 +129,39 @@ impl CandidateBlock for FFICandidateBlock {
                 batches_to_add.append(&mut injected_batches);
             }
 
+            match self.usage.check(&batches_to_add, &batch) {
+                Ok(true) => (),
+                Ok(false) => {
+                    warn!(
+                        "Leaving out injected batches, which leave no room for batch {}",
+                        batch_header_signature.as_str()
+                    );
+                    for injected in batches_to_add.drain(..) {
+                        self.injected_batch_ids.remove(&injected.header_signature);
+                    }
+                }
+                Err(err) => {
+                    for injected in batches_to_add {
+                        self.injected_batch_ids.remove(&injected.header_signature);
+                    }
+                    self.committed_txn_cache.remove_batch(&batch);
+                    match err {
+                        BlockLimitError::Oversized => {
+                            warn!(
+                                "Dropping batch {}, which is larger than the block limits allow",
+                                batch_header_signature.as_str()
+                            );
+                            self.oversized_batches.push(batch);
+                        }
+                        BlockLimitError::Full => debug!(
+                            "Block is full, batch {} will wait for the next block",
+                            batch_header_signature.as_str()
+                        ),
+                    }
+                    return;
+                }
+            }
+
             batches_to_add.push(batch);
 
             {
@@ -133,13 +172,14 @@ impl CandidateBlock for FFICandidateBlock {
                     .collect::<Vec<_>>();
                 if !validation_rule_enforcer::enforce_validation_rules(
                     &self.settings_view,
-                    &self.get_signer_public_key_hex(),
+                    self.block_signer.public_key(),
                     &batches_to_test,
                 ) {
                     return;
                 }
             }
 
+            self.usage.add(&batches_to_add);
             for b in batches_to_add {
                 let batch_id = b.header_signature.clone();
                 self.pending_batches.push(b.clone());
@@ -188,14 +228,9 @@ impl CandidateBlock for FFICandidateBlock {
             .map(|(b_id, _)| b_id)
             .collect();
 
-        let builder = {
-            let gil = Python::acquire_gil();
-            let py = gil.python();
-            self.block_builder.clone_ref(py)
-        };
-
         let mut bad_batches = vec![];
         let mut pending_batches = vec![];
+        let mut block_usage = BlockUsage::new(self.limits);
 
         if self.injected_batch_ids == valid_batch_ids {
             // There only injected batches in this block
@@ -238,50 +273,43 @@ impl CandidateBlock for FFICandidateBlock {
                     );
                     return Ok(None);
                 } else {
-                    let gil = Python::acquire_gil();
-                    let py = gil.python();
-                    let batch_wrapper = PyObjectWrapper::from(batch.clone());
-                    builder
-                        .call_method(py, "add_batch", (batch_wrapper,), None)
-                        .expect("BlockBuilder has no method 'add_batch'");
-                    committed_txn_cache.add_batch(&batch.clone());
+                    // The limits were enforced as batches were added, so this
+                    // only fails if the settings view and the batches disagree
+                    if block_usage.check(&[], &batch).is_err() {
+                        warn!(
+                            "Abandoning block, batch {} takes it over the block limits",
+                            header_signature
+                        );
+                        self.remaining_batches = self
+                            .pending_batches
+                            .iter()
+                            .filter(|b| !self.injected_batch_ids.contains(&b.header_signature))
+                            .cloned()
+                            .collect();
+                        return Ok(None);
+                    }
+
+                    block_usage.add(slice::from_ref(&batch));
+                    committed_txn_cache.add_batch(&batch);
+                    self.block_builder.add_batch(batch);
                 }
             } else {
                 bad_batches.push(batch.clone());
                 debug!("Batch {} invalid, not added to block", header_signature);
             }
         }
-        if execution_results.ending_state_hash.is_none() || self.no_batches_added(&builder) {
-            debug!("Abandoning block, no batches added");
-            return Ok(None);
-        }
-
-        let gil = cpython::Python::acquire_gil();
-        let py = gil.python();
-        builder
-            .call_method(
-                py,
-                "set_state_hash",
-                (execution_results.ending_state_hash,),
-                None,
-            )
-            .expect("BlockBuilder has no method 'set_state_hash'");
-
-        let batch_py_objs = builder
-            .getattr(py, "batches")
-            .expect("BlockBuilder has no attribute 'batches'")
-            .extract::<PyList>(py)
-            .expect("Failed to extract PyList from uncommitted_batches")
-            .iter(py)
-            .map(PyObjectWrapper::new)
-            .collect::<Vec<PyObjectWrapper>>();
-
-        let batches = batch_py_objs
-            .into_iter()
-            .map(Batch::from)
-            .collect::<Vec<Batch>>();
+        let ending_state_hash = match execution_results.ending_state_hash {
+            Some(ref state_hash) if !self.block_builder.batches().is_empty() => state_hash.clone(),
+            _ => {
+                debug!("Abandoning block, no batches added");
+                return Ok(None);
+            }
+        };
+        self.block_builder.set_state_hash(ending_state_hash);
 
-        let batch_ids: Vec<&str> = batches
+        let batch_ids: Vec<&str> = self
+            .block_builder
+            .batches()
             .iter()
             .map(|batch| batch.header_signature.as_str())
             .collect();
@@ -306,22 +334,18 @@ impl CandidateBlock for FFICandidateBlock {
             return self.build_result(None);
         }
 
-        let builder = &self.block_builder;
-        let gil = cpython::Python::acquire_gil();
-        let py = gil.python();
-        builder
-            .getattr(py, "block_header")
-            .expect("BlockBuilder has no attribute 'block_header'")
-            .setattr(py, "consensus", cpython::PyBytes::new(py, consensus_data))
-            .expect("BlockHeader has no attribute 'consensus'");
-
-        self.sign_block(builder);
-
-        self.build_result(Some(
-            builder
-                .call_method(py, "build_block", cpython::NoArgs, None)
-                .expect("BlockBuilder has no method 'build_block'"),
-        ))
+        self.block_builder.set_consensus(consensus_data.to_vec());
+        // The block is abandoned if it can not be built, as it can not be
+        // published without a signature
+        let block = self
+            .block_builder
+            .build_block(&self.block_signer)
+            .map_err(|err| {
+                error!("Failed to build and sign block: {:?}", err);
+                CandidateBlockError::BlockEmpty
+            })?;
+
+        self.build_result(Some(block))
     }
 }
 
@@ -332,27 +356,29 @@ impl FFICandidateBlock {
         commit_store: CommitStore,
         scheduler: Box<dyn Scheduler>,
         committed_txn_cache: TransactionCommitCache,
-        block_builder: cpython::PyObject,
-        max_batches: usize,
+        block_builder: BlockBuilder,
+        limits: BlockLimits,
         batch_injectors: Vec<cpython::PyObject>,
-        identity_signer: cpython::PyObject,
+        block_signer: Arc<BlockSigner>,
         settings_view: SettingsView,
     ) -> Self {
         FFICandidateBlock {
             previous_block,
             commit_store,
             scheduler,
-            max_batches,
+            limits,
             committed_txn_cache,
             block_builder,
             batch_injectors,
-            identity_signer,
+            block_signer,
             settings_view,
             summary: None,
             remaining_batches: vec![],
             pending_batches: vec![],
             pending_batch_ids: HashSet::new(),
             injected_batch_ids: HashSet::new(),
+            usage: BlockUsage::new(limits),
+            oversized_batches: vec![],
         }
     }
 
@@ -360,6 +386,13 @@ impl FFICandidateBlock {
         self.pending_batches.last()
     }
 
+    /// Returns the batches that were dropped because they are larger than the
+    /// block limits allow, so that they can be removed from the pending batch
+    /// pool rather than retried.
+    pub fn take_oversized_batches(&mut self) -> Vec<Batch> {
+        ::std::mem::replace(&mut self.oversized_batches, vec![])
+    }
+
     fn check_batch_dependencies_add_batch(&mut self, batch: &Batch) -> bool {
         for txn in &batch.transactions {
             if self.txn_is_already_committed(txn, &self.committed_txn_cache) {
@@ -453,60 +486,11 @@ impl FFICandidateBlock {
         batches
     }
 
-    fn get_signer_public_key_hex(&self) -> String {
-        let gil = cpython::Python::acquire_gil();
-        let py = gil.python();
-
-        self.identity_signer
-            .call_method(py, "get_public_key", cpython::NoArgs, None)
-            .expect("IdentitySigner has no method 'get_public_key'")
-            .call_method(py, "as_hex", cpython::NoArgs, None)
-            .expect("PublicKey has no method 'as_hex'")
-            .extract(py)
-            .expect("Unable to convert python string to rust")
-    }
-
-    pub fn sign_block(&self, block_builder: &cpython::PyObject) {
-        let gil = cpython::Python::acquire_gil();
-        let py = gil.python();
-        let header_bytes = block_builder
-            .getattr(py, "block_header")
-            .expect("BlockBuilder has no attribute 'block_header'")
-            .call_method(py, "SerializeToString", cpython::NoArgs, None)
-            .unwrap();
-        let signature = self
-            .identity_signer
-            .call_method(py, "sign", (header_bytes,), None)
-            .expect("Signer has no method 'sign'");
-        block_builder
-            .call_method(py, "set_signature", (signature,), None)
-            .expect("BlockBuilder has no method 'set_signature'");
-    }
-
-    fn no_batches_added(&self, builder: &cpython::PyObject) -> bool {
-        let gil = cpython::Python::acquire_gil();
-        let py = gil.python();
-        builder
-            .getattr(py, "batches")
-            .expect("BlockBuilder has no attribute 'batches'")
-            .extract::<cpython::PyList>(py)
-            .unwrap()
-            .len(py)
-            == 0
-    }
-
     fn build_result(
         &self,
-        block: Option<cpython::PyObject>,
+        block: Option<Block>,
     ) -> Result<FinalizeBlockResult, CandidateBlockError> {
         if let Some(last_batch) = self.last_batch().cloned() {
-            let block: Option<Block> = if let Some(py_block) = block {
-                let wrapper = PyObjectWrapper::new(py_block);
-                Some(Block::from(wrapper))
-            } else {
-                None
-            };
-
             Ok(FinalizeBlockResult {
                 block,
                 remaining_batches: self.remaining_batches.clone(),
@@ -522,3 +506,25 @@ impl FFICandidateBlock {
         }
     }
 }
+
+/// Returns the size of a batch serialized as a protobuf message.
+pub fn batch_size(batch: &Batch) -> usize {
+    let mut proto_batch = proto::batch::Batch::new();
+    proto_batch.set_header(batch.header_bytes.clone());
+    proto_batch.set_header_signature(batch.header_signature.clone());
+    proto_batch.set_trace(batch.trace);
+    proto_batch.set_transactions(RepeatedField::from_vec(
+        batch
+            .transactions
+            .iter()
+            .map(|txn| {
+                let mut proto_txn = proto::transaction::Transaction::new();
+                proto_txn.set_header(txn.header_bytes.clone());
+                proto_txn.set_header_signature(txn.header_signature.clone());
+                proto_txn.set_payload(txn.payload.clone());
+                proto_txn
+            })
+            .collect(),
+    ));
+    proto_batch.compute_size() as usize
+}
@@ -15,6 +15,11 @@
  * ------------------------------------------------------------------------------
  */
 
+pub mod batch_expiry;
+pub mod batch_ordering;
+pub mod batch_quotas;
+pub mod block_builder;
+pub mod block_limits;
 pub mod block_manager_ffi;
 pub mod block_validator_ffi;
 pub mod block_wrapper;
@@ -19,13 +19,13 @@
 // allow borrowed box, this is required to use PublisherState trait
 #![allow(clippy::borrowed_box)]
 
-use cpython::{NoArgs, ObjectProtocol, PyClone, PyDict, PyList, PyObject, Python};
+use cpython::{ObjectProtocol, PyClone, PyList, PyObject, Python};
 use std::collections::{HashMap, HashSet};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, SendError, Sender};
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use sawtooth::journal::{
     block_manager::{BlockManager, BlockRef},
@@ -41,19 +41,20 @@ use sawtooth::journal::{
 use sawtooth::state::{settings_view::SettingsView, state_view_factory::StateViewFactory};
 use sawtooth::{batch::Batch, block::Block, execution::execution_platform::ExecutionPlatform};
 
-use ffi::py_import_class;
-use journal::candidate_block::FFICandidateBlock;
+use journal::batch_expiry::{BatchTtl, PendingBatchAges};
+use journal::batch_ordering;
+use journal::batch_quotas::{PendingBatchQuotas, QuotaError, QuotaLimits};
+use journal::block_builder::{BlockBuilder, BlockSigner};
+use journal::block_limits::BlockLimits;
+use journal::candidate_block::{batch_size, FFICandidateBlock};
 use py_object_wrapper::PyObjectWrapper;
 
 const NUM_PUBLISH_COUNT_SAMPLES: usize = 5;
 const INITIAL_PUBLISH_COUNT: usize = 30;
 
-lazy_static! {
-    static ref PY_BLOCK_HEADER_CLASS: PyObject =
-        py_import_class("sawtooth_validator.protobuf.block_pb2", "BlockHeader");
-    static ref PY_BLOCK_BUILDER_CLASS: PyObject =
-        py_import_class("sawtooth_validator.journal.block_builder", "BlockBuilder");
-}
+const OVERSIZED_BATCH_MESSAGE: &str = "Batch is larger than the block limits allow";
+const OVER_QUOTA_BATCH_MESSAGE: &str =
+    "Batch does not fit in its signer's quota or the pending batch pool";
 
 #[derive(Debug)]
 pub enum CancelBlockError {
@@ -65,6 +66,17 @@ pub enum BlockPublisherError {
     UnknownBlock(String),
 }
 
+/// Observes batches that are removed from the pending batch pool without being
+/// committed.
+pub trait BatchEvictionObserver: Send + Sync {
+    /// Called when a batch expired before it could be committed.
+    fn notify_batch_evicted(&self, batch: &Batch);
+
+    /// Called when a batch can never be committed, for the reason given in
+    /// MESSAGE.
+    fn notify_batch_invalid(&self, batch: &Batch, message: &str);
+}
+
 pub struct BlockPublisherState {
     pub transaction_executor: Box<dyn ExecutionPlatform>,
     pub batch_observers: Vec<Box<dyn BatchObserver>>,
@@ -141,10 +153,13 @@ pub struct SyncBlockPublisher {
     state_view_factory: StateViewFactory,
     block_sender: PyObject,
     batch_publisher: PyObject,
-    identity_signer: PyObject,
+    block_signer: Arc<BlockSigner>,
     data_dir: PyObject,
     config_dir: PyObject,
     permission_verifier: PyObject,
+    batch_quotas: Arc<Mutex<PendingBatchQuotas>>,
+    pending_batch_ages: Arc<Mutex<PendingBatchAges>>,
+    batch_eviction_observers: Arc<Vec<Box<dyn BatchEvictionObserver>>>,
 
     exit: Arc<Exit>,
 }
@@ -164,10 +179,13 @@ impl Clone for SyncBlockPublisher {
             state_view_factory: self.state_view_factory.clone(),
             block_sender: self.block_sender.clone_ref(py),
             batch_publisher: self.batch_publisher.clone_ref(py),
-            identity_signer: self.identity_signer.clone_ref(py),
+            block_signer: Arc::clone(&self.block_signer),
             data_dir: self.data_dir.clone_ref(py),
             config_dir: self.config_dir.clone_ref(py),
             permission_verifier: self.permission_verifier.clone_ref(py),
+            batch_quotas: Arc::clone(&self.batch_quotas),
+            pending_batch_ages: Arc::clone(&self.pending_batch_ages),
+            batch_eviction_observers: Arc::clone(&self.batch_eviction_observers),
             exit: Arc::clone(&self.exit),
         }
     }
@@ -191,6 +209,17 @@ impl SyncPublisher for SyncBlockPublisher {
     ) {
         info!("Now building on top of block, {}", chain_head);
         let batches_len = chain_head.batches.len();
+        let block_num = chain_head.block_num;
+        let (quota_limits, batch_ttl) = {
+            let settings_view: SettingsView = self
+                .state_view_factory
+                .create_view(&chain_head.state_root_hash)
+                .expect("Failed to get state view for chain head");
+            (
+                QuotaLimits::from_settings(&settings_view),
+                BatchTtl::from_settings(&settings_view),
+            )
+        };
         state.chain_head(Some(chain_head));
         let mut previous_block_option = None;
         if let (true, Some(previous_block)) = self.is_building_block(state) {
@@ -203,6 +232,21 @@ impl SyncPublisher for SyncBlockPublisher {
             .mut_pending_batches()
             .rebuild(Some(committed_batches), Some(uncommitted_batches));
 
+        self.batch_quotas
+            .lock()
+            .expect("Batch quotas lock was poisoned")
+            .set_limits(quota_limits);
+        {
+            let mut pending_batch_ages = self
+                .pending_batch_ages
+                .lock()
+                .expect("Pending batch ages lock was poisoned");
+            pending_batch_ages.set_ttl(batch_ttl);
+            pending_batch_ages.set_block_num(block_num);
+        }
+        self.recount_pending_batches(state);
+        self.evict_expired_batches(state);
+
         if let Some(previous_block) = previous_block_option {
             if let Err(err) = self.initialize_block(state, &previous_block, false) {
                 error!("Unable to initialize block after canceling: {:?}", err);
@@ -251,8 +295,46 @@ impl SyncPublisher for SyncBlockPublisher {
             .expect("Couldn't check for batch");
 
         if permission_check && !batch_already_committed {
+            // The client was told whether the batch fit when it was submitted,
+            // but other batches may have been received since
+            let batch_size = batch_size(&batch);
+            let mut batch_quotas = self
+                .batch_quotas
+                .lock()
+                .expect("Batch quotas lock was poisoned");
+            if let Err(err) = batch_quotas.check(&batch.signer_public_key, batch_size) {
+                // A batch that is already pending is counted in the quotas, so
+                // it is not over them
+                if state
+                    .pending_batches()
+                    .iter()
+                    .any(|pending| pending.header_signature == batch.header_signature)
+                {
+                    return;
+                }
+
+                debug!(
+                    "Dropping batch {} from {}: {:?}",
+                    batch.header_signature, batch.signer_public_key, err
+                );
+                counter!("publisher.BlockPublisher.batches_over_quota_count", 1);
+                // The observers may take the GIL, which a quota check from the
+                // submission handler holds while it waits for this lock
+                drop(batch_quotas);
+                for observer in self.batch_eviction_observers.iter() {
+                    observer.notify_batch_invalid(&batch, OVER_QUOTA_BATCH_MESSAGE);
+                }
+                return;
+            }
+
             // If the batch is already in the pending queue, don't do anything further
             if state.mut_pending_batches().append(batch.clone()) {
+                batch_quotas.add(&batch.signer_public_key, batch_size);
+                self.pending_batch_ages
+                    .lock()
+                    .expect("Pending batch ages lock was poisoned")
+                    .add(&batch.header_signature, Instant::now());
+
                 // Notify observers
                 for observer in state.batch_observers() {
                     observer.notify_batch_pending(&batch);
@@ -318,40 +400,27 @@ impl SyncPublisher for SyncBlockPublisher {
                 }
             }
         }
-        let mut candidate_block = {
+        let (mut candidate_block, ordering) = {
             let settings_view: SettingsView = self
                 .state_view_factory
                 .create_view(&previous_block.state_root_hash)
                 .expect("Failed to get state view for previous block");
 
-            let max_batches = settings_view
-                .get_setting_u32("sawtooth.publisher.max_batches_per_block", Some(0u32))
-                .expect("Unable to get value from settings view")
-                .expect("Failed to return expected default") as usize;
+            let block_limits = BlockLimits::from_settings(&settings_view);
 
-            let gil = Python::acquire_gil();
-            let py = gil.python();
+            let ordering = batch_ordering::from_settings(&settings_view);
+
+            let batch_injectors = {
+                let gil = Python::acquire_gil();
+                let py = gil.python();
+                self.load_injectors(py, &previous_block.state_root_hash)
+            };
 
-            let public_key = self.get_public_key(py);
-            let batch_injectors = self.load_injectors(py, &previous_block.state_root_hash);
-
-            let kwargs = PyDict::new(py);
-            kwargs
-                .set_item(py, "block_num", previous_block.block_num + 1)
-                .unwrap();
-            kwargs
-                .set_item(py, "previous_block_id", &previous_block.header_signature)
-                .unwrap();
-            kwargs
-                .set_item(py, "signer_public_key", &public_key)
-                .unwrap();
-            let block_header = PY_BLOCK_HEADER_CLASS
-                .call(py, NoArgs, Some(&kwargs))
-                .expect("BlockHeader could not be constructed");
-
-            let block_builder = PY_BLOCK_BUILDER_CLASS
-                .call(py, (block_header,), None)
-                .expect("BlockBuilder could not be constructed");
+            let block_builder = BlockBuilder::new(
+                previous_block.block_num + 1,
+                previous_block.header_signature.clone(),
+                self.block_signer.public_key().to_string(),
+            );
 
             let scheduler = state
                 .transaction_executor()
@@ -360,19 +429,42 @@ impl SyncPublisher for SyncBlockPublisher {
 
             let committed_txn_cache = TransactionCommitCache::new(self.commit_store.clone());
 
-            FFICandidateBlock::new(
-                previous_block.clone(),
-                self.commit_store.clone(),
-                scheduler,
-                committed_txn_cache,
-                block_builder,
-                max_batches,
-                batch_injectors,
-                self.identity_signer.clone_ref(py),
-                settings_view,
+            (
+                FFICandidateBlock::new(
+                    previous_block.clone(),
+                    self.commit_store.clone(),
+                    scheduler,
+                    committed_txn_cache,
+                    block_builder,
+                    block_limits,
+                    batch_injectors,
+                    Arc::clone(&self.block_signer),
+                    settings_view,
+                ),
+                ordering,
             )
         };
 
+        self.evict_expired_batches(state);
+
+        // The pending batches are reordered in place, rather than only as they
+        // are added, because finalizing the block removes every pending batch
+        // up to the last one added to it. Under fifo they are already in order.
+        if let Some(ordering) = ordering {
+            let moved_batches: Vec<Batch> = {
+                let pending_batches: Vec<&Batch> = state.pending_batches().iter().collect();
+                batch_ordering::reorder_batches(&*ordering, &pending_batches)
+                    .into_iter()
+                    .map(|i| pending_batches[i].clone())
+                    .collect()
+            };
+            if !moved_batches.is_empty() {
+                state
+                    .mut_pending_batches()
+                    .rebuild(None, Some(moved_batches));
+            }
+        }
+
         for batch in state.pending_batches().iter() {
             if candidate_block.can_add_batch() {
                 candidate_block.add_batch(batch.clone());
@@ -380,6 +472,7 @@ impl SyncPublisher for SyncBlockPublisher {
                 break;
             }
         }
+        self.remove_oversized_batches(state, candidate_block.take_oversized_batches());
         state.set_candidate_block(Some(Box::new(candidate_block)));
 
         Ok(())
@@ -403,6 +496,7 @@ impl SyncPublisher for SyncBlockPublisher {
                         finalize_result.remaining_batches.clone(),
                         &finalize_result.last_batch,
                     );
+                    self.recount_pending_batches(state);
 
                     let previous_block_id = &state
                         .candidate_block()
@@ -488,6 +582,74 @@ impl SyncBlockPublisher {
             .collect()
     }
 
+    /// Brings the quotas and ages of the pending batches up to date, after
+    /// batches were removed from the pool.
+    fn recount_pending_batches(&self, state: &Box<dyn PublisherState>) {
+        self.batch_quotas
+            .lock()
+            .expect("Batch quotas lock was poisoned")
+            .recount(state.pending_batches().iter());
+        self.pending_batch_ages
+            .lock()
+            .expect("Pending batch ages lock was poisoned")
+            .retain(state.pending_batches().iter());
+    }
+
+    /// Removes the pending batches that have expired, so that batches whose
+    /// dependencies never arrive are not retried forever.
+    fn evict_expired_batches(&self, state: &mut Box<dyn PublisherState>) {
+        let expired = self
+            .pending_batch_ages
+            .lock()
+            .expect("Pending batch ages lock was poisoned")
+            .expired(state.pending_batches().iter(), Instant::now());
+        if expired.is_empty() {
+            return;
+        }
+
+        info!("Evicting {} expired pending batches", expired.len());
+        // Rebuilding the pool without the expired batches keeps the others in
+        // order
+        state
+            .mut_pending_batches()
+            .rebuild(Some(expired.clone()), None);
+        self.recount_pending_batches(state);
+        counter!(
+            "publisher.BlockPublisher.batches_evicted_count",
+            expired.len() as u64
+        );
+
+        for batch in &expired {
+            for observer in self.batch_eviction_observers.iter() {
+                observer.notify_batch_evicted(batch);
+            }
+        }
+    }
+
+    /// Removes the pending batches that are larger than the block limits
+    /// allow, so that they are not retried on every block, and reports them
+    /// as invalid.
+    fn remove_oversized_batches(&self, state: &mut Box<dyn PublisherState>, oversized: Vec<Batch>) {
+        if oversized.is_empty() {
+            return;
+        }
+
+        state
+            .mut_pending_batches()
+            .rebuild(Some(oversized.clone()), None);
+        self.recount_pending_batches(state);
+        counter!(
+            "publisher.BlockPublisher.batches_oversized_count",
+            oversized.len() as u64
+        );
+
+        for batch in &oversized {
+            for observer in self.batch_eviction_observers.iter() {
+                observer.notify_batch_invalid(batch, OVERSIZED_BATCH_MESSAGE);
+            }
+        }
+    }
+
     fn get_block(&self, block_id: &str) -> Result<Block, BlockPublisherError> {
         self.block_manager
             .get(&[block_id])
@@ -529,16 +691,6 @@ impl SyncBlockPublisher {
         block_id
     }
 
-    fn get_public_key(&self, py: Python) -> String {
-        self.identity_signer
-            .call_method(py, "get_public_key", NoArgs, None)
-            .expect("IdentitySigner has no method get_public_key")
-            .call_method(py, "as_hex", NoArgs, None)
-            .expect("PublicKey object as no method as_hex")
-            .extract::<String>(py)
-            .unwrap()
-    }
-
     fn is_building_block(&self, state: &mut Box<dyn PublisherState>) -> (bool, Option<Block>) {
         if let Some(ref candidate_block) = state.candidate_block() {
             let previous = self
@@ -554,6 +706,7 @@ impl SyncBlockPublisher {
 #[derive(Clone)]
 pub struct BlockPublisher {
     pub publisher: Box<dyn SyncPublisher>,
+    batch_quotas: Arc<Mutex<PendingBatchQuotas>>,
 }
 
 impl BlockPublisher {
@@ -566,11 +719,12 @@ impl BlockPublisher {
         block_sender: PyObject,
         batch_publisher: PyObject,
         chain_head: Option<Block>,
-        identity_signer: PyObject,
+        block_signer: BlockSigner,
         data_dir: PyObject,
         config_dir: PyObject,
         permission_verifier: PyObject,
         batch_observers: Vec<Box<dyn BatchObserver>>,
+        batch_eviction_observers: Vec<Box<dyn BatchEvictionObserver>>,
         batch_injector_factory: PyObject,
     ) -> Self {
         let state: Arc<RwLock<Box<dyn PublisherState>>> =
@@ -582,6 +736,8 @@ impl BlockPublisher {
                 PendingBatchesPool::new(NUM_PUBLISH_COUNT_SAMPLES, INITIAL_PUBLISH_COUNT),
             ))));
 
+        let batch_quotas = Arc::new(Mutex::new(PendingBatchQuotas::default()));
+
         let publisher = SyncBlockPublisher {
             state,
             commit_store,
@@ -589,16 +745,20 @@ impl BlockPublisher {
             state_view_factory,
             block_sender,
             batch_publisher,
-            identity_signer,
+            block_signer: Arc::new(block_signer),
             data_dir,
             config_dir,
             permission_verifier,
             batch_injector_factory,
+            batch_quotas: Arc::clone(&batch_quotas),
+            pending_batch_ages: Arc::new(Mutex::new(PendingBatchAges::default())),
+            batch_eviction_observers: Arc::new(batch_eviction_observers),
             exit: Arc::new(Exit::new()),
         };
 
         BlockPublisher {
             publisher: Box::new(publisher),
+            batch_quotas,
         }
     }
 
@@ -690,6 +850,15 @@ impl BlockPublisher {
             .expect("RwLock was poisoned during a write lock");
         state.pending_batches().contains(batch_id)
     }
+
+    /// Checks whether BATCHES, a submitted batch list, would fit in their
+    /// signers' quotas and the pool, without taking the publisher's lock.
+    pub fn check_batch_quota(&self, batches: &[Batch]) -> Result<(), QuotaError> {
+        self.batch_quotas
+            .lock()
+            .expect("Batch quotas lock was poisoned")
+            .check_batches(batches)
+    }
 }
 
 /// This queue keeps track of the batch ids so that components on the edge
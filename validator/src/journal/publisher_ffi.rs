@@ -16,6 +16,7 @@
  */
 use py_ffi;
 use std::ffi::CStr;
+use std::fs;
 use std::mem;
 use std::os::raw::{c_char, c_void};
 use std::slice;
@@ -28,15 +29,8 @@ use sawtooth::{batch::Batch, block::Block};
 
 use crate::py_object_wrapper::PyObjectWrapper;
 use execution::py_executor::PyExecutor;
-use ffi::py_import_class;
-use journal::publisher::{BlockPublisher, IncomingBatchSender};
-
-lazy_static! {
-    static ref PY_BATCH_PUBLISHER_CLASS: PyObject = py_import_class(
-        "sawtooth_validator.journal.consensus.batch_publisher",
-        "BatchPublisher"
-    );
-}
+use journal::block_builder::BlockSigner;
+use journal::publisher::{BatchEvictionObserver, BlockPublisher, IncomingBatchSender};
 
 #[repr(u32)]
 #[derive(Debug)]
@@ -48,6 +42,7 @@ pub enum ErrorCode {
     BlockNotInitialized = 0x04,
     BlockEmpty = 0x05,
     MissingPredecessor = 0x07,
+    QuotaExceeded = 0x08,
 }
 
 macro_rules! check_null {
@@ -63,9 +58,9 @@ pub unsafe extern "C" fn block_publisher_new(
     transaction_executor_ptr: *mut py_ffi::PyObject,
     state_view_factory_ptr: *const c_void,
     block_sender_ptr: *mut py_ffi::PyObject,
-    batch_sender_ptr: *mut py_ffi::PyObject,
+    batch_publisher_ptr: *mut py_ffi::PyObject,
     chain_head_ptr: *mut py_ffi::PyObject,
-    identity_signer_ptr: *mut py_ffi::PyObject,
+    identity_key_path: *const c_char,
     data_dir_ptr: *mut py_ffi::PyObject,
     config_dir_ptr: *mut py_ffi::PyObject,
     permission_verifier_ptr: *mut py_ffi::PyObject,
@@ -79,9 +74,9 @@ pub unsafe extern "C" fn block_publisher_new(
         transaction_executor_ptr,
         state_view_factory_ptr,
         block_sender_ptr,
-        batch_sender_ptr,
+        batch_publisher_ptr,
         chain_head_ptr,
-        identity_signer_ptr,
+        identity_key_path,
         data_dir_ptr,
         config_dir_ptr,
         permission_verifier_ptr,
@@ -89,6 +84,18 @@ pub unsafe extern "C" fn block_publisher_new(
         batch_injector_factory_ptr
     );
 
+    let identity_key_path = match CStr::from_ptr(identity_key_path).to_str() {
+        Ok(identity_key_path) => identity_key_path,
+        Err(_) => return ErrorCode::InvalidInput,
+    };
+    let block_signer = match fs::read_to_string(identity_key_path)
+        .ok()
+        .and_then(|private_key_hex| BlockSigner::from_hex(private_key_hex.trim()).ok())
+    {
+        Some(block_signer) => block_signer,
+        None => return ErrorCode::InvalidInput,
+    };
+
     let py = Python::assume_gil_acquired();
 
     let commit_store = (*(commit_store_ptr as *mut CommitStore)).clone();
@@ -98,9 +105,8 @@ pub unsafe extern "C" fn block_publisher_new(
         .as_ref()
         .unwrap();
     let block_sender = PyObject::from_borrowed_ptr(py, block_sender_ptr);
-    let batch_sender = PyObject::from_borrowed_ptr(py, batch_sender_ptr);
+    let batch_publisher = PyObject::from_borrowed_ptr(py, batch_publisher_ptr);
     let chain_head = PyObject::from_borrowed_ptr(py, chain_head_ptr);
-    let identity_signer = PyObject::from_borrowed_ptr(py, identity_signer_ptr);
     let data_dir = PyObject::from_borrowed_ptr(py, data_dir_ptr);
     let config_dir = PyObject::from_borrowed_ptr(py, config_dir_ptr);
     let permission_verifier = PyObject::from_borrowed_ptr(py, permission_verifier_ptr);
@@ -114,19 +120,19 @@ pub unsafe extern "C" fn block_publisher_new(
         Some(Block::from(wrapped_chain_head))
     };
 
-    let batch_observers = if let Ok(py_list) = batch_observers.extract::<PyList>(py) {
-        let mut res: Vec<Box<dyn BatchObserver>> = Vec::with_capacity(py_list.len(py));
-        py_list
-            .iter(py)
-            .for_each(|pyobj| res.push(Box::new(PyBatchObserver::new(pyobj))));
-        res
-    } else {
-        return ErrorCode::InvalidInput;
-    };
-
-    let batch_publisher = PY_BATCH_PUBLISHER_CLASS
-        .call(py, (identity_signer.clone_ref(py), batch_sender), None)
-        .expect("Unable to create BatchPublisher");
+    let (batch_observers, batch_eviction_observers) =
+        if let Ok(py_list) = batch_observers.extract::<PyList>(py) {
+            let mut res: Vec<Box<dyn BatchObserver>> = Vec::with_capacity(py_list.len(py));
+            let mut eviction_res: Vec<Box<dyn BatchEvictionObserver>> =
+                Vec::with_capacity(py_list.len(py));
+            py_list.iter(py).for_each(|pyobj| {
+                eviction_res.push(Box::new(PyBatchObserver::new(pyobj.clone_ref(py))));
+                res.push(Box::new(PyBatchObserver::new(pyobj)));
+            });
+            (res, eviction_res)
+        } else {
+            return ErrorCode::InvalidInput;
+        };
 
     let publisher = BlockPublisher::new(
         commit_store,
@@ -139,11 +145,12 @@ pub unsafe extern "C" fn block_publisher_new(
         block_sender,
         batch_publisher,
         chain_head,
-        identity_signer,
+        block_signer,
         data_dir,
         config_dir,
         permission_verifier,
         batch_observers,
+        batch_eviction_observers,
         batch_injector_factory,
     );
 
@@ -394,6 +401,30 @@ pub unsafe extern "C" fn block_publisher_has_batch(
     ErrorCode::Success
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn block_publisher_check_batch_quota(
+    publisher: *mut c_void,
+    batches: *mut py_ffi::PyObject,
+) -> ErrorCode {
+    check_null!(publisher, batches);
+    let gil = Python::acquire_gil();
+    let py = gil.python();
+
+    let py_batches = PyObject::from_borrowed_ptr(py, batches);
+    let batches: Vec<Batch> = match py_batches.extract::<PyList>(py) {
+        Ok(py_list) => py_list
+            .iter(py)
+            .map(|batch| Batch::from(PyObjectWrapper::new(batch)))
+            .collect(),
+        Err(_) => return ErrorCode::InvalidInput,
+    };
+
+    match (*(publisher as *mut BlockPublisher)).check_batch_quota(&batches) {
+        Ok(_) => ErrorCode::Success,
+        Err(_) => ErrorCode::QuotaExceeded,
+    }
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn block_publisher_cancel_block(publisher: *mut c_void) -> ErrorCode {
     check_null!(publisher);
@@ -412,6 +443,14 @@ impl PyBatchObserver {
     fn new(py_batch_observer: PyObject) -> Self {
         PyBatchObserver { py_batch_observer }
     }
+
+    /// Whether the observer has METHOD. Only notify_batch_pending is
+    /// required, so that observers without the eviction methods still work.
+    fn has_method(&self, py: Python, method: &str) -> bool {
+        self.py_batch_observer
+            .hasattr(py, method)
+            .expect("Unable to check BatchObserver for a method")
+    }
 }
 
 impl BatchObserver for PyBatchObserver {
@@ -424,3 +463,29 @@ impl BatchObserver for PyBatchObserver {
             .expect("BatchObserver has no method notify_batch_pending");
     }
 }
+
+impl BatchEvictionObserver for PyBatchObserver {
+    fn notify_batch_evicted(&self, batch: &Batch) {
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+        if !self.has_method(py, "notify_batch_evicted") {
+            return;
+        }
+        let batch_wrapper = PyObjectWrapper::from(batch.clone());
+        self.py_batch_observer
+            .call_method(py, "notify_batch_evicted", (batch_wrapper,), None)
+            .expect("BatchObserver has no method notify_batch_evicted");
+    }
+
+    fn notify_batch_invalid(&self, batch: &Batch, message: &str) {
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+        if !self.has_method(py, "notify_batch_invalid") {
+            return;
+        }
+        let batch_wrapper = PyObjectWrapper::from(batch.clone());
+        self.py_batch_observer
+            .call_method(py, "notify_batch_invalid", (batch_wrapper, message), None)
+            .expect("BatchObserver has no method notify_batch_invalid");
+    }
+}
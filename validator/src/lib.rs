@@ -27,6 +27,7 @@ extern crate log;
 #[macro_use]
 extern crate metrics;
 extern crate sawtooth;
+extern crate sawtooth_sdk;
 
 // exported modules
 pub(crate) mod consensus;
@@ -15,6 +15,8 @@
 import unittest
 
 from sawtooth_validator.protobuf import batch_pb2
+from sawtooth_validator.protobuf.client_batch_submit_pb2 \
+    import ClientBatchStatus
 from sawtooth_validator.protobuf import transaction_pb2
 from sawtooth_validator.state.batch_tracker import BatchTracker
 
@@ -47,6 +49,40 @@ class BatchTrackerTest(unittest.TestCase):
         self.assertEqual(1, len(more_invalid_info))
         self.assertEqual("bad_txn", more_invalid_info[0]["id"])
 
+    def test_evicted_batch(self):
+        """Test that an evicted batch is no longer reported as pending.
+        """
+        batch_tracker = BatchTracker(batch_committed=lambda batch_id: False)
+
+        batch = make_batch("evicted_batch", "evicted_txn")
+        batch_tracker.notify_batch_pending(batch)
+        self.assertEqual(
+            ClientBatchStatus.PENDING,
+            batch_tracker.get_status("evicted_batch"))
+
+        batch_tracker.notify_batch_evicted(batch)
+        self.assertEqual(
+            ClientBatchStatus.UNKNOWN,
+            batch_tracker.get_status("evicted_batch"))
+
+    def test_invalid_batch(self):
+        """Test that a batch the publisher found to be invalid is reported as
+        invalid, with the publisher's message.
+        """
+        batch_tracker = BatchTracker(batch_committed=lambda batch_id: False)
+
+        batch = make_batch("oversized_batch", "oversized_txn")
+        batch_tracker.notify_batch_pending(batch)
+        batch_tracker.notify_batch_invalid(batch, "Too large")
+        self.assertEqual(
+            ClientBatchStatus.INVALID,
+            batch_tracker.get_status("oversized_batch"))
+
+        invalid_info = batch_tracker.get_invalid_txn_info("oversized_batch")
+        self.assertEqual(1, len(invalid_info))
+        self.assertEqual("oversized_txn", invalid_info[0]["id"])
+        self.assertEqual("Too large", invalid_info[0]["message"])
+
 
 def make_batch(batch_id, txn_id):
     transaction = transaction_pb2.Transaction(header_signature=txn_id)
@@ -118,6 +118,9 @@ class BlockTreeManager:
 
         identity_private_key = context.new_random_private_key()
         self.identity_signer = crypto_factory.new_signer(identity_private_key)
+        self.identity_key_path = os.path.join(self.dir, 'validator.priv')
+        with open(self.identity_key_path, 'w') as key_file:
+            key_file.write(identity_private_key.as_hex())
         chain_head = None
         if with_genesis:
             self.genesis_block = self.generate_genesis_block()
@@ -135,6 +138,7 @@ class BlockTreeManager:
             block_sender=self.block_sender,
             batch_sender=self.block_sender,
             identity_signer=self.identity_signer,
+            identity_key_path=self.identity_key_path,
             data_dir=None,
             config_dir=None,
             permission_verifier=MockPermissionVerifier(),
@@ -129,6 +129,7 @@ class TestBlockPublisher(unittest.TestCase):
             block_sender=self.block_sender,
             batch_sender=self.batch_sender,
             identity_signer=self.block_tree_manager.identity_signer,
+            identity_key_path=self.block_tree_manager.identity_key_path,
             data_dir=None,
             config_dir=None,
             batch_observers=[],
@@ -298,6 +299,7 @@ class TestBlockPublisher(unittest.TestCase):
             block_sender=self.block_sender,
             batch_sender=self.batch_sender,
             identity_signer=self.block_tree_manager.identity_signer,
+            identity_key_path=self.block_tree_manager.identity_key_path,
             data_dir=None,
             config_dir=None,
             batch_observers=[],
@@ -335,6 +337,7 @@ class TestBlockPublisher(unittest.TestCase):
             block_sender=self.block_sender,
             batch_sender=self.batch_sender,
             identity_signer=self.block_tree_manager.identity_signer,
+            identity_key_path=self.block_tree_manager.identity_key_path,
             data_dir=None,
             config_dir=None,
             batch_observers=[],
@@ -388,6 +391,7 @@ class TestBlockPublisher(unittest.TestCase):
             block_sender=self.block_sender,
             batch_sender=self.batch_sender,
             identity_signer=self.block_tree_manager.identity_signer,
+            identity_key_path=self.block_tree_manager.identity_key_path,
             data_dir=None,
             config_dir=None,
             permission_verifier=self.permission_verifier,
@@ -432,6 +436,7 @@ class TestBlockPublisher(unittest.TestCase):
             block_sender=self.block_sender,
             batch_sender=self.batch_sender,
             identity_signer=self.block_tree_manager.identity_signer,
+            identity_key_path=self.block_tree_manager.identity_key_path,
             data_dir=None,
             config_dir=None,
             batch_observers=[],